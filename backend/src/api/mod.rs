@@ -1,2 +1,7 @@
+pub mod routes_broadcast;
+pub mod routes_chains;
 pub mod routes_collect;
 pub mod routes_distribute;
+pub mod routes_events;
+pub mod routes_jobs;
+pub mod routes_safe;
@@ -0,0 +1,38 @@
+use crate::api::routes_jobs::job_origin;
+use crate::api::routes_jobs::JobAccepted;
+use crate::application::action_service::ActionService;
+use crate::shared::app_error::AppError;
+use axum::extract::{ConnectInfo, State};
+use axum::http::HeaderMap;
+use axum::response::{IntoResponse, Response};
+use axum::routing::post;
+use axum::{Json, Router};
+use serde::{Deserialize, Serialize};
+use std::net::SocketAddr;
+
+pub fn routes(dc: ActionService) -> Router {
+    Router::new()
+        .route("/broadcast", post(broadcast_signed_transactions))
+        .with_state(dc)
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct BroadcastPayload {
+    pub chain_id: u64,
+    /// Hex encoded signed transactions, e.g. the output of a `/build` route signed offline.
+    /// They are sent in this order, each one once the previous one is confirmed.
+    pub raw_transactions: Vec<String>,
+}
+
+async fn broadcast_signed_transactions(
+    State(dc): State<ActionService>,
+    ConnectInfo(addr): ConnectInfo<SocketAddr>,
+    headers: HeaderMap,
+    Json(payload): Json<BroadcastPayload>,
+) -> Result<Response, AppError> {
+    println!("->> broadcast_signed_transactions. Params: {:?}", payload);
+
+    let job = dc.enqueue_broadcast(payload, job_origin(&headers, addr))?;
+
+    Ok(JobAccepted::response(job).into_response())
+}
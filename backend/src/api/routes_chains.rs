@@ -0,0 +1,64 @@
+use crate::application::chain_registry::{ChainInfo, ChainRegistry};
+use crate::shared::provider_health::HealthSnapshot;
+use axum::extract::State;
+use axum::http::StatusCode;
+use axum::routing::get;
+use axum::{Json, Router};
+use serde::Serialize;
+
+pub fn routes(chains: ChainRegistry) -> Router {
+    Router::new()
+        .route("/health", get(get_health))
+        .route("/chains", get(get_chains))
+        .route("/accounts", get(get_accounts))
+        .with_state(chains)
+}
+
+#[derive(Debug, Serialize)]
+pub struct ChainHealth {
+    pub chain_id: u64,
+    #[serde(flatten)]
+    pub health: HealthSnapshot,
+}
+
+#[derive(Debug, Serialize)]
+pub struct HealthResponse {
+    pub healthy: bool,
+    pub chains: Vec<ChainHealth>,
+}
+
+/// 503 while any RPC connection is down or being re-established, so load balancers can react
+async fn get_health(State(chains): State<ChainRegistry>) -> (StatusCode, Json<HealthResponse>) {
+    let chains: Vec<ChainHealth> = chains
+        .chains()
+        .map(|chain| ChainHealth {
+            chain_id: chain.info.chain_id,
+            health: chain.health.snapshot(),
+        })
+        .collect();
+
+    let healthy = chains.iter().all(|chain| chain.health.is_healthy());
+
+    let status = match healthy {
+        true => StatusCode::OK,
+        false => StatusCode::SERVICE_UNAVAILABLE,
+    };
+
+    (status, Json(HealthResponse { healthy, chains }))
+}
+
+/// Chains accepted in the `chain_id` of action payloads
+async fn get_chains(State(chains): State<ChainRegistry>) -> Json<Vec<ChainInfo>> {
+    Json(chains.chains().map(|chain| chain.info.clone()).collect())
+}
+
+/// Accounts accepted in the `from` of action payloads, the default one first
+async fn get_accounts(State(chains): State<ChainRegistry>) -> Json<Vec<String>> {
+    Json(
+        chains
+            .accounts()
+            .iter()
+            .map(|account| account.to_string())
+            .collect(),
+    )
+}
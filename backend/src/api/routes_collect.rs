@@ -1,40 +1,95 @@
+use crate::api::routes_jobs::job_origin;
+use crate::api::routes_jobs::JobAccepted;
 use crate::application::action_service::ActionService;
-use crate::AppResponse;
-use axum::extract::State;
+use crate::shared::app_error::AppError;
+use crate::shared::units::FormattedAmount;
+use crate::BuildResponse;
+use axum::extract::{ConnectInfo, State};
+use axum::http::HeaderMap;
+use axum::response::{IntoResponse, Response};
 use axum::routing::post;
 use axum::{Json, Router};
-use serde::Deserialize;
+use serde::{Deserialize, Serialize};
+use std::net::SocketAddr;
 
 pub fn routes(dc: ActionService) -> Router {
     Router::new()
         .route("/collect/erc20", post(collect_erc20_tokens))
+        .route("/collect/erc20/build", post(build_collect_erc20_tokens))
+        .route("/collect/erc20/preview", post(preview_collect_erc20_tokens))
         .with_state(dc)
 }
 
-#[derive(Debug, Deserialize)]
+#[derive(Debug, Serialize, Deserialize)]
 pub struct FromWalletWithPercent {
     pub from: String,
     pub scaled_percent: String,
 }
 
-#[derive(Debug, Deserialize)]
+#[derive(Debug, Serialize, Deserialize)]
 pub struct CollectErc20Payload {
+    pub chain_id: u64,
     pub sets: Vec<FromWalletWithPercent>,
     pub token_address: String,
+    /// Account sending the collection and receiving the tokens, the default signer when omitted
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub from: Option<String>,
+    /// Only simulate the transactions and estimate their fees, nothing is broadcast
+    #[serde(default)]
+    pub dry_run: bool,
 }
 
 async fn collect_erc20_tokens(
     State(dc): State<ActionService>,
+    ConnectInfo(addr): ConnectInfo<SocketAddr>,
+    headers: HeaderMap,
     Json(payload): Json<CollectErc20Payload>,
-) -> Json<AppResponse> {
+) -> Result<Response, AppError> {
     println!("->> collect_erc20_tokens. Params: {:?}", payload);
 
-    match dc.collect_erc20_tokens(payload).await {
-        Ok(res) => Json(res),
-        Err(e) => Json(AppResponse {
-            tx_hash_approve: None,
-            tx_hash_distribute: None,
-            error: Some(format!("Error: {}", e)),
-        }),
+    if payload.dry_run {
+        return Ok(Json(dc.dry_run_collect_erc20_tokens(payload).await?).into_response());
     }
+
+    let job = dc.enqueue_collect_erc20_tokens(payload, job_origin(&headers, addr))?;
+
+    Ok(JobAccepted::response(job).into_response())
+}
+
+/// Same collection as unsigned transactions for the `from` account, which may be any address
+async fn build_collect_erc20_tokens(
+    State(dc): State<ActionService>,
+    Json(payload): Json<CollectErc20Payload>,
+) -> Result<Json<BuildResponse>, AppError> {
+    println!("->> build_collect_erc20_tokens. Params: {:?}", payload);
+
+    Ok(Json(dc.build_collect_erc20_tokens(payload).await?))
+}
+
+#[derive(Debug, Serialize)]
+pub struct WalletCollectPreview {
+    pub from: String,
+    pub scaled_percent: String,
+    pub balance: FormattedAmount,
+    pub collect_amount: FormattedAmount,
+    pub allowance: FormattedAmount,
+    pub shortfall: FormattedAmount,
+}
+
+#[derive(Debug, Serialize)]
+pub struct CollectionPreview {
+    pub token_address: String,
+    pub wallets: Vec<WalletCollectPreview>,
+    pub total_collect_amount: FormattedAmount,
+    /// Wallets that have to approve TokenManager before collection can succeed
+    pub under_approved_wallets: Vec<String>,
+}
+
+async fn preview_collect_erc20_tokens(
+    State(dc): State<ActionService>,
+    Json(payload): Json<CollectErc20Payload>,
+) -> Result<Json<CollectionPreview>, AppError> {
+    println!("->> preview_collect_erc20_tokens. Params: {:?}", payload);
+
+    Ok(Json(dc.preview_collect_erc20_tokens(payload).await?))
 }
@@ -1,50 +1,93 @@
+use crate::api::routes_jobs::job_origin;
+use crate::api::routes_jobs::JobAccepted;
 use crate::application::action_service::ActionService;
-use crate::AppResponse;
-use axum::extract::State;
+use crate::shared::app_error::AppError;
+use crate::shared::units::FormattedAmount;
+use crate::BuildResponse;
+use axum::extract::{ConnectInfo, State};
+use axum::http::HeaderMap;
+use axum::response::{IntoResponse, Response};
 use axum::routing::post;
 use axum::{Json, Router};
-use serde::Deserialize;
+use serde::{Deserialize, Serialize};
+use std::net::SocketAddr;
 
 pub fn routes(dc: ActionService) -> Router {
     Router::new()
         .route("/distribute/native", post(distribute_native_tokens))
+        .route(
+            "/distribute/native/build",
+            post(build_distribute_native_tokens),
+        )
         .route("/distribute/erc20", post(distribute_erc20_tokens))
+        .route(
+            "/distribute/erc20/build",
+            post(build_distribute_erc20_tokens),
+        )
+        .route("/distribute/preview", post(preview_distribution))
         .with_state(dc)
 }
 
-#[derive(Debug, Deserialize)]
+#[derive(Debug, Serialize, Deserialize)]
 pub struct ReceiversWithProportions {
     pub receiver: String,
     pub proportion: String,
 }
 
-#[derive(Debug, Deserialize)]
+#[derive(Debug, Default, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum AmountUnit {
+    /// Raw base units, e.g. wei for native tokens. Echoed amounts are still formatted
+    /// with the token's decimals.
+    #[default]
+    Base,
+    /// Token units like "1250.5", scaled by token decimals()
+    Decimal,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
 pub struct DistributeBasePayload {
+    pub chain_id: u64,
     pub receivers_with_proportions: Vec<ReceiversWithProportions>,
     pub amount: String,
+    #[serde(default)]
+    pub unit: AmountUnit,
+    /// Account paying the distribution, the default signer when omitted
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub from: Option<String>,
+    /// Only simulate the transactions and estimate their fees, nothing is broadcast
+    #[serde(default)]
+    pub dry_run: bool,
 }
 
 async fn distribute_native_tokens(
     State(dc): State<ActionService>,
+    ConnectInfo(addr): ConnectInfo<SocketAddr>,
+    headers: HeaderMap,
     Json(payload): Json<DistributeBasePayload>,
-) -> Json<AppResponse> {
-    println!("->> distribute_erc20_tokens. Params: {:?}", payload);
-    /*    Json(AppResponse {
-        tx_hash_approve: None,
-        tx_hash_distribute: None,
-        error: None,
-    })*/
-    match dc.distribute_native_tokens(payload).await {
-        Ok(res) => Json(res),
-        Err(e) => Json(AppResponse {
-            tx_hash_approve: None,
-            tx_hash_distribute: None,
-            error: Some(format!("Error: {}", e)),
-        }),
+) -> Result<Response, AppError> {
+    println!("->> distribute_native_tokens. Params: {:?}", payload);
+
+    if payload.dry_run {
+        return Ok(Json(dc.dry_run_distribute_native_tokens(payload).await?).into_response());
     }
+
+    let job = dc.enqueue_distribute_native_tokens(payload, job_origin(&headers, addr))?;
+
+    Ok(JobAccepted::response(job).into_response())
 }
 
-#[derive(Debug, Deserialize)]
+/// Same distribution as unsigned transactions for the `from` account, which may be any address
+async fn build_distribute_native_tokens(
+    State(dc): State<ActionService>,
+    Json(payload): Json<DistributeBasePayload>,
+) -> Result<Json<BuildResponse>, AppError> {
+    println!("->> build_distribute_native_tokens. Params: {:?}", payload);
+
+    Ok(Json(dc.build_distribute_native_tokens(payload).await?))
+}
+
+#[derive(Debug, Serialize, Deserialize)]
 pub struct DistributeErc20Payload {
     pub base: DistributeBasePayload,
     pub token_address: String,
@@ -52,20 +95,62 @@ pub struct DistributeErc20Payload {
 
 async fn distribute_erc20_tokens(
     State(dc): State<ActionService>,
+    ConnectInfo(addr): ConnectInfo<SocketAddr>,
+    headers: HeaderMap,
     Json(payload): Json<DistributeErc20Payload>,
-) -> Json<AppResponse> {
+) -> Result<Response, AppError> {
     println!("->> distribute_erc20_tokens. Params: {:?}", payload);
-    /* Json(AppResponse {
-        tx_hash_approve: None,
-        tx_hash_distribute: None,
-        error: None,
-    })*/
-    match dc.distribute_erc20_tokens(payload).await {
-        Ok(res) => Json(res),
-        Err(e) => Json(AppResponse {
-            tx_hash_approve: None,
-            tx_hash_distribute: None,
-            error: Some(format!("Error: {}", e)),
-        }),
+
+    if payload.base.dry_run {
+        return Ok(Json(dc.dry_run_distribute_erc20_tokens(payload).await?).into_response());
     }
+
+    let job = dc.enqueue_distribute_erc20_tokens(payload, job_origin(&headers, addr))?;
+
+    Ok(JobAccepted::response(job).into_response())
+}
+
+/// Same distribution as unsigned transactions for the `from` account, which may be any address
+async fn build_distribute_erc20_tokens(
+    State(dc): State<ActionService>,
+    Json(payload): Json<DistributeErc20Payload>,
+) -> Result<Json<BuildResponse>, AppError> {
+    println!("->> build_distribute_erc20_tokens. Params: {:?}", payload);
+
+    Ok(Json(dc.build_distribute_erc20_tokens(payload).await?))
+}
+
+#[derive(Debug, Deserialize)]
+pub struct DistributePreviewPayload {
+    pub base: DistributeBasePayload,
+    /// Omitted for native distribution
+    pub token_address: Option<String>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct ReceiverAmount {
+    pub receiver: String,
+    pub proportion: String,
+    pub amount: FormattedAmount,
+}
+
+#[derive(Debug, Serialize)]
+pub struct DistributionPreview {
+    pub token_address: Option<String>,
+    pub total_amount: FormattedAmount,
+    pub total_parts: String,
+    pub receivers: Vec<ReceiverAmount>,
+    /// Sum of all receiver amounts
+    pub distributed_amount: FormattedAmount,
+    /// Rounding remainder that stays with the sender
+    pub dust: FormattedAmount,
+}
+
+async fn preview_distribution(
+    State(dc): State<ActionService>,
+    Json(payload): Json<DistributePreviewPayload>,
+) -> Result<Json<DistributionPreview>, AppError> {
+    println!("->> preview_distribution. Params: {:?}", payload);
+
+    Ok(Json(dc.preview_distribution(payload).await?))
 }
@@ -0,0 +1,21 @@
+use crate::application::event_indexer::{EventFilter, EventIndexer, IndexedEvent};
+use crate::shared::app_error::AppError;
+use axum::extract::{Query, State};
+use axum::routing::get;
+use axum::{Json, Router};
+
+pub fn routes(events: EventIndexer) -> Router {
+    Router::new()
+        .route("/events", get(get_events))
+        .with_state(events)
+}
+
+/// Indexed `Distributed` and `Collected` events, newest first
+async fn get_events(
+    State(events): State<EventIndexer>,
+    Query(filter): Query<EventFilter>,
+) -> Result<Json<Vec<IndexedEvent>>, AppError> {
+    println!("->> get_events. Filter: {:?}", filter);
+
+    Ok(Json(events.list(&filter)?))
+}
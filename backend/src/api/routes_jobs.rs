@@ -0,0 +1,126 @@
+use crate::application::action_service::ActionService;
+use crate::application::job_service::{
+    EnqueuedJob, HistoryFilter, Job, JobOrigin, JobService, TxKind,
+};
+use crate::shared::app_error::AppError;
+use crate::shared::submitter::Replacement;
+use axum::extract::{Path, Query, State};
+use axum::http::{HeaderMap, StatusCode};
+use axum::routing::{get, post};
+use axum::{Json, Router};
+use serde::Serialize;
+use std::net::SocketAddr;
+use uuid::Uuid;
+
+pub fn routes(jobs: JobService, dc: ActionService) -> Router {
+    let replacement_routes = Router::new()
+        .route("/jobs/:id/speed-up", post(speed_up_job))
+        .route("/jobs/:id/cancel", post(cancel_job))
+        .with_state(dc);
+
+    Router::new()
+        .route("/jobs/:id", get(get_job))
+        .route("/history", get(get_history))
+        .with_state(jobs)
+        .merge(replacement_routes)
+}
+
+/// Requester is taken from the `X-Requested-By` header, or the client address.
+/// `Idempotency-Key` header protects action routes from running the same request twice.
+pub fn job_origin(headers: &HeaderMap, addr: SocketAddr) -> JobOrigin {
+    let header = |name: &str| {
+        headers
+            .get(name)
+            .and_then(|value| value.to_str().ok())
+            .map(|value| value.trim().to_string())
+            .filter(|value| !value.is_empty())
+    };
+
+    JobOrigin {
+        requested_by: header("x-requested-by").or_else(|| Some(addr.ip().to_string())),
+        idempotency_key: header("idempotency-key"),
+    }
+}
+
+#[derive(Debug, Serialize)]
+pub struct JobAccepted {
+    pub job_id: Uuid,
+    pub status_url: String,
+    pub replayed: bool,
+}
+
+impl JobAccepted {
+    /// 202 for a new job, 200 when an idempotency key returned the original one
+    pub fn response(job: EnqueuedJob) -> (StatusCode, Json<JobAccepted>) {
+        let status_code = match job.replayed {
+            true => StatusCode::OK,
+            false => StatusCode::ACCEPTED,
+        };
+
+        (
+            status_code,
+            Json(JobAccepted {
+                job_id: job.job_id,
+                status_url: format!("/jobs/{}", job.job_id),
+                replayed: job.replayed,
+            }),
+        )
+    }
+}
+
+/// New version of the job's pending transaction, sent with the same nonce
+#[derive(Debug, Serialize)]
+pub struct TxReplaced {
+    pub job_id: Uuid,
+    pub kind: TxKind,
+    pub replacement: Replacement,
+    pub replaced_tx_hash: String,
+    pub tx_hash: String,
+}
+
+async fn get_job(
+    State(jobs): State<JobService>,
+    Path(job_id): Path<Uuid>,
+) -> Result<Json<Job>, AppError> {
+    println!("->> get_job. Id: {}", job_id);
+
+    jobs.get(job_id)?
+        .map(Json)
+        .ok_or_else(|| AppError::NotFound(format!("Job {} not found", job_id)))
+}
+
+async fn get_history(
+    State(jobs): State<JobService>,
+    Query(filter): Query<HistoryFilter>,
+) -> Result<Json<Vec<Job>>, AppError> {
+    println!("->> get_history. Filter: {:?}", filter);
+
+    Ok(Json(jobs.history(&filter)?))
+}
+
+/// Resends the pending transaction of a running job with higher fees
+async fn speed_up_job(
+    State(dc): State<ActionService>,
+    Path(job_id): Path<Uuid>,
+) -> Result<Json<TxReplaced>, AppError> {
+    println!("->> speed_up_job. Id: {}", job_id);
+
+    Ok(Json(
+        dc.replace_job_transaction(job_id, Replacement::SpeedUp)
+            .await?,
+    ))
+}
+
+/// Replaces the pending transaction of a running job with a zero-value transfer
+/// to its sender, the job fails once the cancellation is mined
+async fn cancel_job(
+    State(dc): State<ActionService>,
+    Path(job_id): Path<Uuid>,
+) -> Result<Json<TxReplaced>, AppError> {
+    println!("->> cancel_job. Id: {}", job_id);
+
+    Ok(Json(
+        dc.replace_job_transaction(job_id, Replacement::Cancel)
+            .await?,
+    ))
+}
@@ -0,0 +1,167 @@
+use crate::api::routes_collect::CollectErc20Payload;
+use crate::api::routes_distribute::{DistributeBasePayload, DistributeErc20Payload};
+use crate::api::routes_jobs::job_origin;
+use crate::api::routes_jobs::JobAccepted;
+use crate::application::action_service::ActionService;
+use crate::shared::app_error::AppError;
+use axum::extract::{ConnectInfo, Path, Query, State};
+use axum::http::HeaderMap;
+use axum::response::{IntoResponse, Response};
+use axum::routing::post;
+use axum::{Json, Router};
+use serde::{Deserialize, Serialize};
+use std::net::SocketAddr;
+use uuid::Uuid;
+
+pub fn routes(dc: ActionService) -> Router {
+    Router::new()
+        .route(
+            "/distribute/native/safe",
+            post(export_safe_distribute_native_tokens),
+        )
+        .route(
+            "/distribute/erc20/safe",
+            post(export_safe_distribute_erc20_tokens),
+        )
+        .route(
+            "/collect/erc20/safe",
+            post(export_safe_collect_erc20_tokens),
+        )
+        .route("/jobs/:id/safe-execution", post(record_safe_execution))
+        .with_state(dc)
+}
+
+#[derive(Debug, Default, Deserialize)]
+pub struct SafeExportQuery {
+    /// Receivers (or wallets) per batch, everything goes into a single batch when omitted
+    pub chunk_size: Option<usize>,
+}
+
+/// File the Safe Transaction Builder imports, executed as one Safe transaction
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SafeBatchFile {
+    pub version: String,
+    pub chain_id: String,
+    /// Milliseconds since the Unix epoch
+    pub created_at: u64,
+    pub meta: SafeBatchMeta,
+    pub transactions: Vec<SafeBatchTransaction>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SafeBatchMeta {
+    pub name: String,
+    pub description: String,
+    pub created_from_safe_address: String,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SafeBatchTransaction {
+    pub to: String,
+    pub value: String,
+    pub data: String,
+    /// Always null, the Transaction Builder sends `data` as it is
+    pub contract_method: Option<serde_json::Value>,
+    pub contract_inputs_values: Option<serde_json::Value>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct SafeExport {
+    pub job_id: Uuid,
+    pub status_url: String,
+    pub replayed: bool,
+    /// One file per Safe transaction, to be executed in this order
+    pub batches: Vec<SafeBatchFile>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct SafeExecutionPayload {
+    /// Hash of the Safe transaction that executed the batch
+    pub tx_hash: String,
+    /// Index of the executed batch, 0 for exports of a single batch
+    #[serde(default)]
+    pub batch: usize,
+}
+
+/// The payload's `from` is the Safe, which pays the distribution
+async fn export_safe_distribute_native_tokens(
+    State(dc): State<ActionService>,
+    ConnectInfo(addr): ConnectInfo<SocketAddr>,
+    headers: HeaderMap,
+    Query(query): Query<SafeExportQuery>,
+    Json(payload): Json<DistributeBasePayload>,
+) -> Result<Json<SafeExport>, AppError> {
+    println!(
+        "->> export_safe_distribute_native_tokens. Params: {:?}",
+        payload
+    );
+
+    Ok(Json(
+        dc.export_safe_distribute_native_tokens(
+            payload,
+            query.chunk_size,
+            job_origin(&headers, addr),
+        )
+        .await?,
+    ))
+}
+
+/// The payload's `from` is the Safe, which pays the distribution
+async fn export_safe_distribute_erc20_tokens(
+    State(dc): State<ActionService>,
+    ConnectInfo(addr): ConnectInfo<SocketAddr>,
+    headers: HeaderMap,
+    Query(query): Query<SafeExportQuery>,
+    Json(payload): Json<DistributeErc20Payload>,
+) -> Result<Json<SafeExport>, AppError> {
+    println!(
+        "->> export_safe_distribute_erc20_tokens. Params: {:?}",
+        payload
+    );
+
+    Ok(Json(
+        dc.export_safe_distribute_erc20_tokens(
+            payload,
+            query.chunk_size,
+            job_origin(&headers, addr),
+        )
+        .await?,
+    ))
+}
+
+/// The payload's `from` is the Safe, which receives the collected tokens
+async fn export_safe_collect_erc20_tokens(
+    State(dc): State<ActionService>,
+    ConnectInfo(addr): ConnectInfo<SocketAddr>,
+    headers: HeaderMap,
+    Query(query): Query<SafeExportQuery>,
+    Json(payload): Json<CollectErc20Payload>,
+) -> Result<Json<SafeExport>, AppError> {
+    println!(
+        "->> export_safe_collect_erc20_tokens. Params: {:?}",
+        payload
+    );
+
+    Ok(Json(
+        dc.export_safe_collect_erc20_tokens(payload, query.chunk_size, job_origin(&headers, addr))
+            .await?,
+    ))
+}
+
+async fn record_safe_execution(
+    State(dc): State<ActionService>,
+    Path(job_id): Path<Uuid>,
+    Json(payload): Json<SafeExecutionPayload>,
+) -> Result<Response, AppError> {
+    println!(
+        "->> record_safe_execution. Job: {}, Params: {:?}",
+        job_id, payload
+    );
+
+    let job = dc.record_safe_execution(job_id, payload).await?;
+
+    Ok(JobAccepted::response(job).into_response())
+}
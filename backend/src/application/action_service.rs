@@ -1,139 +1,2086 @@
-use crate::api::routes_collect::CollectErc20Payload;
-use crate::api::routes_distribute::{DistributeBasePayload, DistributeErc20Payload};
-use crate::application::erc20_service::{Erc20Service, WalletAndAmount};
-use crate::application::token_manager_service::TokenManagerService;
-use crate::AppResponse;
-use alloy::primitives::{Address, U256};
-use anyhow::Result;
+use crate::api::routes_broadcast::BroadcastPayload;
+use crate::api::routes_collect::{CollectErc20Payload, CollectionPreview, WalletCollectPreview};
+use crate::api::routes_distribute::{
+    DistributeBasePayload, DistributeErc20Payload, DistributePreviewPayload, DistributionPreview,
+    ReceiverAmount,
+};
+use crate::api::routes_jobs::TxReplaced;
+use crate::api::routes_safe::{
+    SafeBatchFile, SafeBatchMeta, SafeBatchTransaction, SafeExecutionPayload, SafeExport,
+};
+use crate::application::chain_registry::{Chain, ChainRegistry};
+use crate::application::erc20_service::{WalletAllowance, WalletAndAmount};
+use crate::application::job_service::{
+    EnqueuedJob, Job, JobCreation, JobKind, JobOrigin, JobService, JobStatus, JobTracker,
+    JobTransaction, TxChunk, TxKind, TxStatus,
+};
+use crate::application::token_manager_service::{
+    erc20_transfers, split_collection, split_distribution, DistributionChunk, SafeCall,
+};
+use crate::application::validation::{self, ValidatedDistribution};
+use crate::shared::app_error::AppError;
+use crate::shared::contracts::{TokenManager, ERC20};
+use crate::shared::execute_call::TxListener;
+use crate::shared::nonce_manager::SubmissionLock;
+use crate::shared::submitter::Replacement;
+use crate::shared::token_manager_math;
+use crate::shared::units::{FormattedAmount, NATIVE_DECIMALS};
+use crate::{
+    AddressTransfer, AppResponse, BuildResponse, DryRunResponse, SimulatedTransaction,
+    TransferReport, UnsignedTransaction,
+};
+use alloy::consensus::{Transaction, TxEnvelope};
+use alloy::eips::eip2718::Decodable2718;
+use alloy::network::TransactionBuilder;
+use alloy::primitives::hex::{self, FromHexError};
+use alloy::primitives::{Address, TxHash, U256};
+use alloy::rpc::types::{TransactionReceipt, TransactionRequest};
+use alloy::sol_types::SolCall;
+use alloy::transports::{BoxTransport, Transport};
+use serde::Serialize;
+use std::collections::{BTreeSet, HashMap};
+use std::future::Future;
 use std::str::FromStr;
+use std::time::{SystemTime, UNIX_EPOCH};
+use uuid::Uuid;
 
 #[derive(Clone)]
-pub struct ActionService {
-    pub erc20_service: Erc20Service,
-    pub token_manager_service: TokenManagerService,
+pub struct ActionService<T: Transport + Clone = BoxTransport> {
+    pub chains: ChainRegistry<T>,
+    pub job_service: JobService,
 }
 
-impl ActionService {
-    pub fn new(erc20_service: Erc20Service, token_manager_service: TokenManagerService) -> Self {
+impl<T> ActionService<T>
+where
+    T: Transport + Clone,
+{
+    pub fn new(chains: ChainRegistry<T>, job_service: JobService) -> Self {
         Self {
-            erc20_service,
-            token_manager_service,
+            chains,
+            job_service,
         }
     }
 }
 
-impl ActionService {
-    pub async fn distribute_native_tokens(
+impl<T> ActionService<T>
+where
+    T: Transport + Clone,
+{
+    pub fn enqueue_distribute_native_tokens(
         &self,
         payload: DistributeBasePayload,
-    ) -> Result<AppResponse> {
-        let (receivers, proportions, amount) = self.transform_args_to_alloy(payload);
+        origin: JobOrigin,
+    ) -> Result<EnqueuedJob, AppError> {
+        self.sending_chain(payload.chain_id, payload.from.as_deref())?;
 
-        let tx_hash = self
-            .token_manager_service
-            .distribute_native_tokens(receivers, proportions, amount)
+        let payload_json = to_payload_json(&payload)?;
+
+        self.spawn_job(
+            JobKind::DistributeNative,
+            payload.chain_id,
+            origin,
+            payload_json,
+            |service, tracker| async move { service.distribute_native_tokens(payload, &tracker).await },
+        )
+    }
+
+    pub fn enqueue_distribute_erc20_tokens(
+        &self,
+        payload: DistributeErc20Payload,
+        origin: JobOrigin,
+    ) -> Result<EnqueuedJob, AppError> {
+        self.sending_chain(payload.base.chain_id, payload.base.from.as_deref())?;
+
+        let payload_json = to_payload_json(&payload)?;
+
+        self.spawn_job(
+            JobKind::DistributeErc20,
+            payload.base.chain_id,
+            origin,
+            payload_json,
+            |service, tracker| async move { service.distribute_erc20_tokens(payload, &tracker).await },
+        )
+    }
+
+    pub fn enqueue_collect_erc20_tokens(
+        &self,
+        payload: CollectErc20Payload,
+        origin: JobOrigin,
+    ) -> Result<EnqueuedJob, AppError> {
+        self.sending_chain(payload.chain_id, payload.from.as_deref())?;
+
+        let payload_json = to_payload_json(&payload)?;
+
+        self.spawn_job(
+            JobKind::CollectErc20,
+            payload.chain_id,
+            origin,
+            payload_json,
+            |service, tracker| async move { service.collect_erc20_tokens(payload, &tracker).await },
+        )
+    }
+
+    /// Checks every signed transaction before the job is stored, so a batch is never sent halfway
+    /// because of a transaction that could have been refused upfront
+    pub fn enqueue_broadcast(
+        &self,
+        payload: BroadcastPayload,
+        origin: JobOrigin,
+    ) -> Result<EnqueuedJob, AppError> {
+        let chain = self.chains.get(payload.chain_id)?;
+
+        if payload.raw_transactions.is_empty() {
+            return Err(AppError::validation("No transaction to broadcast"));
+        }
+
+        let transactions = payload
+            .raw_transactions
+            .iter()
+            .map(|raw| decode_signed(chain, raw))
+            .collect::<Result<Vec<_>, AppError>>()?;
+
+        let payload_json = to_payload_json(&payload)?;
+
+        self.spawn_job(
+            JobKind::Broadcast,
+            payload.chain_id,
+            origin,
+            payload_json,
+            |service, tracker| async move {
+                service
+                    .broadcast(payload.chain_id, transactions, &tracker)
+                    .await
+            },
+        )
+    }
+
+    /// Services of the chain sending from the payload's `from` account, the default one without it.
+    /// Unknown chains and accounts are refused before a job is stored.
+    fn sending_chain(&self, chain_id: u64, from: Option<&str>) -> Result<Chain<T>, AppError> {
+        let chain = self.chains.get(chain_id)?;
+
+        let from = match from {
+            Some(from) => Some(from.parse::<Address>()?),
+            None => None,
+        };
+
+        Ok(chain.with_sender(self.chains.sender(from)?))
+    }
+
+    /// Services of the chain building transactions for the payload's `from` account, which
+    /// does not have to be held by the signer, the default account without it
+    fn building_chain(&self, chain_id: u64, from: Option<&str>) -> Result<Chain<T>, AppError> {
+        let chain = self.chains.get(chain_id)?;
+
+        let sender = match from {
+            Some(from) => from.parse::<Address>()?,
+            None => self.chains.sender(None)?,
+        };
+
+        Ok(chain.with_sender(sender))
+    }
+
+    /// Registers a job and runs it in the background, the caller only gets its id.
+    /// A replayed idempotency key returns the original job without running anything.
+    fn spawn_job<F, Fut>(
+        &self,
+        kind: JobKind,
+        chain_id: u64,
+        origin: JobOrigin,
+        payload: serde_json::Value,
+        run: F,
+    ) -> Result<EnqueuedJob, AppError>
+    where
+        F: FnOnce(ActionService<T>, JobTracker) -> Fut,
+        Fut: Future<Output = Result<AppResponse, AppError>> + Send + 'static,
+    {
+        let tracker = match self.job_service.create(kind, chain_id, origin, payload)? {
+            JobCreation::Created(tracker) => tracker,
+            JobCreation::Replayed(job_id) => {
+                return Ok(EnqueuedJob {
+                    job_id,
+                    replayed: true,
+                })
+            }
+        };
+        let job_id = tracker.job_id();
+
+        let job = run(self.clone(), tracker.clone());
+
+        tokio::spawn(async move {
+            tracker.finish(job.await);
+        });
+
+        Ok(EnqueuedJob {
+            job_id,
+            replayed: false,
+        })
+    }
+
+    async fn distribute_native_tokens(
+        &self,
+        payload: DistributeBasePayload,
+        tracker: &JobTracker,
+    ) -> Result<AppResponse, AppError> {
+        tracker.set_status(JobStatus::Validating);
+
+        let chain = &self.sending_chain(payload.chain_id, payload.from.as_deref())?;
+
+        let (receivers, proportions, amount, _) =
+            self.transform_args_to_alloy(chain, payload, None).await?;
+
+        record_distribution(
+            tracker,
+            None,
+            amount,
+            NATIVE_DECIMALS,
+            &receivers,
+            &proportions,
+        )?;
+
+        let _submissions = lock_submissions(chain, chain.token_manager_service.sender()).await;
+
+        let tx_hashes = self
+            .send_distribution(chain, None, receivers, proportions, amount, tracker)
             .await?;
 
         Ok(AppResponse {
-            tx_hash_distribute: Some(tx_hash.to_string()),
+            tx_hash_distribute: single_tx_hash(&tx_hashes),
+            tx_hashes_distribute: tx_hashes,
             tx_hash_approve: None,
-            error: None,
+            amount: Some(FormattedAmount::new(amount, NATIVE_DECIMALS)),
+            transfers: self
+                .transfer_report(
+                    chain,
+                    tracker.job_id(),
+                    chain.token_manager_service.sender(),
+                )
+                .await,
         })
     }
 
-    pub async fn distribute_erc20_tokens(
+    async fn distribute_erc20_tokens(
         &self,
         payload: DistributeErc20Payload,
-    ) -> Result<AppResponse> {
-        let token_address = payload.token_address.parse::<Address>()?.clone();
+        tracker: &JobTracker,
+    ) -> Result<AppResponse, AppError> {
+        tracker.set_status(JobStatus::Validating);
 
-        let (receivers, proportions, amount) = self.transform_args_to_alloy(payload.base);
+        let chain = &self.sending_chain(payload.base.chain_id, payload.base.from.as_deref())?;
 
-        let token_manager_address = self.token_manager_service.get_token_manager_address();
+        let token_address = payload.token_address.parse::<Address>()?;
+        let (receivers, proportions, amount, decimals) = self
+            .transform_args_to_alloy(chain, payload.base, Some(token_address))
+            .await?;
+
+        record_distribution(
+            tracker,
+            Some(token_address),
+            amount,
+            decimals,
+            &receivers,
+            &proportions,
+        )?;
+
+        let token_manager_address = chain.token_manager_service.get_token_manager_address();
+
+        // Another job of the account could change the allowance between approval and distribution
+        let _submissions = lock_submissions(chain, chain.token_manager_service.sender()).await;
 
-        let approve_hash = self
+        tracker.set_status(JobStatus::Approving);
+
+        let listener = tracker.tx_listener(TxKind::Approve);
+        let result = chain
             .erc20_service
-            .check_signer_allowance_or_approve(token_address.clone(), token_manager_address, amount)
+            .check_signer_allowance_or_approve(
+                token_address,
+                token_manager_address,
+                amount,
+                &listener,
+            )
+            .await;
+        let approve_receipt = tracker.settle_tx(&listener, result)?;
+
+        let tx_hashes = self
+            .send_distribution(
+                chain,
+                Some(token_address),
+                receivers,
+                proportions,
+                amount,
+                tracker,
+            )
             .await?;
 
-        let tx_hash = self
+        Ok(AppResponse {
+            tx_hash_distribute: single_tx_hash(&tx_hashes),
+            tx_hashes_distribute: tx_hashes,
+            tx_hash_approve: approve_receipt.map(|receipt| receipt.transaction_hash.to_string()),
+            amount: Some(FormattedAmount::new(amount, decimals)),
+            transfers: self
+                .transfer_report(
+                    chain,
+                    tracker.job_id(),
+                    chain.token_manager_service.sender(),
+                )
+                .await,
+        })
+    }
+
+    /// Sends a validated distribution, split into as many transactions as the gas limit requires.
+    /// Chunks are sent one by one and the first failed chunk stops the job.
+    async fn send_distribution(
+        &self,
+        chain: &Chain<T>,
+        token_address: Option<Address>,
+        receivers: Vec<Address>,
+        proportions: Vec<U256>,
+        amount: U256,
+        tracker: &JobTracker,
+    ) -> Result<Vec<String>, AppError> {
+        let chunks = chain
             .token_manager_service
-            .distribute_erc20_tokens(token_address.clone(), receivers, proportions, amount)
+            .plan_distribution(token_address, receivers, proportions, amount)
             .await?;
 
+        let count = chunks.len();
+        let mut offset = 0;
+        let mut tx_hashes: Vec<String> = vec![];
+
+        for (index, chunk) in chunks.into_iter().enumerate() {
+            let tx_chunk = TxChunk {
+                index,
+                count,
+                offset,
+                receivers: chunk.receivers.len(),
+            };
+            offset += chunk.receivers.len();
+
+            let listener = tracker.chunk_listener(TxKind::Distribute, tx_chunk);
+
+            let result = match token_address {
+                Some(token_address) => {
+                    chain
+                        .token_manager_service
+                        .distribute_erc20_tokens(
+                            token_address,
+                            chunk.receivers,
+                            chunk.proportions,
+                            chunk.total_amount,
+                            &listener,
+                        )
+                        .await
+                }
+                None => {
+                    chain
+                        .token_manager_service
+                        .distribute_native_tokens(
+                            chunk.receivers,
+                            chunk.proportions,
+                            chunk.total_amount,
+                            &listener,
+                        )
+                        .await
+                }
+            };
+            let receipt = tracker.settle_tx(&listener, result)?;
+
+            tx_hashes.push(receipt.transaction_hash.to_string());
+        }
+
+        Ok(tx_hashes)
+    }
+
+    async fn broadcast(
+        &self,
+        chain_id: u64,
+        transactions: Vec<(TxKind, TxEnvelope)>,
+        tracker: &JobTracker,
+    ) -> Result<AppResponse, AppError> {
+        let chain = self.chains.get(chain_id)?;
+
+        // Signers may be accounts of this backend, whose jobs must not take the nonces in
+        // between. Locked in address order, so two broadcasts never wait for each other.
+        let signers: BTreeSet<Address> = transactions
+            .iter()
+            .filter_map(|(_, envelope)| envelope.recover_signer().ok())
+            .collect();
+        let mut _submissions = vec![];
+        for signer in signers {
+            _submissions.push(lock_submissions(chain, signer).await);
+        }
+
+        let mut tx_hash_approve: Option<String> = None;
+        let mut tx_hashes: Vec<String> = vec![];
+
+        for (kind, envelope) in transactions {
+            if kind == TxKind::Approve {
+                tracker.set_status(JobStatus::Approving);
+            }
+
+            let listener = tracker.tx_listener(kind);
+            let result = chain
+                .token_manager_service
+                .submit_signed(&envelope, &listener)
+                .await;
+            let receipt = tracker.settle_tx(&listener, result)?;
+
+            match kind {
+                TxKind::Approve => tx_hash_approve = Some(receipt.transaction_hash.to_string()),
+                TxKind::Distribute | TxKind::Collect => {
+                    tx_hashes.push(receipt.transaction_hash.to_string())
+                }
+            }
+        }
+
         Ok(AppResponse {
-            tx_hash_distribute: Some(tx_hash.to_string()),
-            tx_hash_approve: Some(approve_hash.unwrap().to_string()),
-            error: None,
+            tx_hash_distribute: single_tx_hash(&tx_hashes),
+            tx_hashes_distribute: tx_hashes,
+            tx_hash_approve,
+            amount: None,
+            // Signed transactions come without the requested amounts to compare against
+            transfers: None,
+        })
+    }
+
+    /// Validates and simulates a native distribution the way its job would, without broadcasting
+    pub async fn dry_run_distribute_native_tokens(
+        &self,
+        payload: DistributeBasePayload,
+    ) -> Result<DryRunResponse, AppError> {
+        let chain = &self.sending_chain(payload.chain_id, payload.from.as_deref())?;
+
+        let (receivers, proportions, amount, _) =
+            self.transform_args_to_alloy(chain, payload, None).await?;
+
+        let transactions = self
+            .simulate_distribution(chain, None, receivers, proportions, amount)
+            .await?;
+
+        self.dry_run_response(
+            chain,
+            transactions,
+            Some(FormattedAmount::new(amount, NATIVE_DECIMALS)),
+        )
+        .await
+    }
+
+    /// Validates and simulates an ERC20 distribution the way its job would, without broadcasting
+    pub async fn dry_run_distribute_erc20_tokens(
+        &self,
+        payload: DistributeErc20Payload,
+    ) -> Result<DryRunResponse, AppError> {
+        let chain = &self.sending_chain(payload.base.chain_id, payload.base.from.as_deref())?;
+
+        let token_address = payload.token_address.parse::<Address>()?;
+        let (receivers, proportions, amount, decimals) = self
+            .transform_args_to_alloy(chain, payload.base, Some(token_address))
+            .await?;
+
+        let token_manager_address = chain.token_manager_service.get_token_manager_address();
+
+        let approve_gas = chain
+            .erc20_service
+            .simulate_signer_approve(token_address, token_manager_address, amount)
+            .await?;
+
+        let transactions = match approve_gas {
+            // Distribution reverts until the approval is mined, its chunks are planned and
+            // estimated by the gas bound of their receivers the way they would be built
+            Some(approve_gas) => {
+                let chunks = chain
+                    .token_manager_service
+                    .build_unsimulated_distribution(token_address, receivers, proportions, amount)
+                    .await?;
+
+                let mut transactions = vec![SimulatedTransaction {
+                    kind: TxKind::Approve,
+                    chunk: None,
+                    gas_estimate: Some(approve_gas.to_string()),
+                }];
+                transactions.extend(distribution_transactions(chunks).into_iter().map(simulated));
+
+                transactions
+            }
+            None => {
+                self.simulate_distribution(
+                    chain,
+                    Some(token_address),
+                    receivers,
+                    proportions,
+                    amount,
+                )
+                .await?
+            }
+        };
+
+        self.dry_run_response(
+            chain,
+            transactions,
+            Some(FormattedAmount::new(amount, decimals)),
+        )
+        .await
+    }
+
+    /// Validates and simulates a collection the way its job would, without broadcasting
+    pub async fn dry_run_collect_erc20_tokens(
+        &self,
+        payload: CollectErc20Payload,
+    ) -> Result<DryRunResponse, AppError> {
+        let chain = &self.sending_chain(payload.chain_id, payload.from.as_deref())?;
+
+        let token_address = payload.token_address.parse::<Address>()?;
+
+        let (froms, scaled_percents) = self.transform_collect_args_to_alloy(&payload)?;
+
+        let (_, allowances) = self
+            .check_collect_allowances(chain, token_address, &froms, &scaled_percents)
+            .await?;
+
+        ensure_collect_allowances(&allowances)?;
+
+        let (_, total_collect_amount) = self
+            .collect_amounts(chain, token_address, &allowances)
+            .await?;
+
+        let transactions = self
+            .build_collection(chain, token_address, froms, scaled_percents)
+            .await?
+            .into_iter()
+            .map(simulated)
+            .collect();
+
+        self.dry_run_response(chain, transactions, Some(total_collect_amount))
+            .await
+    }
+
+    async fn simulate_distribution(
+        &self,
+        chain: &Chain<T>,
+        token_address: Option<Address>,
+        receivers: Vec<Address>,
+        proportions: Vec<U256>,
+        amount: U256,
+    ) -> Result<Vec<SimulatedTransaction>, AppError> {
+        let transactions = self
+            .build_distribution(chain, token_address, receivers, proportions, amount)
+            .await?;
+
+        Ok(transactions.into_iter().map(simulated).collect())
+    }
+
+    /// Builds a native distribution as unsigned transactions without sending anything
+    pub async fn build_distribute_native_tokens(
+        &self,
+        payload: DistributeBasePayload,
+    ) -> Result<BuildResponse, AppError> {
+        let chain = &self.building_chain(payload.chain_id, payload.from.as_deref())?;
+
+        let (receivers, proportions, amount, _) =
+            self.transform_args_to_alloy(chain, payload, None).await?;
+
+        let transactions = self
+            .build_distribution(chain, None, receivers, proportions, amount)
+            .await?;
+
+        self.build_response(
+            chain,
+            transactions,
+            Some(FormattedAmount::new(amount, NATIVE_DECIMALS)),
+        )
+        .await
+    }
+
+    /// Builds an ERC20 distribution as unsigned transactions without sending anything,
+    /// preceded by the approval when the allowance of `from` is too low
+    pub async fn build_distribute_erc20_tokens(
+        &self,
+        payload: DistributeErc20Payload,
+    ) -> Result<BuildResponse, AppError> {
+        let chain = &self.building_chain(payload.base.chain_id, payload.base.from.as_deref())?;
+
+        let token_address = payload.token_address.parse::<Address>()?;
+        let (receivers, proportions, amount, decimals) = self
+            .transform_args_to_alloy(chain, payload.base, Some(token_address))
+            .await?;
+
+        let token_manager_address = chain.token_manager_service.get_token_manager_address();
+
+        let approve = chain
+            .erc20_service
+            .build_signer_approve(token_address, token_manager_address, amount)
+            .await?;
+
+        let transactions = match approve {
+            Some(approve) => {
+                // Signed right after the approval with the next nonces, their gas can't be simulated yet
+                let chunks = chain
+                    .token_manager_service
+                    .build_unsimulated_distribution(token_address, receivers, proportions, amount)
+                    .await?;
+
+                let mut transactions = vec![UnsignedTransaction {
+                    kind: TxKind::Approve,
+                    chunk: None,
+                    tx: approve,
+                }];
+                transactions.extend(distribution_transactions(chunks));
+
+                transactions
+            }
+            None => {
+                self.build_distribution(chain, Some(token_address), receivers, proportions, amount)
+                    .await?
+            }
+        };
+
+        self.build_response(
+            chain,
+            transactions,
+            Some(FormattedAmount::new(amount, decimals)),
+        )
+        .await
+    }
+
+    /// Builds a collection as unsigned transactions without sending anything
+    pub async fn build_collect_erc20_tokens(
+        &self,
+        payload: CollectErc20Payload,
+    ) -> Result<BuildResponse, AppError> {
+        let chain = &self.building_chain(payload.chain_id, payload.from.as_deref())?;
+
+        let token_address = payload.token_address.parse::<Address>()?;
+
+        let (froms, scaled_percents) = self.transform_collect_args_to_alloy(&payload)?;
+
+        let (_, allowances) = self
+            .check_collect_allowances(chain, token_address, &froms, &scaled_percents)
+            .await?;
+
+        ensure_collect_allowances(&allowances)?;
+
+        let (_, total_collect_amount) = self
+            .collect_amounts(chain, token_address, &allowances)
+            .await?;
+
+        let transactions = self
+            .build_collection(chain, token_address, froms, scaled_percents)
+            .await?;
+
+        self.build_response(chain, transactions, Some(total_collect_amount))
+            .await
+    }
+
+    /// Plans and simulates a distribution, nonce and fees of the transactions are still missing
+    async fn build_distribution(
+        &self,
+        chain: &Chain<T>,
+        token_address: Option<Address>,
+        receivers: Vec<Address>,
+        proportions: Vec<U256>,
+        amount: U256,
+    ) -> Result<Vec<UnsignedTransaction>, AppError> {
+        let chunks = chain
+            .token_manager_service
+            .plan_distribution(token_address, receivers, proportions, amount)
+            .await?;
+
+        let mut transactions = vec![];
+        for chunk in chunks {
+            let tx = chain
+                .token_manager_service
+                .build_distribution(token_address, &chunk)
+                .await?;
+
+            transactions.push((chunk, tx));
+        }
+
+        Ok(distribution_transactions(transactions))
+    }
+
+    /// Plans and simulates a collection, nonce and fees of the transactions are still missing
+    async fn build_collection(
+        &self,
+        chain: &Chain<T>,
+        token_address: Address,
+        froms: Vec<Address>,
+        scaled_percents: Vec<U256>,
+    ) -> Result<Vec<UnsignedTransaction>, AppError> {
+        let chunks = chain
+            .token_manager_service
+            .plan_collection(token_address, froms, scaled_percents)
+            .await?;
+
+        let count = chunks.len();
+        let mut offset = 0;
+        let mut transactions: Vec<UnsignedTransaction> = vec![];
+
+        for chunk in chunks.iter() {
+            let tx = chain
+                .token_manager_service
+                .build_collection(token_address, chunk)
+                .await?;
+
+            transactions.push(UnsignedTransaction {
+                kind: TxKind::Collect,
+                chunk: (count > 1).then_some(TxChunk {
+                    index: transactions.len(),
+                    count,
+                    offset,
+                    receivers: chunk.froms.len(),
+                }),
+                tx,
+            });
+            offset += chunk.froms.len();
+        }
+
+        Ok(transactions)
+    }
+
+    /// Numbers the transactions from the pending nonce of the sender and prices them with
+    /// the chain's gas policy, EIP-1559 unless it is in legacy mode
+    async fn build_response(
+        &self,
+        chain: &Chain<T>,
+        mut transactions: Vec<UnsignedTransaction>,
+        amount: Option<FormattedAmount>,
+    ) -> Result<BuildResponse, AppError> {
+        let nonce = chain.token_manager_service.pending_nonce().await?;
+        let fees = chain.token_manager_service.estimate_fees().await?;
+
+        for (position, transaction) in transactions.iter_mut().enumerate() {
+            let tx = std::mem::take(&mut transaction.tx)
+                .with_nonce(nonce + position as u64)
+                .with_chain_id(chain.info.chain_id);
+
+            transaction.tx = fees
+                .apply(tx)
+                .transaction_type(match fees.max_priority_fee_per_gas {
+                    Some(_) => 2,
+                    None => 0,
+                });
+        }
+
+        Ok(BuildResponse {
+            chain_id: chain.info.chain_id,
+            from: chain.token_manager_service.sender().to_string(),
+            transactions,
+            amount,
+        })
+    }
+
+    async fn dry_run_response(
+        &self,
+        chain: &Chain<T>,
+        transactions: Vec<SimulatedTransaction>,
+        amount: Option<FormattedAmount>,
+    ) -> Result<DryRunResponse, AppError> {
+        let fees = chain.token_manager_service.estimate_fees().await?;
+
+        let gas_estimate: u128 = transactions
+            .iter()
+            .filter_map(|tx| tx.gas_estimate.as_deref())
+            .filter_map(|gas| gas.parse::<u128>().ok())
+            .sum();
+
+        Ok(DryRunResponse {
+            transactions,
+            gas_estimate: gas_estimate.to_string(),
+            max_fee_per_gas: fees.max_fee_per_gas.to_string(),
+            max_priority_fee_per_gas: fees.max_priority_fee_per_gas.map(|fee| fee.to_string()),
+            max_fee: FormattedAmount::new(
+                U256::from(gas_estimate) * U256::from(fees.max_fee_per_gas),
+                NATIVE_DECIMALS,
+            ),
+            amount,
         })
     }
 
-    fn transform_args_to_alloy(
+    /// Exports a native distribution paid by the Safe in `from` as Transaction Builder batches
+    pub async fn export_safe_distribute_native_tokens(
         &self,
         payload: DistributeBasePayload,
-    ) -> (Vec<Address>, Vec<U256>, U256) {
-        let mut receivers: Vec<Address> = vec![];
-        let mut proportions: Vec<U256> = vec![];
+        chunk_size: Option<usize>,
+        origin: JobOrigin,
+    ) -> Result<SafeExport, AppError> {
+        safe_address(payload.from.as_deref())?;
+        let chain = &self.building_chain(payload.chain_id, payload.from.as_deref())?;
+
+        let payload_json = to_payload_json(&payload)?;
+
+        let (receivers, proportions, amount, _) =
+            self.transform_args_to_alloy(chain, payload, None).await?;
+
+        let token_manager_address = chain.token_manager_service.get_token_manager_address();
+
+        let batches = split_distribution(
+            receivers.clone(),
+            proportions.clone(),
+            amount,
+            chunk_size.unwrap_or(usize::MAX),
+        )?
+        .into_iter()
+        .map(|chunk| SafeBatch {
+            receivers: chunk.receivers.len(),
+            transactions: vec![safe_transaction(
+                token_manager_address,
+                chunk.total_amount,
+                TokenManager::distributeNativeTokensCall {
+                    receivers: chunk.receivers,
+                    proportions: chunk.proportions,
+                    totalAmount: chunk.total_amount,
+                }
+                .abi_encode(),
+            )],
+        })
+        .collect();
+
+        self.export_safe_batches(
+            chain,
+            JobKind::DistributeNative,
+            payload_json,
+            origin,
+            batches,
+            |tracker| {
+                record_distribution(
+                    tracker,
+                    None,
+                    amount,
+                    NATIVE_DECIMALS,
+                    &receivers,
+                    &proportions,
+                )
+            },
+        )
+    }
+
+    /// Exports an ERC20 distribution paid by the Safe in `from` as Transaction Builder batches,
+    /// the first batch approves TokenManager when the Safe's allowance is too low
+    pub async fn export_safe_distribute_erc20_tokens(
+        &self,
+        payload: DistributeErc20Payload,
+        chunk_size: Option<usize>,
+        origin: JobOrigin,
+    ) -> Result<SafeExport, AppError> {
+        safe_address(payload.base.from.as_deref())?;
+        let chain = &self.building_chain(payload.base.chain_id, payload.base.from.as_deref())?;
+
+        let payload_json = to_payload_json(&payload)?;
+
+        let token_address = payload.token_address.parse::<Address>()?;
+        let (receivers, proportions, amount, decimals) = self
+            .transform_args_to_alloy(chain, payload.base, Some(token_address))
+            .await?;
+
+        let token_manager_address = chain.token_manager_service.get_token_manager_address();
 
-        for set in payload.receivers_with_proportions {
-            receivers.push(set.receiver.parse::<Address>().unwrap());
-            proportions.push(set.proportion.parse::<U256>().unwrap())
+        let allowance = chain
+            .erc20_service
+            .fetch_signer_allowance(token_address, token_manager_address)
+            .await?;
+
+        let mut batches: Vec<SafeBatch> = split_distribution(
+            receivers.clone(),
+            proportions.clone(),
+            amount,
+            chunk_size.unwrap_or(usize::MAX),
+        )?
+        .into_iter()
+        .map(|chunk| SafeBatch {
+            receivers: chunk.receivers.len(),
+            transactions: vec![safe_transaction(
+                token_manager_address,
+                U256::ZERO,
+                TokenManager::distributeERC20TokensCall {
+                    tokenAddress: token_address,
+                    receivers: chunk.receivers,
+                    proportions: chunk.proportions,
+                    totalAmount: chunk.total_amount,
+                }
+                .abi_encode(),
+            )],
+        })
+        .collect();
+
+        if allowance < amount {
+            batches[0].transactions.insert(
+                0,
+                safe_transaction(
+                    token_address,
+                    U256::ZERO,
+                    ERC20::approveCall {
+                        spender: token_manager_address,
+                        value: amount,
+                    }
+                    .abi_encode(),
+                ),
+            );
         }
 
-        let amount = U256::from_str(&payload.amount).unwrap();
+        self.export_safe_batches(
+            chain,
+            JobKind::DistributeErc20,
+            payload_json,
+            origin,
+            batches,
+            |tracker| {
+                record_distribution(
+                    tracker,
+                    Some(token_address),
+                    amount,
+                    decimals,
+                    &receivers,
+                    &proportions,
+                )
+            },
+        )
+    }
 
-        (receivers, proportions, amount)
+    /// Exports a collection into the Safe in `from` as Transaction Builder batches
+    pub async fn export_safe_collect_erc20_tokens(
+        &self,
+        payload: CollectErc20Payload,
+        chunk_size: Option<usize>,
+        origin: JobOrigin,
+    ) -> Result<SafeExport, AppError> {
+        safe_address(payload.from.as_deref())?;
+        let chain = &self.building_chain(payload.chain_id, payload.from.as_deref())?;
+
+        let token_address = payload.token_address.parse::<Address>()?;
+
+        let (froms, scaled_percents) = self.transform_collect_args_to_alloy(&payload)?;
+
+        let (_, allowances) = self
+            .check_collect_allowances(chain, token_address, &froms, &scaled_percents)
+            .await?;
+
+        ensure_collect_allowances(&allowances)?;
+
+        let (wallets, total_collect_amount) = self
+            .collect_amounts(chain, token_address, &allowances)
+            .await?;
+
+        let token_manager_address = chain.token_manager_service.get_token_manager_address();
+
+        let batches = split_collection(froms, scaled_percents, chunk_size.unwrap_or(usize::MAX))
+            .into_iter()
+            .map(|chunk| SafeBatch {
+                receivers: chunk.froms.len(),
+                transactions: vec![safe_transaction(
+                    token_manager_address,
+                    U256::ZERO,
+                    TokenManager::collectERC20TokensCall {
+                        tokenAddress: token_address,
+                        wallets: chunk.froms,
+                        percentages: chunk.scaled_percents,
+                    }
+                    .abi_encode(),
+                )],
+            })
+            .collect();
+
+        self.export_safe_batches(
+            chain,
+            JobKind::CollectErc20,
+            to_payload_json(&payload)?,
+            origin,
+            batches,
+            |tracker| {
+                tracker.set_resolved_amounts(
+                    Some(token_address),
+                    Some(total_collect_amount),
+                    &wallets,
+                );
+
+                Ok(())
+            },
+        )
     }
 
-    pub async fn collect_erc20_tokens(&self, payload: CollectErc20Payload) -> Result<AppResponse> {
-        let token_address = payload.token_address.parse::<Address>()?.clone();
+    /// Stores the job of an export with one exported transaction per batch, so the hashes of
+    /// the Safe executions can be recorded against it. The files are stored with the job and a
+    /// replayed idempotency key returns them as first exported, whatever was built this time.
+    fn export_safe_batches(
+        &self,
+        chain: &Chain<T>,
+        kind: JobKind,
+        payload: serde_json::Value,
+        origin: JobOrigin,
+        batches: Vec<SafeBatch>,
+        record: impl FnOnce(&JobTracker) -> Result<(), AppError>,
+    ) -> Result<SafeExport, AppError> {
+        let (name, tx_kind) = match kind {
+            JobKind::DistributeNative => ("Distribute native tokens", TxKind::Distribute),
+            JobKind::DistributeErc20 => ("Distribute ERC20 tokens", TxKind::Distribute),
+            JobKind::CollectErc20 | JobKind::Broadcast => ("Collect ERC20 tokens", TxKind::Collect),
+        };
+        let safe = chain.token_manager_service.sender();
+        let count = batches.len();
 
-        let mut froms: Vec<Address> = vec![];
-        let mut scaled_percents: Vec<U256> = vec![];
+        let tracker = match self
+            .job_service
+            .create(kind, chain.info.chain_id, origin, payload)?
+        {
+            JobCreation::Created(tracker) => tracker,
+            JobCreation::Replayed(job_id) => return self.replay_safe_export(job_id),
+        };
+        let job_id = tracker.job_id();
 
-        for set in payload.sets {
-            froms.push(set.from.parse::<Address>()?);
-            scaled_percents.push(set.scaled_percent.parse::<U256>()?)
+        record(&tracker)?;
+
+        let created_at = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|duration| duration.as_millis() as u64)
+            .unwrap_or_default();
+
+        let mut files = vec![];
+        let mut offset = 0;
+        for (index, batch) in batches.into_iter().enumerate() {
+            let file = SafeBatchFile {
+                version: "1.0".to_string(),
+                chain_id: chain.info.chain_id.to_string(),
+                created_at,
+                meta: SafeBatchMeta {
+                    name: format!("{} ({} of {})", name, index + 1, count),
+                    description: format!(
+                        "Job {}, record the execution at /jobs/{}/safe-execution with batch {}",
+                        job_id, job_id, index
+                    ),
+                    created_from_safe_address: safe.to_string(),
+                },
+                transactions: batch.transactions,
+            };
+
+            tracker.add_exported_tx(
+                tx_kind,
+                TxChunk {
+                    index,
+                    count,
+                    offset,
+                    receivers: batch.receivers,
+                },
+                serde_json::to_string(&file).map_err(|e| AppError::Internal(e.to_string()))?,
+            );
+            offset += batch.receivers;
+            files.push(file);
         }
 
-        let mut wallets_and_balances_to_be_sent: Vec<WalletAndAmount> = vec![];
+        tracker.set_status(JobStatus::AwaitingExecution);
 
-        for (pos, _) in froms.iter().enumerate() {
-            let wallet_address = froms[pos];
-            let scaled_percent = scaled_percents[pos];
+        Ok(SafeExport {
+            job_id,
+            status_url: format!("/jobs/{}", job_id),
+            replayed: false,
+            batches: files,
+        })
+    }
+
+    /// Export of a replayed idempotency key, with the files stored when it was first exported
+    fn replay_safe_export(&self, job_id: Uuid) -> Result<SafeExport, AppError> {
+        let job = self
+            .job_service
+            .get(job_id)?
+            .ok_or_else(|| AppError::NotFound(format!("Job {} not found", job_id)))?;
+
+        let batches = job
+            .transactions
+            .iter()
+            .map(|tx| stored_safe_batch(job_id, tx))
+            .collect::<Result<Vec<_>, AppError>>()?;
+
+        Ok(SafeExport {
+            job_id,
+            status_url: format!("/jobs/{}", job_id),
+            replayed: true,
+            batches,
+        })
+    }
 
-            let balance = self
+    /// Speeds up or cancels the transaction a running job is waiting for
+    pub async fn replace_job_transaction(
+        &self,
+        job_id: Uuid,
+        replacement: Replacement,
+    ) -> Result<TxReplaced, AppError> {
+        let job = self
+            .job_service
+            .get(job_id)?
+            .ok_or_else(|| AppError::NotFound(format!("Job {} not found", job_id)))?;
+
+        let pending = job
+            .transactions
+            .iter()
+            .rev()
+            .find(|tx| tx.status == TxStatus::Submitted)
+            .and_then(|tx| Some((tx.kind, tx.tx_hash.as_deref()?.parse::<TxHash>().ok()?)));
+
+        let Some((kind, replaced_tx_hash)) = pending else {
+            return Err(AppError::Conflict {
+                code: "NO_PENDING_TRANSACTION",
+                message: format!(
+                    "Job {} is {:?} and has no transaction waiting for its receipt",
+                    job_id, job.status
+                ),
+            });
+        };
+
+        let chain_id = job
+            .chain_id
+            .ok_or_else(|| AppError::validation(format!("Job {} has no chain", job_id)))?;
+
+        let (tx_hash, fees) = self
+            .chains
+            .get(chain_id)?
+            .token_manager_service
+            .replace(replaced_tx_hash, replacement)
+            .await?;
+
+        self.job_service
+            .tracker(job_id)
+            .tx_listener(kind)
+            .on_replaced(replacement, replaced_tx_hash, tx_hash, fees);
+
+        Ok(TxReplaced {
+            job_id,
+            kind,
+            replacement,
+            replaced_tx_hash: replaced_tx_hash.to_string(),
+            tx_hash: tx_hash.to_string(),
+        })
+    }
+
+    /// Records the hash of the Safe transaction that executed one batch of an export and
+    /// confirms it in the background, the job is confirmed once every batch is
+    pub async fn record_safe_execution(
+        &self,
+        job_id: Uuid,
+        payload: SafeExecutionPayload,
+    ) -> Result<EnqueuedJob, AppError> {
+        let job = self
+            .job_service
+            .get(job_id)?
+            .ok_or_else(|| AppError::NotFound(format!("Job {} not found", job_id)))?;
+
+        if !job.transactions.iter().any(|tx| tx.safe_batch.is_some()) {
+            return Err(AppError::Conflict {
+                code: "JOB_NOT_AWAITING_EXECUTION",
+                message: format!("Job {} is not a Safe export", job_id),
+            });
+        }
+
+        let (position, batch) = job
+            .transactions
+            .iter()
+            .enumerate()
+            .find(|(_, tx)| tx.chunk.map_or(0, |chunk| chunk.index) == payload.batch)
+            .ok_or_else(|| AppError::Validation {
+                code: "UNKNOWN_BATCH",
+                message: format!(
+                    "Job {} has {} batch(es), there is no batch {}",
+                    job_id,
+                    job.transactions.len(),
+                    payload.batch
+                ),
+            })?;
+
+        if batch.status != TxStatus::Exported {
+            return Err(AppError::Conflict {
+                code: "BATCH_ALREADY_RECORDED",
+                message: format!(
+                    "Batch {} of job {} is already recorded as {:?}",
+                    payload.batch,
+                    job_id,
+                    batch.tx_hash.as_deref().unwrap_or_default()
+                ),
+            });
+        }
+        let calls = safe_calls(&stored_safe_batch(job_id, batch)?)?;
+
+        let safe = safe_address(job_safe_address(&job)?.as_deref())?;
+        let chain_id = job
+            .chain_id
+            .ok_or_else(|| AppError::validation(format!("Job {} has no chain", job_id)))?;
+        let tx_hash = payload.tx_hash.trim().parse::<TxHash>().map_err(|e| {
+            AppError::validation(format!(
+                "Invalid transaction hash '{}': {}",
+                payload.tx_hash, e
+            ))
+        })?;
+
+        self.chains
+            .get(chain_id)?
+            .token_manager_service
+            .check_safe_transaction(safe, tx_hash, &calls)
+            .await?;
+
+        let tracker = self.job_service.tracker(job_id);
+
+        // Recorded right away, so the batch is no longer awaited while the receipt is confirmed.
+        // The checks above may have raced with another record of this batch, the claim can't.
+        if !tracker.claim_exported_tx(position, tx_hash)? {
+            return Err(AppError::Conflict {
+                code: "BATCH_ALREADY_RECORDED",
+                message: format!(
+                    "Batch {} of job {} was recorded while {} was checked",
+                    payload.batch, job_id, tx_hash
+                ),
+            });
+        }
+
+        let listener = tracker.exported_tx_listener(position)?;
+
+        let service = self.clone();
+        tokio::spawn(async move {
+            let result = match service.chains.get(chain_id) {
+                Ok(chain) => {
+                    chain
+                        .token_manager_service
+                        .confirm_safe_execution(safe, tx_hash, &calls, &listener)
+                        .await
+                }
+                Err(error) => Err(error.into()),
+            };
+
+            let error = tracker.settle_tx(&listener, result).err();
+
+            service
+                .settle_safe_job(chain_id, safe, &tracker, error)
+                .await;
+        });
+
+        Ok(EnqueuedJob {
+            job_id,
+            replayed: false,
+        })
+    }
+
+    /// Finishes the job once every batch is settled, otherwise it keeps waiting for the other
+    /// executions. `error` is the failure of the batch settled last, if it failed.
+    async fn settle_safe_job(
+        &self,
+        chain_id: u64,
+        safe: Address,
+        tracker: &JobTracker,
+        error: Option<AppError>,
+    ) {
+        let job = match self.job_service.get(tracker.job_id()) {
+            Ok(Some(job)) => job,
+            _ => return,
+        };
+
+        match job.status {
+            JobStatus::Confirmed => {}
+            JobStatus::Failed => {
+                let failed = job
+                    .transactions
+                    .iter()
+                    .filter(|tx| tx.status == TxStatus::Failed)
+                    .count();
+
+                return tracker.finish(Err(error.unwrap_or_else(|| AppError::Revert {
+                    code: "SAFE_BATCH_FAILED",
+                    message: format!(
+                        "{} of {} batch(es) of job {} failed",
+                        failed,
+                        job.transactions.len(),
+                        job.id
+                    ),
+                })));
+            }
+            _ => return,
+        }
+
+        let tx_hashes: Vec<String> = job
+            .transactions
+            .iter()
+            .filter_map(|tx| tx.tx_hash.clone())
+            .collect();
+
+        let transfers = match self.chains.get(chain_id) {
+            Ok(chain) => self.transfer_report(chain, job.id, safe).await,
+            Err(_) => None,
+        };
+
+        tracker.finish(Ok(AppResponse {
+            tx_hash_distribute: single_tx_hash(&tx_hashes),
+            tx_hashes_distribute: tx_hashes,
+            tx_hash_approve: None,
+            amount: job.amount,
+            transfers,
+        }));
+    }
+
+    /// Compares what the confirmed transactions of a job moved with what it asked for. The
+    /// transactions are confirmed either way, so a report that can't be read is only logged.
+    async fn transfer_report(
+        &self,
+        chain: &Chain<T>,
+        job_id: Uuid,
+        payer: Address,
+    ) -> Option<TransferReport> {
+        match self.read_transfer_report(chain, job_id, payer).await {
+            Ok(report) => report,
+            Err(e) => {
+                println!(
+                    "->> {:<12} - transfer report of job {} failed: {}",
+                    "JOB", job_id, e
+                );
+                None
+            }
+        }
+    }
+
+    async fn read_transfer_report(
+        &self,
+        chain: &Chain<T>,
+        job_id: Uuid,
+        payer: Address,
+    ) -> anyhow::Result<Option<TransferReport>> {
+        let Some(job) = self.job_service.get(job_id)? else {
+            return Ok(None);
+        };
+
+        let receipts: Vec<TransactionReceipt> = job
+            .transactions
+            .iter()
+            .filter(|tx| tx.kind != TxKind::Approve && tx.status == TxStatus::Confirmed)
+            .filter_map(|tx| tx.receipt.as_deref())
+            .map(serde_json::from_str)
+            .collect::<Result<_, _>>()?;
+
+        let mut moved: Vec<(Address, U256)> = vec![];
+
+        let source = match job.kind {
+            JobKind::DistributeNative => {
+                for receipt in &receipts {
+                    let service = &chain.token_manager_service;
+
+                    match service.native_transfers(receipt.transaction_hash).await {
+                        Some(transfers) => moved.extend(transfers),
+                        None => return Ok(None),
+                    }
+                }
+
+                "trace"
+            }
+            JobKind::DistributeErc20 | JobKind::CollectErc20 => {
+                let Some(token_address) = job.token_address.as_deref() else {
+                    return Ok(None);
+                };
+                let token_address = Address::from_str(token_address)?;
+                let collection = job.kind == JobKind::CollectErc20;
+
+                for receipt in &receipts {
+                    moved.extend(erc20_transfers(receipt, token_address, payer, collection));
+                }
+
+                "logs"
+            }
+            JobKind::Broadcast => return Ok(None),
+        };
+
+        let requested = job
+            .receivers
+            .iter()
+            .map(|receiver| {
+                Ok((
+                    Address::from_str(&receiver.address)?,
+                    U256::from_str(&receiver.amount)?,
+                ))
+            })
+            .collect::<anyhow::Result<Vec<_>>>()?;
+
+        let decimals = job
+            .amount
+            .as_ref()
+            .map_or(NATIVE_DECIMALS, |amount| amount.decimals);
+
+        Ok(Some(reconcile_transfers(
+            source, &requested, &moved, decimals,
+        )))
+    }
+
+    /// Computes what each receiver gets without sending anything, using the same
+    /// formula and rounding as `distributeNativeTokens` / `distributeERC20Tokens`
+    pub async fn preview_distribution(
+        &self,
+        payload: DistributePreviewPayload,
+    ) -> Result<DistributionPreview, AppError> {
+        let chain = self.chains.get(payload.base.chain_id)?;
+
+        let token_address = match payload.token_address {
+            Some(token_address) => Some(token_address.parse::<Address>()?),
+            None => None,
+        };
+        let (receivers, proportions, amount, decimals) = self
+            .transform_args_to_alloy(chain, payload.base, token_address)
+            .await?;
+
+        let amounts = token_manager_math::distribution_amounts(amount, &proportions)?;
+        let distributed_amount: U256 = amounts.iter().sum();
+
+        let receivers = receivers
+            .iter()
+            .zip(proportions.iter())
+            .zip(amounts.iter())
+            .map(|((receiver, proportion), amount)| ReceiverAmount {
+                receiver: receiver.to_string(),
+                proportion: proportion.to_string(),
+                amount: FormattedAmount::new(*amount, decimals),
+            })
+            .collect();
+
+        Ok(DistributionPreview {
+            token_address: token_address.map(|address| address.to_string()),
+            total_amount: FormattedAmount::new(amount, decimals),
+            total_parts: token_manager_math::total_parts(&proportions)?.to_string(),
+            receivers,
+            distributed_amount: FormattedAmount::new(distributed_amount, decimals),
+            dust: FormattedAmount::new(amount - distributed_amount, decimals),
+        })
+    }
+
+    /// Validates a distribution and returns it with the decimals its amounts are formatted with.
+    /// Native tokens always have 18 decimals, ERC20 decimals are read even for base units,
+    /// so the echoed amounts show token units either way.
+    async fn transform_args_to_alloy(
+        &self,
+        chain: &Chain<T>,
+        payload: DistributeBasePayload,
+        token_address: Option<Address>,
+    ) -> Result<(Vec<Address>, Vec<U256>, U256, u8), AppError> {
+        let token_manager_address = chain.token_manager_service.get_token_manager_address();
+
+        validation::check_distribution(&payload, token_manager_address)?;
+
+        let decimals = match token_address {
+            None => NATIVE_DECIMALS,
+            Some(token_address) => chain.erc20_service.fetch_decimals(token_address).await?,
+        };
+
+        let ValidatedDistribution {
+            receivers,
+            proportions,
+            amount,
+        } = validation::validate_distribution(&payload, token_manager_address, decimals)?;
+
+        Ok((receivers, proportions, amount, decimals))
+    }
+
+    async fn collect_erc20_tokens(
+        &self,
+        payload: CollectErc20Payload,
+        tracker: &JobTracker,
+    ) -> Result<AppResponse, AppError> {
+        tracker.set_status(JobStatus::Validating);
+
+        let chain = &self.sending_chain(payload.chain_id, payload.from.as_deref())?;
+
+        let token_address = payload.token_address.parse::<Address>()?;
+
+        let (froms, scaled_percents) = self.transform_collect_args_to_alloy(&payload)?;
+
+        let (_, allowances) = self
+            .check_collect_allowances(chain, token_address, &froms, &scaled_percents)
+            .await?;
+
+        let (wallets, total_collect_amount) = self
+            .collect_amounts(chain, token_address, &allowances)
+            .await?;
+
+        tracker.set_resolved_amounts(
+            Some(token_address),
+            Some(total_collect_amount.clone()),
+            &wallets,
+        );
+
+        ensure_collect_allowances(&allowances)?;
+
+        let _submissions = lock_submissions(chain, chain.token_manager_service.sender()).await;
+
+        let chunks = chain
+            .token_manager_service
+            .plan_collection(token_address, froms, scaled_percents)
+            .await?;
+
+        let count = chunks.len();
+        let mut offset = 0;
+        let mut tx_hashes: Vec<String> = vec![];
+
+        for (index, chunk) in chunks.into_iter().enumerate() {
+            let tx_chunk = TxChunk {
+                index,
+                count,
+                offset,
+                receivers: chunk.froms.len(),
+            };
+            offset += chunk.froms.len();
+
+            let listener = tracker.chunk_listener(TxKind::Collect, tx_chunk);
+            let result = chain
+                .token_manager_service
+                .collect_erc20_tokens(token_address, chunk.froms, chunk.scaled_percents, &listener)
+                .await;
+            let receipt = tracker.settle_tx(&listener, result)?;
+
+            tx_hashes.push(receipt.transaction_hash.to_string());
+        }
+
+        Ok(AppResponse {
+            tx_hash_distribute: single_tx_hash(&tx_hashes),
+            tx_hashes_distribute: tx_hashes,
+            tx_hash_approve: None,
+            amount: Some(total_collect_amount),
+            transfers: self
+                .transfer_report(
+                    chain,
+                    tracker.job_id(),
+                    chain.token_manager_service.sender(),
+                )
+                .await,
+        })
+    }
+
+    /// Reports balances, amounts `collectERC20Tokens` would pull and allowance gaps of every wallet
+    pub async fn preview_collect_erc20_tokens(
+        &self,
+        payload: CollectErc20Payload,
+    ) -> Result<CollectionPreview, AppError> {
+        let chain = self.chains.get(payload.chain_id)?;
+
+        let token_address = payload.token_address.parse::<Address>()?;
+
+        let (froms, scaled_percents) = self.transform_collect_args_to_alloy(&payload)?;
+        let decimals = chain.erc20_service.fetch_decimals(token_address).await?;
+
+        let (balances, allowances) = self
+            .check_collect_allowances(chain, token_address, &froms, &scaled_percents)
+            .await?;
+
+        let total_collect_amount: U256 =
+            allowances.iter().map(|wallet| wallet.to_check_amount).sum();
+
+        let under_approved_wallets = allowances
+            .iter()
+            .filter(|wallet| !wallet.shortfall.is_zero())
+            .map(|wallet| wallet.address.to_string())
+            .collect();
+
+        let wallets = allowances
+            .iter()
+            .zip(scaled_percents.iter())
+            .zip(balances.iter())
+            .map(|((wallet, scaled_percent), balance)| WalletCollectPreview {
+                from: wallet.address.to_string(),
+                scaled_percent: scaled_percent.to_string(),
+                balance: FormattedAmount::new(*balance, decimals),
+                collect_amount: FormattedAmount::new(wallet.to_check_amount, decimals),
+                allowance: FormattedAmount::new(wallet.allowance, decimals),
+                shortfall: FormattedAmount::new(wallet.shortfall, decimals),
+            })
+            .collect();
+
+        Ok(CollectionPreview {
+            token_address: token_address.to_string(),
+            wallets,
+            total_collect_amount: FormattedAmount::new(total_collect_amount, decimals),
+            under_approved_wallets,
+        })
+    }
+
+    /// What `collectERC20Tokens` would pull from each wallet, and the total in token units
+    async fn collect_amounts(
+        &self,
+        chain: &Chain<T>,
+        token_address: Address,
+        allowances: &[WalletAllowance],
+    ) -> Result<(Vec<(Address, U256)>, FormattedAmount), AppError> {
+        let decimals = chain.erc20_service.fetch_decimals(token_address).await?;
+
+        let wallets: Vec<(Address, U256)> = allowances
+            .iter()
+            .map(|wallet| (wallet.address, wallet.to_check_amount))
+            .collect();
+        let total: U256 = wallets.iter().map(|(_, amount)| *amount).sum();
+
+        Ok((wallets, FormattedAmount::new(total, decimals)))
+    }
+
+    /// Fetches balances of wallets and checks their allowances against amounts
+    /// `collectERC20Tokens` would pull from them
+    async fn check_collect_allowances(
+        &self,
+        chain: &Chain<T>,
+        token_address: Address,
+        froms: &[Address],
+        scaled_percents: &[U256],
+    ) -> Result<(Vec<U256>, Vec<WalletAllowance>), AppError> {
+        let mut balances: Vec<U256> = vec![];
+        let mut wallets_and_balances_to_be_sent: Vec<WalletAndAmount> = vec![];
+
+        for (wallet_address, scaled_percent) in froms.iter().zip(scaled_percents.iter()) {
+            let balance = chain
                 .erc20_service
-                .fetch_balance(token_address.clone(), wallet_address)
+                .fetch_balance(token_address, *wallet_address)
                 .await?;
-            // In contract  uint256 public constant PERCENT_PRECISION = 1_000_000;
-            let to_check_amount = balance * scaled_percent / U256::from(1_000_000);
 
+            let to_check_amount = token_manager_math::collect_amount(balance, *scaled_percent)?;
+
+            balances.push(balance);
             wallets_and_balances_to_be_sent.push(WalletAndAmount {
-                address: wallet_address,
+                address: *wallet_address,
                 to_check_amount,
             })
         }
 
-        let token_manager_address = self.token_manager_service.get_token_manager_address();
+        let token_manager_address = chain.token_manager_service.get_token_manager_address();
 
-        self.erc20_service
+        let allowances = chain
+            .erc20_service
             .check_wallets_allowances(
-                token_address.clone(),
+                token_address,
                 wallets_and_balances_to_be_sent,
                 token_manager_address,
             )
             .await?;
 
-        let tx_hash = self
-            .token_manager_service
-            .collect_erc20_tokens(token_address.clone(), froms, scaled_percents)
-            .await?;
+        Ok((balances, allowances))
+    }
 
-        Ok(AppResponse {
-            tx_hash_distribute: Some(tx_hash.to_string()),
-            tx_hash_approve: None,
-            error: None,
+    fn transform_collect_args_to_alloy(
+        &self,
+        payload: &CollectErc20Payload,
+    ) -> Result<(Vec<Address>, Vec<U256>), AppError> {
+        let mut froms: Vec<Address> = vec![];
+        let mut scaled_percents: Vec<U256> = vec![];
+
+        for set in &payload.sets {
+            froms.push(set.from.parse::<Address>()?);
+            scaled_percents.push(set.scaled_percent.parse::<U256>()?)
+        }
+
+        Ok((froms, scaled_percents))
+    }
+}
+
+/// Waits until other jobs of the sending account are done, so their transactions never
+/// interleave. Jobs of the same account run one after another from this point.
+async fn lock_submissions<T>(chain: &Chain<T>, account: Address) -> SubmissionLock
+where
+    T: Transport + Clone,
+{
+    println!("->> {:<12} - waiting for other jobs of {}", "JOB", account);
+
+    chain.submitter.nonces.lock_submissions(account).await
+}
+
+/// Calls of one Safe transaction and the number of receivers (or wallets) they cover
+struct SafeBatch {
+    receivers: usize,
+    transactions: Vec<SafeBatchTransaction>,
+}
+
+fn safe_transaction(to: Address, value: U256, data: Vec<u8>) -> SafeBatchTransaction {
+    SafeBatchTransaction {
+        to: to.to_string(),
+        value: value.to_string(),
+        data: hex::encode_prefixed(data),
+        contract_method: None,
+        contract_inputs_values: None,
+    }
+}
+
+/// Transaction Builder file stored for an exported transaction
+fn stored_safe_batch(job_id: Uuid, tx: &JobTransaction) -> Result<SafeBatchFile, AppError> {
+    let safe_batch = tx.safe_batch.as_deref().ok_or_else(|| AppError::Conflict {
+        code: "SAFE_BATCH_NOT_STORED",
+        message: format!(
+            "Job {} was exported before its batch files were stored",
+            job_id
+        ),
+    })?;
+
+    serde_json::from_str(safe_batch).map_err(|e| AppError::Internal(e.to_string()))
+}
+
+/// Calls of a batch file, as the transaction executing it has to show them.
+/// The files are built by this backend, what they hold can't be wrong unless the store is.
+fn safe_calls(file: &SafeBatchFile) -> Result<Vec<SafeCall>, AppError> {
+    let stored = |field: &str, value: &str, e: FromHexError| {
+        AppError::Internal(format!(
+            "Stored batch has an invalid {} '{}': {}",
+            field, value, e
+        ))
+    };
+
+    file.transactions
+        .iter()
+        .map(|transaction| {
+            Ok(SafeCall {
+                to: transaction
+                    .to
+                    .parse::<Address>()
+                    .map_err(|e| stored("to", &transaction.to, e))?,
+                data: hex::decode(&transaction.data)
+                    .map_err(|e| stored("data", &transaction.data, e))?
+                    .into(),
+            })
         })
+        .collect()
+}
+
+/// Safe exports send from the Safe, so there is no default account to fall back to
+fn safe_address(from: Option<&str>) -> Result<Address, AppError> {
+    let from = from.ok_or_else(|| AppError::Validation {
+        code: "SAFE_ADDRESS_REQUIRED",
+        message: "Safe exports need the Safe address in `from`".to_string(),
+    })?;
+
+    Ok(from.parse::<Address>()?)
+}
+
+/// `from` of the payload an exported job was created with
+fn job_safe_address(job: &Job) -> Result<Option<String>, AppError> {
+    let from = match job.kind {
+        JobKind::DistributeNative => {
+            serde_json::from_value::<DistributeBasePayload>(job.payload.clone())
+                .map(|payload| payload.from)
+        }
+        JobKind::DistributeErc20 => {
+            serde_json::from_value::<DistributeErc20Payload>(job.payload.clone())
+                .map(|payload| payload.base.from)
+        }
+        JobKind::CollectErc20 => serde_json::from_value::<CollectErc20Payload>(job.payload.clone())
+            .map(|payload| payload.from),
+        JobKind::Broadcast => Ok(None),
+    };
+
+    from.map_err(|e| AppError::Internal(e.to_string()))
+}
+
+/// Distribution chunks as transactions, numbered when there are several
+fn distribution_transactions(
+    chunks: Vec<(DistributionChunk, TransactionRequest)>,
+) -> Vec<UnsignedTransaction> {
+    let count = chunks.len();
+    let mut offset = 0;
+
+    chunks
+        .into_iter()
+        .enumerate()
+        .map(|(index, (chunk, tx))| {
+            let tx_chunk = TxChunk {
+                index,
+                count,
+                offset,
+                receivers: chunk.receivers.len(),
+            };
+            offset += chunk.receivers.len();
+
+            UnsignedTransaction {
+                kind: TxKind::Distribute,
+                chunk: (count > 1).then_some(tx_chunk),
+                tx,
+            }
+        })
+        .collect()
+}
+
+fn simulated(transaction: UnsignedTransaction) -> SimulatedTransaction {
+    SimulatedTransaction {
+        kind: transaction.kind,
+        chunk: transaction.chunk,
+        gas_estimate: transaction.tx.gas.map(|gas| gas.to_string()),
+    }
+}
+
+/// Decodes a signed transaction of `/broadcast`. Only TokenManager calls and approvals
+/// for TokenManager are relayed, this is not a general purpose broadcaster.
+fn decode_signed<T>(chain: &Chain<T>, raw: &str) -> Result<(TxKind, TxEnvelope), AppError>
+where
+    T: Transport + Clone,
+{
+    let invalid = |message: String| AppError::Validation {
+        code: "INVALID_SIGNED_TRANSACTION",
+        message,
+    };
+
+    let bytes = hex::decode(raw.trim())
+        .map_err(|e| invalid(format!("Signed transaction is not hex: {}", e)))?;
+    let envelope = TxEnvelope::decode_2718(&mut bytes.as_slice())
+        .map_err(|e| invalid(format!("Signed transaction can't be decoded: {}", e)))?;
+
+    envelope.recover_signer().map_err(|e| {
+        invalid(format!(
+            "Signature of {} is invalid: {}",
+            envelope.tx_hash(),
+            e
+        ))
+    })?;
+
+    if envelope.chain_id() != Some(chain.info.chain_id) {
+        return Err(AppError::Validation {
+            code: "WRONG_CHAIN",
+            message: format!(
+                "Transaction {} is signed for chain {:?} instead of {}",
+                envelope.tx_hash(),
+                envelope.chain_id(),
+                chain.info.chain_id
+            ),
+        });
+    }
+
+    let token_manager_address = chain.token_manager_service.get_token_manager_address();
+    let input = envelope.input();
+    let selector = input.get(..4).unwrap_or_default();
+
+    let kind = match envelope.to().to() {
+        Some(to) if *to == token_manager_address => {
+            if selector == TokenManager::collectERC20TokensCall::SELECTOR {
+                Some(TxKind::Collect)
+            } else if selector == TokenManager::distributeNativeTokensCall::SELECTOR
+                || selector == TokenManager::distributeERC20TokensCall::SELECTOR
+            {
+                Some(TxKind::Distribute)
+            } else {
+                None
+            }
+        }
+        Some(_) => ERC20::approveCall::abi_decode(input, true)
+            .ok()
+            .filter(|call| call.spender == token_manager_address)
+            .map(|_| TxKind::Approve),
+        None => None,
+    };
+
+    let kind = kind.ok_or_else(|| AppError::Validation {
+        code: "UNSUPPORTED_TRANSACTION",
+        message: format!(
+            "Transaction {} is neither a TokenManager call nor an approval for it",
+            envelope.tx_hash()
+        ),
+    })?;
+
+    Ok((kind, envelope))
+}
+
+/// Stores amounts each receiver is going to get, so history can be filtered by them
+fn record_distribution(
+    tracker: &JobTracker,
+    token_address: Option<Address>,
+    amount: U256,
+    decimals: u8,
+    receivers: &[Address],
+    proportions: &[U256],
+) -> Result<(), AppError> {
+    let amounts = token_manager_math::distribution_amounts(amount, proportions)?;
+
+    let receivers: Vec<(Address, U256)> = receivers.iter().copied().zip(amounts).collect();
+
+    tracker.set_resolved_amounts(
+        token_address,
+        Some(FormattedAmount::new(amount, decimals)),
+        &receivers,
+    );
+
+    Ok(())
+}
+
+/// Collection reverts on the first under-approved wallet, so all of them are reported upfront
+fn ensure_collect_allowances(allowances: &[WalletAllowance]) -> Result<(), AppError> {
+    let under_approved: Vec<String> = allowances
+        .iter()
+        .filter(|wallet| !wallet.shortfall.is_zero())
+        .map(|wallet| {
+            format!(
+                "{} (needed {}, but has {})",
+                wallet.address, wallet.to_check_amount, wallet.allowance
+            )
+        })
+        .collect();
+
+    if !under_approved.is_empty() {
+        return Err(AppError::InsufficientFunds {
+            code: "INSUFFICIENT_ALLOWANCE",
+            message: format!(
+                "Wallets have insufficient allowance: {}",
+                under_approved.join(", ")
+            ),
+        });
+    }
+
+    Ok(())
+}
+
+fn to_payload_json<T: Serialize>(payload: &T) -> Result<serde_json::Value, AppError> {
+    serde_json::to_value(payload).map_err(|e| AppError::Internal(e.to_string()))
+}
+
+/// Sums both sides per address, requested addresses first in their order,
+/// then the addresses nobody asked for in the order they were paid
+fn reconcile_transfers(
+    source: &str,
+    requested: &[(Address, U256)],
+    moved: &[(Address, U256)],
+    decimals: u8,
+) -> TransferReport {
+    let mut order: Vec<Address> = vec![];
+    let mut requested_sums: HashMap<Address, U256> = HashMap::new();
+    let mut moved_sums: HashMap<Address, U256> = HashMap::new();
+
+    for (address, amount) in requested {
+        if !requested_sums.contains_key(address) {
+            order.push(*address);
+        }
+        *requested_sums.entry(*address).or_default() += *amount;
+    }
+
+    for (address, amount) in moved {
+        if !requested_sums.contains_key(address) && !moved_sums.contains_key(address) {
+            order.push(*address);
+        }
+        *moved_sums.entry(*address).or_default() += *amount;
+    }
+
+    let transfers: Vec<AddressTransfer> = order
+        .into_iter()
+        .map(|address| {
+            let requested = requested_sums.get(&address).copied();
+            let transferred = moved_sums.get(&address).copied().unwrap_or_default();
+
+            AddressTransfer {
+                address: address.to_string(),
+                requested: requested.map(|amount| FormattedAmount::new(amount, decimals)),
+                transferred: FormattedAmount::new(transferred, decimals),
+                matches: requested == Some(transferred),
+            }
+        })
+        .collect();
+
+    TransferReport {
+        source: source.to_string(),
+        reconciled: transfers.iter().all(|transfer| transfer.matches),
+        transfers,
+    }
+}
+
+/// Hash kept in `tx_hash_distribute` for jobs sent as one transaction
+fn single_tx_hash(tx_hashes: &[String]) -> Option<String> {
+    match tx_hashes {
+        [tx_hash] => Some(tx_hash.clone()),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const ALICE: Address = Address::with_last_byte(1);
+    const BOB: Address = Address::with_last_byte(2);
+    const FEE_RECEIVER: Address = Address::with_last_byte(3);
+
+    fn amounts(transfers: &[(Address, u64)]) -> Vec<(Address, U256)> {
+        transfers
+            .iter()
+            .map(|(address, amount)| (*address, U256::from(*amount)))
+            .collect()
+    }
+
+    fn summary(report: &TransferReport) -> Vec<(Address, Option<String>, String, bool)> {
+        report
+            .transfers
+            .iter()
+            .map(|transfer| {
+                (
+                    transfer.address.parse().unwrap(),
+                    transfer.requested.as_ref().map(|amount| amount.raw.clone()),
+                    transfer.transferred.raw.clone(),
+                    transfer.matches,
+                )
+            })
+            .collect()
+    }
+
+    #[test]
+    fn reconciles_exact_transfers() {
+        let report = reconcile_transfers(
+            "logs",
+            &amounts(&[(ALICE, 25), (BOB, 75)]),
+            &amounts(&[(BOB, 75), (ALICE, 25)]),
+            0,
+        );
+
+        assert!(report.reconciled);
+        assert_eq!(report.source, "logs");
+        assert_eq!(
+            summary(&report),
+            [
+                (ALICE, Some("25".to_string()), "25".to_string(), true),
+                (BOB, Some("75".to_string()), "75".to_string(), true),
+            ]
+        );
+    }
+
+    #[test]
+    fn sums_both_sides_per_address() {
+        // A receiver listed in two chunks, paid by two transactions
+        let report = reconcile_transfers(
+            "logs",
+            &amounts(&[(ALICE, 10), (BOB, 5), (ALICE, 15)]),
+            &amounts(&[(ALICE, 10), (BOB, 5), (ALICE, 15)]),
+            0,
+        );
+
+        assert!(report.reconciled);
+        assert_eq!(
+            summary(&report),
+            [
+                (ALICE, Some("25".to_string()), "25".to_string(), true),
+                (BOB, Some("5".to_string()), "5".to_string(), true),
+            ]
+        );
+    }
+
+    #[test]
+    fn reports_short_payments_and_addresses_nobody_asked_for() {
+        // Fee-on-transfer token: receivers get less and the fee goes elsewhere
+        let report = reconcile_transfers(
+            "logs",
+            &amounts(&[(ALICE, 100), (BOB, 100)]),
+            &amounts(&[(ALICE, 99), (FEE_RECEIVER, 1), (BOB, 100)]),
+            0,
+        );
+
+        assert!(!report.reconciled);
+        assert_eq!(
+            summary(&report),
+            [
+                (ALICE, Some("100".to_string()), "99".to_string(), false),
+                (BOB, Some("100".to_string()), "100".to_string(), true),
+                (FEE_RECEIVER, None, "1".to_string(), false),
+            ]
+        );
+    }
+
+    #[test]
+    fn reports_requested_addresses_that_got_nothing() {
+        let report = reconcile_transfers("trace", &amounts(&[(ALICE, 1)]), &[], 18);
+
+        assert!(!report.reconciled);
+        assert_eq!(report.transfers[0].transferred.formatted, "0");
+        assert_eq!(
+            report.transfers[0].requested.as_ref().unwrap().formatted,
+            "0.000000000000000001"
+        );
+    }
+
+    #[test]
+    fn single_tx_hash_only_for_jobs_of_one_transaction() {
+        let hashes = ["0x01".to_string(), "0x02".to_string()];
+
+        assert_eq!(single_tx_hash(&hashes[..1]), Some("0x01".to_string()));
+        assert_eq!(single_tx_hash(&hashes), None);
+        assert_eq!(single_tx_hash(&[]), None);
     }
 }
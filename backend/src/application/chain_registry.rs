@@ -0,0 +1,187 @@
+use crate::application::erc20_service::Erc20Service;
+use crate::application::token_manager_service::TokenManagerService;
+use crate::shared::app_error::AppError;
+use crate::shared::chain_config::ChainConfig;
+use crate::shared::config::GasConfig;
+use crate::shared::contracts::TokenManager;
+use crate::shared::provider_health::ProviderHealth;
+use crate::shared::signed_provider::{SignedProvider, SignerWallet, Web3Provider};
+use crate::shared::submitter::{ReplacementPolicy, Submitter};
+use alloy::network::{Ethereum, NetworkWallet};
+use alloy::primitives::Address;
+use alloy::providers::Provider;
+use alloy::transports::{BoxTransport, Transport};
+use anyhow::{bail, Result};
+use serde::Serialize;
+use std::collections::BTreeMap;
+use std::sync::Arc;
+
+/// Services bound to the provider and TokenManager deployment of one chain
+pub struct Chain<T: Transport + Clone = BoxTransport> {
+    pub info: ChainInfo,
+    pub erc20_service: Erc20Service<T>,
+    pub token_manager_service: TokenManagerService<T>,
+    pub health: ProviderHealth,
+    pub submitter: Submitter,
+    /// Read-only uses like the event indexer, transactions go through the services
+    pub provider: SignedProvider<T>,
+}
+
+impl<T> Chain<T>
+where
+    T: Transport + Clone,
+{
+    /// Services of this chain sending from `sender` instead of the default account
+    pub fn with_sender(&self, sender: Address) -> Self {
+        Self {
+            info: self.info.clone(),
+            erc20_service: self.erc20_service.with_sender(sender),
+            token_manager_service: self.token_manager_service.with_sender(sender),
+            health: self.health.clone(),
+            submitter: self.submitter.clone(),
+            provider: self.provider.clone(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct ChainInfo {
+    pub chain_id: u64,
+    pub name: String,
+    pub token_manager_address: String,
+    pub native_symbol: String,
+    pub confirmations: u64,
+    /// Events are indexed from this block, absent when the indexer is off for this chain
+    pub events_start_block: Option<u64>,
+}
+
+#[derive(Clone)]
+pub struct ChainRegistry<T: Transport + Clone = BoxTransport> {
+    chains: Arc<BTreeMap<u64, Chain<T>>>,
+    /// Accounts of the wallet, the default one first
+    accounts: Arc<Vec<Address>>,
+}
+
+impl ChainRegistry {
+    /// Connects to every configured chain and makes sure each node serves the expected chain id
+    pub async fn connect(
+        configs: Vec<ChainConfig>,
+        wallet: SignerWallet,
+        gas: &GasConfig,
+        policy: ReplacementPolicy,
+    ) -> Result<Self> {
+        let default_account = NetworkWallet::<Ethereum>::default_signer_address(&wallet);
+
+        let mut accounts: Vec<Address> = NetworkWallet::<Ethereum>::signer_addresses(&wallet)
+            .filter(|account| *account != default_account)
+            .collect();
+        accounts.sort();
+        accounts.insert(0, default_account);
+
+        let mut chains = BTreeMap::new();
+
+        for config in configs {
+            let (provider, health) =
+                Web3Provider::prepare_signed(&config.rpc_url, wallet.clone()).await?;
+
+            let chain_id = provider.get_chain_id().await?;
+
+            if let Some(expected) = config.chain_id {
+                if expected != chain_id {
+                    bail!(
+                        "Chain {} is configured, but its RPC node serves chain {}",
+                        expected,
+                        chain_id
+                    );
+                }
+            }
+
+            if chains.contains_key(&chain_id) {
+                bail!("Chain {} is configured more than once", chain_id);
+            }
+
+            let submitter = Submitter::new(policy, gas.policy(&config.gas), config.confirmations);
+            submitter.nonces.sync(&provider, &accounts).await?;
+
+            let token_manager_instance =
+                TokenManager::new(config.token_manager_address, provider.clone());
+
+            let info = ChainInfo {
+                chain_id,
+                name: config.name.unwrap_or(format!("chain-{}", chain_id)),
+                token_manager_address: config.token_manager_address.to_string(),
+                native_symbol: config.native_symbol,
+                confirmations: config.confirmations,
+                events_start_block: config.events_start_block,
+            };
+
+            println!("->> Chain {} ({}) is ready!", info.chain_id, info.name);
+
+            chains.insert(
+                chain_id,
+                Chain {
+                    erc20_service: Erc20Service::new(provider.clone(), submitter.clone()),
+                    token_manager_service: TokenManagerService::new(
+                        token_manager_instance,
+                        u128::from(gas.max_gas_per_tx),
+                        submitter.clone(),
+                    ),
+                    health,
+                    submitter,
+                    provider,
+                    info,
+                },
+            );
+        }
+
+        Ok(Self {
+            chains: Arc::new(chains),
+            accounts: Arc::new(accounts),
+        })
+    }
+}
+
+impl<T> ChainRegistry<T>
+where
+    T: Transport + Clone,
+{
+    pub fn get(&self, chain_id: u64) -> Result<&Chain<T>, AppError> {
+        self.chains
+            .get(&chain_id)
+            .ok_or_else(|| AppError::Validation {
+                code: "UNKNOWN_CHAIN",
+                message: format!(
+                    "Chain {} is not configured, available chains: {:?}",
+                    chain_id,
+                    self.chains.keys().collect::<Vec<_>>()
+                ),
+            })
+    }
+
+    pub fn chains(&self) -> impl Iterator<Item = &Chain<T>> {
+        self.chains.values()
+    }
+
+    pub fn accounts(&self) -> &[Address] {
+        &self.accounts
+    }
+
+    /// Account a job sends from, `from` has to be one of the wallet accounts
+    pub fn sender(&self, from: Option<Address>) -> Result<Address, AppError> {
+        match from {
+            None => Ok(self.accounts[0]),
+            Some(from) if self.accounts.contains(&from) => Ok(from),
+            Some(from) => Err(AppError::Validation {
+                code: "UNKNOWN_SENDER",
+                message: format!(
+                    "Account {} is not managed by the signer, available accounts: {:?}",
+                    from,
+                    self.accounts
+                        .iter()
+                        .map(|account| account.to_string())
+                        .collect::<Vec<_>>()
+                ),
+            }),
+        }
+    }
+}
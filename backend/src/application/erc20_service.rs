@@ -1,51 +1,81 @@
 use crate::shared::contracts::ERC20;
 use crate::shared::contracts::ERC20::ERC20Instance;
-use crate::shared::execute_call::execute_call;
+use crate::shared::execute_call::{build_call, execute_call, TxListener};
 use crate::shared::signed_provider::SignedProvider;
-use alloy::primitives::{Address, TxHash, U256};
+use crate::shared::submitter::Submitter;
+use alloy::primitives::{Address, U256};
 use alloy::providers::WalletProvider;
-use alloy::pubsub::PubSubFrontend;
-use anyhow::{bail, Result};
+use alloy::rpc::types::{TransactionReceipt, TransactionRequest};
+use alloy::transports::{BoxTransport, Transport};
+use anyhow::Result;
 
 pub struct WalletAndAmount {
     pub address: Address,
     pub to_check_amount: U256,
 }
 
+pub struct WalletAllowance {
+    pub address: Address,
+    pub to_check_amount: U256,
+    pub allowance: U256,
+    /// Missing allowance, zero when the wallet is approved enough
+    pub shortfall: U256,
+}
+
 #[derive(Clone)]
-pub struct Erc20Service {
-    provider: SignedProvider,
+pub struct Erc20Service<T: Transport + Clone = BoxTransport> {
+    provider: SignedProvider<T>,
+    /// Account approvals are sent from
+    sender: Address,
+    submitter: Submitter,
 }
 
-impl Erc20Service {
-    pub fn new(provider: SignedProvider) -> Self {
-        Self { provider }
+impl<T> Erc20Service<T>
+where
+    T: Transport + Clone,
+{
+    pub fn new(provider: SignedProvider<T>, submitter: Submitter) -> Self {
+        Self {
+            sender: provider.default_signer_address(),
+            provider,
+            submitter,
+        }
+    }
+
+    /// Same service sending from another account registered in the wallet
+    pub fn with_sender(&self, sender: Address) -> Self {
+        Self {
+            sender,
+            ..self.clone()
+        }
     }
 
+    /// Checks every wallet instead of stopping at the first one,
+    /// so all under-approved wallets can be fixed at once
     pub async fn check_wallets_allowances(
         &self,
         token_address: Address,
         wallets: Vec<WalletAndAmount>,
         spender: Address,
-    ) -> Result<()> {
+    ) -> Result<Vec<WalletAllowance>> {
         let contract_instance = ERC20::new(token_address, self.provider.clone());
 
+        let mut checked: Vec<WalletAllowance> = vec![];
+
         for wallet in wallets {
             let allowance: U256 = self
                 .fetch_allowance(contract_instance.clone(), wallet.address, spender)
                 .await?;
 
-            if allowance < wallet.to_check_amount {
-                bail!(
-                    "Wallet {} has insufficient allowance: needed {}, but has {}",
-                    wallet.address,
-                    wallet.to_check_amount,
-                    allowance
-                );
-            }
+            checked.push(WalletAllowance {
+                address: wallet.address,
+                to_check_amount: wallet.to_check_amount,
+                allowance,
+                shortfall: wallet.to_check_amount.saturating_sub(allowance),
+            });
         }
 
-        Ok(())
+        Ok(checked)
     }
 
     pub async fn check_signer_allowance_or_approve(
@@ -53,25 +83,82 @@ impl Erc20Service {
         token_address: Address,
         spender: Address,
         target_amount: U256,
-    ) -> Result<Option<TxHash>> {
+        listener: &dyn TxListener,
+    ) -> Result<Option<TransactionReceipt>> {
         let contract_instance = ERC20::new(token_address, self.provider.clone());
 
-        let signer_address = self.provider.default_signer_address();
-
         let allowance: U256 = self
-            .fetch_allowance(contract_instance.clone(), signer_address, spender)
+            .fetch_allowance(contract_instance.clone(), self.sender, spender)
             .await?;
 
         if allowance < target_amount {
             return Ok(Some(
-                self.approve_spent_amount(contract_instance.clone(), spender, target_amount)
-                    .await?,
+                self.approve_spent_amount(
+                    contract_instance.clone(),
+                    spender,
+                    target_amount,
+                    listener,
+                )
+                .await?,
             ));
         }
 
         Ok(None)
     }
 
+    /// Simulates the approval `check_signer_allowance_or_approve` would send,
+    /// `None` when the signer allowance already covers `target_amount`
+    pub async fn simulate_signer_approve(
+        &self,
+        token_address: Address,
+        spender: Address,
+        target_amount: U256,
+    ) -> Result<Option<u128>> {
+        let tx = self
+            .build_signer_approve(token_address, spender, target_amount)
+            .await?;
+
+        Ok(tx.map(|tx| tx.gas.unwrap_or_default()))
+    }
+
+    /// Unsigned approval `check_signer_allowance_or_approve` would send with its simulated gas,
+    /// `None` when the signer allowance already covers `target_amount`
+    pub async fn build_signer_approve(
+        &self,
+        token_address: Address,
+        spender: Address,
+        target_amount: U256,
+    ) -> Result<Option<TransactionRequest>> {
+        let contract_instance = ERC20::new(token_address, self.provider.clone());
+
+        let allowance: U256 = self
+            .fetch_allowance(contract_instance.clone(), self.sender, spender)
+            .await?;
+
+        if allowance >= target_amount {
+            return Ok(None);
+        }
+
+        let template = contract_instance
+            .approve(spender, target_amount)
+            .from(self.sender);
+
+        Ok(Some(
+            build_call(template, &self.submitter.gas, "approve_spent_amount").await?,
+        ))
+    }
+
+    pub async fn fetch_signer_allowance(
+        &self,
+        token_address: Address,
+        spender: Address,
+    ) -> Result<U256> {
+        let contract_instance = ERC20::new(token_address, self.provider.clone());
+
+        self.fetch_allowance(contract_instance, self.sender, spender)
+            .await
+    }
+
     pub async fn fetch_balance(&self, token_address: Address, owner: Address) -> Result<U256> {
         let contract_instance = ERC20::new(token_address, self.provider.clone());
 
@@ -80,9 +167,17 @@ impl Erc20Service {
         Ok(res)
     }
 
+    pub async fn fetch_decimals(&self, token_address: Address) -> Result<u8> {
+        let contract_instance = ERC20::new(token_address, self.provider.clone());
+
+        let res = contract_instance.decimals().call().await?._0;
+
+        Ok(res)
+    }
+
     async fn fetch_allowance(
         &self,
-        contract: ERC20Instance<PubSubFrontend, SignedProvider>,
+        contract: ERC20Instance<T, SignedProvider<T>>,
         owner: Address,
         spender: Address,
     ) -> Result<U256> {
@@ -93,12 +188,13 @@ impl Erc20Service {
 
     async fn approve_spent_amount(
         &self,
-        contract: ERC20Instance<PubSubFrontend, SignedProvider>,
+        contract: ERC20Instance<T, SignedProvider<T>>,
         spender: Address,
         amount: U256,
-    ) -> Result<TxHash> {
-        let template = contract.approve(spender, amount);
+        listener: &dyn TxListener,
+    ) -> Result<TransactionReceipt> {
+        let template = contract.approve(spender, amount).from(self.sender);
 
-        Ok(execute_call(template, "approve_spent_amount").await?)
+        execute_call(template, &self.submitter, "approve_spent_amount", listener).await
     }
 }
@@ -0,0 +1,438 @@
+use crate::application::chain_registry::{Chain, ChainRegistry};
+use crate::application::job_service::normalize_address;
+use crate::shared::contracts::TokenManager;
+use crate::shared::database::Database;
+use crate::shared::execute_call::last_confirmed_block;
+use crate::shared::signed_provider::SignedProvider;
+use alloy::primitives::Address;
+use alloy::providers::Provider;
+use alloy::rpc::types::{Filter, Log};
+use alloy::sol_types::SolEvent;
+use anyhow::{anyhow, Result};
+use rusqlite::types::{Type, Value};
+use rusqlite::{params, params_from_iter, Connection, OptionalExtension, Row};
+use serde::{Deserialize, Serialize};
+use std::time::Duration;
+
+/// Blocks asked for in one `eth_getLogs` while catching up, most providers cap the range
+const BACKFILL_BLOCKS: u64 = 2_000;
+/// Confirmed blocks are scanned this often, on chains served over HTTP it is the only way
+/// to see new events, subscriptions miss events while their connection is re-established
+const CATCH_UP_INTERVAL: Duration = Duration::from_secs(12);
+const RETRY_DELAY: Duration = Duration::from_secs(10);
+const DEFAULT_EVENTS_LIMIT: u32 = 100;
+const MAX_EVENTS_LIMIT: u32 = 1_000;
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum EventKind {
+    Distributed,
+    Collected,
+}
+
+/// `Distributed` or `Collected` event of a TokenManager
+#[derive(Debug, Clone, Serialize)]
+pub struct IndexedEvent {
+    pub chain_id: u64,
+    pub kind: EventKind,
+    pub block_number: u64,
+    pub block_hash: String,
+    pub tx_hash: String,
+    pub log_index: u64,
+    /// Account that called the TokenManager
+    pub sender: String,
+    /// Zero address for native tokens
+    pub token: String,
+    /// Receiver of a distribution or wallet of a collection
+    pub wallet: String,
+    pub amount: String,
+}
+
+#[derive(Debug, Default, Deserialize)]
+pub struct EventFilter {
+    pub chain_id: Option<u64>,
+    pub kind: Option<EventKind>,
+    pub token: Option<String>,
+    pub sender: Option<String>,
+    pub wallet: Option<String>,
+    /// Inclusive
+    pub from_block: Option<u64>,
+    /// Inclusive
+    pub to_block: Option<u64>,
+    /// 100 by default, at most 1000
+    pub limit: Option<u32>,
+}
+
+/// Keeps the TokenManager events of every chain with an `events_start_block` in the database.
+/// Blocks with `confirmations` blocks on top are scanned with `eth_getLogs` from where the last scan stopped,
+/// the scan replaces whatever was stored for them. Over WebSocket and IPC a log subscription
+/// adds events of newer blocks right away and removes them again when they are reorged out.
+#[derive(Clone)]
+pub struct EventIndexer {
+    database: Database,
+}
+
+/// What one indexing task needs from its chain
+struct IndexedChain {
+    chain_id: u64,
+    provider: SignedProvider,
+    token_manager_address: Address,
+    start_block: u64,
+    /// Blocks on top of a block before it is scanned, like transactions are confirmed
+    confirmations: u64,
+    subscribe: bool,
+}
+
+impl IndexedChain {
+    fn filter(&self) -> Filter {
+        Filter::new()
+            .address(self.token_manager_address)
+            .event_signature(vec![
+                TokenManager::Distributed::SIGNATURE_HASH,
+                TokenManager::Collected::SIGNATURE_HASH,
+            ])
+    }
+}
+
+impl EventIndexer {
+    pub fn new(database: Database) -> Self {
+        Self { database }
+    }
+
+    /// Starts one background task per indexed chain, they run as long as the process
+    pub fn spawn(&self, chains: &ChainRegistry) {
+        for chain in chains.chains() {
+            let Some(start_block) = chain.info.events_start_block else {
+                continue;
+            };
+
+            let indexed = indexed_chain(chain, start_block);
+
+            println!(
+                "->> Indexing events of chain {} from block {}",
+                indexed.chain_id, start_block
+            );
+
+            tokio::spawn(self.clone().run(indexed));
+        }
+    }
+
+    pub fn list(&self, filter: &EventFilter) -> Result<Vec<IndexedEvent>> {
+        let mut conditions: Vec<&str> = vec![];
+        let mut values: Vec<Value> = vec![];
+
+        if let Some(chain_id) = filter.chain_id {
+            conditions.push("chain_id = ?");
+            values.push(Value::Integer(chain_id as i64));
+        }
+        if let Some(kind) = filter.kind {
+            conditions.push("kind = ?");
+            values.push(Value::Text(kind.as_str().to_string()));
+        }
+        if let Some(token) = &filter.token {
+            conditions.push("token = ?");
+            values.push(Value::Text(normalize_address(token)?));
+        }
+        if let Some(sender) = &filter.sender {
+            conditions.push("sender = ?");
+            values.push(Value::Text(normalize_address(sender)?));
+        }
+        if let Some(wallet) = &filter.wallet {
+            conditions.push("wallet = ?");
+            values.push(Value::Text(normalize_address(wallet)?));
+        }
+        if let Some(from_block) = filter.from_block {
+            conditions.push("block_number >= ?");
+            values.push(Value::Integer(from_block as i64));
+        }
+        if let Some(to_block) = filter.to_block {
+            conditions.push("block_number <= ?");
+            values.push(Value::Integer(to_block as i64));
+        }
+
+        let where_clause = match conditions.is_empty() {
+            true => String::new(),
+            false => format!("WHERE {}", conditions.join(" AND ")),
+        };
+
+        values.push(Value::Integer(
+            filter
+                .limit
+                .unwrap_or(DEFAULT_EVENTS_LIMIT)
+                .min(MAX_EVENTS_LIMIT) as i64,
+        ));
+
+        let connection = self.database.connection();
+
+        let events = connection
+            .prepare(&format!(
+                "SELECT chain_id, kind, block_number, block_hash, tx_hash, log_index,
+                        sender, token, wallet, amount
+                 FROM events {}
+                 ORDER BY block_number DESC, log_index DESC LIMIT ?",
+                where_clause
+            ))?
+            .query_map(params_from_iter(values), read_event)?
+            .collect::<rusqlite::Result<_>>()?;
+
+        Ok(events)
+    }
+
+    /// Restarts from the stored cursor whenever the connection or the subscription fails
+    async fn run(self, chain: IndexedChain) {
+        loop {
+            if let Err(e) = self.follow(&chain).await {
+                println!(
+                    "->> {:<12} - chain {}: {}, retrying in {:?}",
+                    "INDEXER", chain.chain_id, e, RETRY_DELAY
+                );
+            }
+
+            tokio::time::sleep(RETRY_DELAY).await;
+        }
+    }
+
+    async fn follow(&self, chain: &IndexedChain) -> Result<()> {
+        // Subscribed before the first scan, so no block falls between the two
+        let mut subscription = match chain.subscribe {
+            true => Some(chain.provider.subscribe_logs(&chain.filter()).await?),
+            false => None,
+        };
+
+        // First tick completes right away
+        let mut catch_up = tokio::time::interval(CATCH_UP_INTERVAL);
+
+        loop {
+            match &mut subscription {
+                Some(subscription) => tokio::select! {
+                    log = subscription.recv() => self.store_live(chain.chain_id, &log?)?,
+                    _ = catch_up.tick() => self.backfill(chain).await?,
+                },
+                None => {
+                    catch_up.tick().await;
+                    self.backfill(chain).await?;
+                }
+            }
+        }
+    }
+
+    async fn backfill(&self, chain: &IndexedChain) -> Result<()> {
+        let head = chain.provider.get_block_number().await?;
+        let confirmed = last_confirmed_block(head, chain.confirmations);
+        let mut from = self.cursor(chain.chain_id)?.unwrap_or(chain.start_block);
+
+        while from <= confirmed {
+            let to = (from + BACKFILL_BLOCKS - 1).min(confirmed);
+
+            let logs = chain
+                .provider
+                .get_logs(&chain.filter().from_block(from).to_block(to))
+                .await?;
+
+            self.store_range(chain.chain_id, from, to, &logs)?;
+
+            if !logs.is_empty() {
+                println!(
+                    "->> {:<12} - chain {}: {} events in blocks {}..={}",
+                    "INDEXER",
+                    chain.chain_id,
+                    logs.len(),
+                    from,
+                    to
+                );
+            }
+
+            from = to + 1;
+        }
+
+        Ok(())
+    }
+
+    /// Next block to scan, `None` before the first scan
+    fn cursor(&self, chain_id: u64) -> Result<Option<u64>> {
+        let next_block: Option<i64> = self
+            .database
+            .connection()
+            .query_row(
+                "SELECT next_block FROM event_cursors WHERE chain_id = ?1",
+                [chain_id as i64],
+                |row| row.get(0),
+            )
+            .optional()?;
+
+        Ok(next_block.map(|block| block as u64))
+    }
+
+    /// Replaces the events of confirmed blocks `from..=to` with `logs` and moves the cursor
+    /// past them. Events a subscription stored for blocks that were reorged out are dropped.
+    fn store_range(&self, chain_id: u64, from: u64, to: u64, logs: &[Log]) -> Result<()> {
+        let mut connection = self.database.connection();
+        let tx = connection.transaction()?;
+
+        tx.execute(
+            "DELETE FROM events WHERE chain_id = ?1 AND block_number BETWEEN ?2 AND ?3",
+            params![chain_id as i64, from as i64, to as i64],
+        )?;
+
+        for log in logs {
+            insert_event(&tx, &decode_event(chain_id, log)?)?;
+        }
+
+        tx.execute(
+            "INSERT INTO event_cursors (chain_id, next_block) VALUES (?1, ?2)
+             ON CONFLICT (chain_id) DO UPDATE SET next_block = ?2",
+            params![chain_id as i64, (to + 1) as i64],
+        )?;
+
+        tx.commit()?;
+
+        Ok(())
+    }
+
+    /// Stores a log of the subscription, or deletes it when a reorg removed it.
+    /// The cursor is left alone, the block is scanned once it is confirmed.
+    fn store_live(&self, chain_id: u64, log: &Log) -> Result<()> {
+        let event = decode_event(chain_id, log)?;
+        let connection = self.database.connection();
+
+        if log.removed {
+            connection.execute(
+                "DELETE FROM events WHERE chain_id = ?1 AND tx_hash = ?2 AND log_index = ?3",
+                params![chain_id as i64, event.tx_hash, event.log_index as i64],
+            )?;
+        } else {
+            insert_event(&connection, &event)?;
+        }
+
+        Ok(())
+    }
+}
+
+impl EventKind {
+    fn as_str(&self) -> &'static str {
+        match self {
+            Self::Distributed => "distributed",
+            Self::Collected => "collected",
+        }
+    }
+
+    fn from_db(kind: &str) -> Option<Self> {
+        match kind {
+            "distributed" => Some(Self::Distributed),
+            "collected" => Some(Self::Collected),
+            _ => None,
+        }
+    }
+}
+
+fn indexed_chain(chain: &Chain, start_block: u64) -> IndexedChain {
+    IndexedChain {
+        chain_id: chain.info.chain_id,
+        provider: chain.provider.clone(),
+        token_manager_address: chain.token_manager_service.get_token_manager_address(),
+        start_block,
+        confirmations: chain.info.confirmations,
+        subscribe: chain.health.snapshot().transport != "http",
+    }
+}
+
+/// The same transaction mined again after a reorg replaces its earlier row
+fn insert_event(connection: &Connection, event: &IndexedEvent) -> rusqlite::Result<()> {
+    connection.execute(
+        "INSERT OR REPLACE INTO events
+         (chain_id, block_number, block_hash, tx_hash, log_index,
+          kind, sender, token, wallet, amount)
+         VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10)",
+        params![
+            event.chain_id as i64,
+            event.block_number as i64,
+            event.block_hash,
+            event.tx_hash,
+            event.log_index as i64,
+            event.kind.as_str(),
+            event.sender,
+            event.token,
+            event.wallet,
+            event.amount,
+        ],
+    )?;
+
+    Ok(())
+}
+
+fn decode_event(chain_id: u64, log: &Log) -> Result<IndexedEvent> {
+    let (kind, sender, token, wallet, amount) = match log.topic0() {
+        Some(&TokenManager::Distributed::SIGNATURE_HASH) => {
+            let event = log.log_decode::<TokenManager::Distributed>()?.inner.data;
+
+            (
+                EventKind::Distributed,
+                event.sender,
+                event.token,
+                event.receiver,
+                event.amount,
+            )
+        }
+        Some(&TokenManager::Collected::SIGNATURE_HASH) => {
+            let event = log.log_decode::<TokenManager::Collected>()?.inner.data;
+
+            (
+                EventKind::Collected,
+                event.collector,
+                event.token,
+                event.wallet,
+                event.amount,
+            )
+        }
+        _ => {
+            return Err(anyhow!(
+                "Log {:?} is not a TokenManager event",
+                log.topic0()
+            ))
+        }
+    };
+
+    let missing = |field: &str| anyhow!("Log has no {}, it is not mined", field);
+
+    Ok(IndexedEvent {
+        chain_id,
+        kind,
+        block_number: log.block_number.ok_or_else(|| missing("block number"))?,
+        block_hash: log
+            .block_hash
+            .ok_or_else(|| missing("block hash"))?
+            .to_string(),
+        tx_hash: log
+            .transaction_hash
+            .ok_or_else(|| missing("transaction hash"))?
+            .to_string(),
+        log_index: log.log_index.ok_or_else(|| missing("log index"))?,
+        sender: sender.to_string(),
+        token: token.to_string(),
+        wallet: wallet.to_string(),
+        amount: amount.to_string(),
+    })
+}
+
+fn read_event(row: &Row) -> rusqlite::Result<IndexedEvent> {
+    let kind: String = row.get(1)?;
+
+    Ok(IndexedEvent {
+        chain_id: row.get::<_, i64>(0)? as u64,
+        kind: EventKind::from_db(&kind).ok_or_else(|| {
+            rusqlite::Error::FromSqlConversionFailure(
+                1,
+                Type::Text,
+                format!("Unknown event kind '{}'", kind).into(),
+            )
+        })?,
+        block_number: row.get::<_, i64>(2)? as u64,
+        block_hash: row.get(3)?,
+        tx_hash: row.get(4)?,
+        log_index: row.get::<_, i64>(5)? as u64,
+        sender: row.get(6)?,
+        token: row.get(7)?,
+        wallet: row.get(8)?,
+        amount: row.get(9)?,
+    })
+}
@@ -0,0 +1,1190 @@
+use crate::shared::app_error::{AppError, ErrorResponse};
+use crate::shared::database::Database;
+use crate::shared::execute_call::{FeeEstimate, TxListener};
+use crate::shared::submitter::Replacement;
+use crate::shared::units::FormattedAmount;
+use crate::AppResponse;
+use alloy::primitives::{keccak256, Address, TxHash, U256};
+use alloy::rpc::types::TransactionReceipt;
+use anyhow::{anyhow, bail, Result};
+use rusqlite::types::Value;
+use rusqlite::{params, params_from_iter, Connection, OptionalExtension, Row};
+use serde::{Deserialize, Serialize};
+use std::str::FromStr;
+use std::sync::OnceLock;
+use std::time::{SystemTime, UNIX_EPOCH};
+use uuid::Uuid;
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum JobKind {
+    DistributeNative,
+    DistributeErc20,
+    CollectErc20,
+    /// Transactions signed outside of this process and sent through `/broadcast`
+    Broadcast,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum JobStatus {
+    Queued,
+    Validating,
+    Approving,
+    Submitted,
+    /// Last transaction is in a block, but not deep enough to be confirmed
+    Included,
+    Confirmed,
+    Failed,
+    /// Exported as a Safe batch, waiting for the hashes of its executions
+    AwaitingExecution,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum TxKind {
+    Approve,
+    Distribute,
+    Collect,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum TxStatus {
+    /// Executed outside of this backend, the hash is not recorded yet
+    Exported,
+    Submitted,
+    /// In a block without `confirmations` blocks on top yet
+    Included,
+    Confirmed,
+    Failed,
+}
+
+/// Position of a transaction within a job split into several transactions
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct TxChunk {
+    pub index: usize,
+    pub count: usize,
+    /// Number of receivers (or wallets) sent by the chunks before this one
+    pub offset: usize,
+    pub receivers: usize,
+}
+
+/// Version of a transaction sent with the same nonce in place of `replaced_tx_hash`
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct TxReplacement {
+    pub kind: Replacement,
+    pub replaced_tx_hash: String,
+    pub tx_hash: String,
+}
+
+/// Block that included a transaction and was then reorged out of the chain
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct TxReorg {
+    pub tx_hash: String,
+    pub block_number: u64,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct JobTransaction {
+    pub kind: TxKind,
+    pub chunk: Option<TxChunk>,
+    pub status: TxStatus,
+    /// Latest version sent, or the version that was mined
+    pub tx_hash: Option<String>,
+    /// Every replacement in the order they were sent
+    pub replacements: Vec<TxReplacement>,
+    /// Fees of the latest version, `gasPrice` when there is no priority fee
+    pub max_fee_per_gas: Option<String>,
+    pub max_priority_fee_per_gas: Option<String>,
+    /// Non-empty when the transaction had to be waited for again after a reorg
+    pub reorgs: Vec<TxReorg>,
+    pub block_number: Option<u64>,
+    pub gas_used: Option<String>,
+    pub effective_gas_price: Option<String>,
+    /// Full receipt is only kept in the database
+    #[serde(skip)]
+    pub receipt: Option<String>,
+    /// Transaction Builder file of an exported batch, returned again when the export is replayed
+    #[serde(skip)]
+    pub safe_batch: Option<String>,
+    pub error: Option<ErrorResponse>,
+}
+
+/// Receiver of a distribution or wallet of a collection with the resolved amount
+#[derive(Debug, Clone, Serialize)]
+pub struct JobReceiver {
+    pub address: String,
+    pub amount: String,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct Job {
+    pub id: Uuid,
+    pub kind: JobKind,
+    /// Absent for jobs created before multi-chain support
+    pub chain_id: Option<u64>,
+    pub status: JobStatus,
+    pub requested_by: Option<String>,
+    pub payload: serde_json::Value,
+    pub token_address: Option<String>,
+    pub amount: Option<FormattedAmount>,
+    pub receivers: Vec<JobReceiver>,
+    pub transactions: Vec<JobTransaction>,
+    pub result: Option<AppResponse>,
+    pub error: Option<ErrorResponse>,
+    pub created_at: u64,
+    pub updated_at: u64,
+}
+
+#[derive(Debug, Default, Deserialize)]
+pub struct HistoryFilter {
+    pub chain_id: Option<u64>,
+    pub token: Option<String>,
+    pub receiver: Option<String>,
+    /// Inclusive, `YYYY-MM-DD` or any datetime SQLite understands
+    pub from_date: Option<String>,
+    /// Inclusive, `YYYY-MM-DD` or any datetime SQLite understands
+    pub to_date: Option<String>,
+    pub status: Option<JobStatus>,
+    /// 100 by default, at most 1000
+    pub limit: Option<u32>,
+}
+
+const DEFAULT_HISTORY_LIMIT: u32 = 100;
+const MAX_HISTORY_LIMIT: u32 = 1_000;
+
+/// Who asked for a job and the optional client-provided `Idempotency-Key`
+#[derive(Debug, Clone, Default)]
+pub struct JobOrigin {
+    pub requested_by: Option<String>,
+    pub idempotency_key: Option<String>,
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct EnqueuedJob {
+    pub job_id: Uuid,
+    /// True when the idempotency key was already used for the same payload
+    pub replayed: bool,
+}
+
+pub enum JobCreation {
+    Created(JobTracker),
+    Replayed(Uuid),
+}
+
+/// Registry of jobs persisted in SQLite, shared between handlers and spawned job tasks
+#[derive(Clone)]
+pub struct JobService {
+    database: Database,
+}
+
+impl JobService {
+    pub fn new(database: Database) -> Self {
+        Self { database }
+    }
+
+    /// Creates a job, or returns the job already created with the same idempotency key.
+    /// Reusing a key with a different payload is rejected.
+    pub fn create(
+        &self,
+        kind: JobKind,
+        chain_id: u64,
+        origin: JobOrigin,
+        payload: serde_json::Value,
+    ) -> Result<JobCreation> {
+        let connection = self.database.connection();
+
+        let payload_hash = keccak256(format!("{}:{}", to_db_enum(&kind)?, payload)).to_string();
+
+        if let Some(key) = &origin.idempotency_key {
+            let existing: Option<(String, String)> = connection
+                .query_row(
+                    "SELECT payload_hash, job_id FROM idempotency_keys WHERE key = ?1",
+                    [key],
+                    |row| Ok((row.get(0)?, row.get(1)?)),
+                )
+                .optional()?;
+
+            if let Some((existing_hash, job_id)) = existing {
+                if existing_hash != payload_hash {
+                    bail!(AppError::Conflict {
+                        code: "IDEMPOTENCY_KEY_REUSED",
+                        message: format!(
+                            "Idempotency key '{}' was already used with a different payload",
+                            key
+                        ),
+                    });
+                }
+
+                return Ok(JobCreation::Replayed(Uuid::from_str(&job_id)?));
+            }
+        }
+
+        let now = unix_timestamp();
+        let job = Job {
+            id: Uuid::new_v4(),
+            kind,
+            chain_id: Some(chain_id),
+            status: JobStatus::Queued,
+            requested_by: origin.requested_by,
+            payload,
+            token_address: None,
+            amount: None,
+            receivers: vec![],
+            transactions: vec![],
+            result: None,
+            error: None,
+            created_at: now,
+            updated_at: now,
+        };
+
+        insert_job(&connection, &job)?;
+
+        if let Some(key) = &origin.idempotency_key {
+            connection.execute(
+                "INSERT INTO idempotency_keys (key, payload_hash, job_id, created_at)
+                 VALUES (?1, ?2, ?3, ?4)",
+                params![key, payload_hash, job.id.to_string(), now as i64],
+            )?;
+        }
+
+        Ok(JobCreation::Created(JobTracker {
+            jobs: self.clone(),
+            job_id: job.id,
+        }))
+    }
+
+    /// Handle of an existing job, e.g. to record a transaction executed outside of this backend
+    pub fn tracker(&self, job_id: Uuid) -> JobTracker {
+        JobTracker {
+            jobs: self.clone(),
+            job_id,
+        }
+    }
+
+    pub fn get(&self, job_id: Uuid) -> Result<Option<Job>> {
+        load_job(&self.database.connection(), job_id)
+    }
+
+    pub fn history(&self, filter: &HistoryFilter) -> Result<Vec<Job>> {
+        let connection = self.database.connection();
+
+        let mut conditions: Vec<&str> = vec![];
+        let mut values: Vec<Value> = vec![];
+
+        if let Some(chain_id) = filter.chain_id {
+            conditions.push("chain_id = ?");
+            values.push(Value::Integer(chain_id as i64));
+        }
+        if let Some(token) = &filter.token {
+            conditions.push("token_address = ?");
+            values.push(Value::Text(normalize_address(token)?));
+        }
+        if let Some(receiver) = &filter.receiver {
+            conditions.push("id IN (SELECT job_id FROM job_receivers WHERE address = ?)");
+            values.push(Value::Text(normalize_address(receiver)?));
+        }
+        if let Some(from_date) = &filter.from_date {
+            check_date(&connection, "from_date", from_date)?;
+            conditions.push("created_at >= CAST(strftime('%s', ?) AS INTEGER)");
+            values.push(Value::Text(from_date.clone()));
+        }
+        if let Some(to_date) = &filter.to_date {
+            check_date(&connection, "to_date", to_date)?;
+            conditions.push("created_at < CAST(strftime('%s', ?, '+1 day') AS INTEGER)");
+            values.push(Value::Text(to_date.clone()));
+        }
+        if let Some(status) = filter.status {
+            conditions.push("status = ?");
+            values.push(Value::Text(to_db_enum(&status)?));
+        }
+
+        let where_clause = match conditions.is_empty() {
+            true => String::new(),
+            false => format!("WHERE {}", conditions.join(" AND ")),
+        };
+
+        values.push(Value::Integer(
+            filter
+                .limit
+                .unwrap_or(DEFAULT_HISTORY_LIMIT)
+                .min(MAX_HISTORY_LIMIT) as i64,
+        ));
+
+        let ids: Vec<String> = connection
+            .prepare(&format!(
+                "SELECT id FROM jobs {} ORDER BY created_at DESC LIMIT ?",
+                where_clause
+            ))?
+            .query_map(params_from_iter(values), |row| row.get(0))?
+            .collect::<rusqlite::Result<_>>()?;
+
+        ids.iter()
+            .filter_map(|id| Uuid::from_str(id).ok())
+            .filter_map(|id| load_job(&connection, id).transpose())
+            .collect()
+    }
+
+    /// Applies `f` to the job without its receivers, which only `set_resolved_amounts` writes.
+    /// The job row and the transactions `f` changed are written back, the others are left alone.
+    fn update(&self, job_id: Uuid, f: impl FnOnce(&mut Job)) {
+        let mut connection = self.database.connection();
+
+        let result = load_job_state(&connection, job_id).and_then(|job| {
+            let mut job = job.ok_or_else(|| anyhow!("Job {} not found", job_id))?;
+            let before = job.transactions.clone();
+
+            f(&mut job);
+            job.updated_at = unix_timestamp();
+
+            save_job_state(&mut connection, &job, &before)
+        });
+
+        if let Err(e) = result {
+            println!("->> {:<12} - failed to update job {}: {}", "JOB", job_id, e);
+        }
+    }
+}
+
+/// Handle of a single job used by the task executing it
+#[derive(Clone)]
+pub struct JobTracker {
+    jobs: JobService,
+    job_id: Uuid,
+}
+
+impl JobTracker {
+    pub fn job_id(&self) -> Uuid {
+        self.job_id
+    }
+
+    pub fn set_status(&self, status: JobStatus) {
+        self.jobs.update(self.job_id, |job| job.status = status);
+    }
+
+    /// Records amounts resolved from the payload, before anything is sent
+    pub fn set_resolved_amounts(
+        &self,
+        token_address: Option<Address>,
+        amount: Option<FormattedAmount>,
+        receivers: &[(Address, U256)],
+    ) {
+        self.jobs.update(self.job_id, |job| {
+            job.token_address = token_address.map(|address| address.to_string());
+            job.amount = amount;
+        });
+
+        let mut connection = self.jobs.database.connection();
+        if let Err(e) = save_receivers(&mut connection, self.job_id, receivers) {
+            println!(
+                "->> {:<12} - failed to record receivers of job {}: {}",
+                "JOB", self.job_id, e
+            );
+        }
+    }
+
+    /// Adds a transaction executed outside of this backend, its hash is recorded later.
+    /// `safe_batch` is the exported file, kept so a replayed export returns it unchanged.
+    pub fn add_exported_tx(&self, kind: TxKind, chunk: TxChunk, safe_batch: String) {
+        self.jobs.update(self.job_id, |job| {
+            job.transactions.push(JobTransaction {
+                kind,
+                chunk: (chunk.count > 1).then_some(chunk),
+                status: TxStatus::Exported,
+                tx_hash: None,
+                replacements: vec![],
+                max_fee_per_gas: None,
+                max_priority_fee_per_gas: None,
+                reorgs: vec![],
+                block_number: None,
+                gas_used: None,
+                effective_gas_price: None,
+                receipt: None,
+                safe_batch: Some(safe_batch),
+                error: None,
+            })
+        });
+    }
+
+    /// Records `tx_hash` as the execution of the exported transaction at `position`, only if
+    /// it is still exported. Checked and written by the same statement, so of two concurrent
+    /// records of a batch only one succeeds, while records of other batches don't interfere.
+    pub fn claim_exported_tx(&self, position: usize, tx_hash: TxHash) -> Result<bool> {
+        let mut connection = self.jobs.database.connection();
+        let tx = connection.transaction()?;
+        let job_id = self.job_id.to_string();
+
+        let claimed = tx.execute(
+            "UPDATE job_transactions SET status = ?3, tx_hash = ?4
+             WHERE job_id = ?1 AND position = ?2 AND status = ?5",
+            params![
+                job_id,
+                position as i64,
+                to_db_enum(&TxStatus::Submitted)?,
+                tx_hash.to_string(),
+                to_db_enum(&TxStatus::Exported)?,
+            ],
+        )?;
+
+        if claimed != 1 {
+            return Ok(false);
+        }
+
+        let statuses = tx
+            .prepare("SELECT status FROM job_transactions WHERE job_id = ?1")?
+            .query_map([&job_id], |row| row.get::<_, String>(0))?
+            .map(|status| from_db_enum(&status?))
+            .collect::<Result<Vec<TxStatus>>>()?;
+
+        tx.execute(
+            "UPDATE jobs SET status = ?2, updated_at = ?3 WHERE id = ?1",
+            params![
+                job_id,
+                to_db_enum(&safe_export_status(&statuses))?,
+                unix_timestamp() as i64,
+            ],
+        )?;
+
+        tx.commit()?;
+
+        Ok(true)
+    }
+
+    pub fn tx_listener(&self, kind: TxKind) -> JobTxListener {
+        JobTxListener {
+            tracker: self.clone(),
+            kind,
+            chunk: None,
+            position: OnceLock::new(),
+        }
+    }
+
+    /// Chunk details are only recorded when the job was actually split
+    pub fn chunk_listener(&self, kind: TxKind, chunk: TxChunk) -> JobTxListener {
+        JobTxListener {
+            tracker: self.clone(),
+            kind,
+            chunk: (chunk.count > 1).then_some(chunk),
+            position: OnceLock::new(),
+        }
+    }
+
+    /// Listener of the exported transaction at `position`, once its execution is claimed
+    pub fn exported_tx_listener(&self, position: usize) -> Result<JobTxListener> {
+        let job = self
+            .jobs
+            .get(self.job_id)?
+            .ok_or_else(|| anyhow!("Job {} not found", self.job_id))?;
+        let tx = job
+            .transactions
+            .get(position)
+            .ok_or_else(|| anyhow!("Job {} has no transaction {}", self.job_id, position))?;
+
+        Ok(JobTxListener {
+            tracker: self.clone(),
+            kind: tx.kind,
+            chunk: tx.chunk,
+            position: OnceLock::from(position),
+        })
+    }
+
+    /// Marks the transaction of `listener` as confirmed or failed depending on `result`
+    pub fn settle_tx<T>(
+        &self,
+        listener: &JobTxListener,
+        result: anyhow::Result<T>,
+    ) -> Result<T, AppError> {
+        match result {
+            Ok(value) => {
+                self.jobs.update(self.job_id, |job| {
+                    if let Some(tx) = listener.transaction(job) {
+                        tx.status = TxStatus::Confirmed;
+                    }
+
+                    follow_safe_export(job);
+                });
+
+                Ok(value)
+            }
+            Err(e) => {
+                let error = AppError::from(e);
+                let response = ErrorResponse::from(&error);
+
+                self.jobs.update(self.job_id, |job| {
+                    match listener.transaction(job) {
+                        Some(tx) => {
+                            tx.status = TxStatus::Failed;
+                            tx.error = Some(response);
+                        }
+                        None => job.transactions.push(JobTransaction {
+                            kind: listener.kind,
+                            chunk: listener.chunk,
+                            status: TxStatus::Failed,
+                            tx_hash: None,
+                            replacements: vec![],
+                            max_fee_per_gas: None,
+                            max_priority_fee_per_gas: None,
+                            reorgs: vec![],
+                            block_number: None,
+                            gas_used: None,
+                            effective_gas_price: None,
+                            receipt: None,
+                            safe_batch: None,
+                            error: Some(response),
+                        }),
+                    }
+
+                    follow_safe_export(job);
+                });
+
+                Err(error)
+            }
+        }
+    }
+
+    pub fn finish(&self, result: Result<AppResponse, AppError>) {
+        self.jobs.update(self.job_id, |job| match result {
+            Ok(response) => {
+                job.status = JobStatus::Confirmed;
+                job.result = Some(response);
+            }
+            Err(error) => {
+                println!("->> {:<12} - job {} failed: {}", "JOB", job.id, error);
+
+                job.status = JobStatus::Failed;
+                job.error = Some(ErrorResponse::from(&error));
+            }
+        });
+    }
+}
+
+pub struct JobTxListener {
+    tracker: JobTracker,
+    kind: TxKind,
+    chunk: Option<TxChunk>,
+    /// Row of the transaction in the job, known once it is submitted
+    position: OnceLock<usize>,
+}
+
+impl JobTxListener {
+    fn transaction<'a>(&self, job: &'a mut Job) -> Option<&'a mut JobTransaction> {
+        job.transactions.get_mut(*self.position.get()?)
+    }
+}
+
+impl TxListener for JobTxListener {
+    fn on_submitted(&self, tx_hash: TxHash, fees: Option<FeeEstimate>) {
+        let kind = self.kind;
+        let chunk = self.chunk;
+        let max_fee_per_gas = fees.map(|fees| fees.max_fee_per_gas.to_string());
+        let max_priority_fee_per_gas = fees
+            .and_then(|fees| fees.max_priority_fee_per_gas)
+            .map(|fee| fee.to_string());
+
+        self.tracker.jobs.update(self.tracker.job_id, |job| {
+            let _ = self.position.set(job.transactions.len());
+
+            job.transactions.push(JobTransaction {
+                kind,
+                chunk,
+                status: TxStatus::Submitted,
+                tx_hash: Some(tx_hash.to_string()),
+                replacements: vec![],
+                max_fee_per_gas,
+                max_priority_fee_per_gas,
+                reorgs: vec![],
+                block_number: None,
+                gas_used: None,
+                effective_gas_price: None,
+                receipt: None,
+                safe_batch: None,
+                error: None,
+            });
+
+            set_progress(job, kind, JobStatus::Submitted);
+        });
+    }
+
+    fn on_replaced(
+        &self,
+        replacement: Replacement,
+        replaced_tx_hash: TxHash,
+        tx_hash: TxHash,
+        fees: FeeEstimate,
+    ) {
+        let replaced_tx_hash = replaced_tx_hash.to_string();
+
+        self.tracker.jobs.update(self.tracker.job_id, |job| {
+            let replaced = job
+                .transactions
+                .iter_mut()
+                .find(|tx| tx.tx_hash.as_ref() == Some(&replaced_tx_hash));
+
+            if let Some(tx) = replaced {
+                tx.tx_hash = Some(tx_hash.to_string());
+                tx.max_fee_per_gas = Some(fees.max_fee_per_gas.to_string());
+                tx.max_priority_fee_per_gas =
+                    fees.max_priority_fee_per_gas.map(|fee| fee.to_string());
+                tx.replacements.push(TxReplacement {
+                    kind: replacement,
+                    replaced_tx_hash,
+                    tx_hash: tx_hash.to_string(),
+                });
+            }
+        });
+    }
+
+    fn on_included(&self, receipt: &TransactionReceipt) {
+        let kind = self.kind;
+
+        self.tracker.jobs.update(self.tracker.job_id, |job| {
+            if let Some(tx) = self.transaction(job) {
+                tx.status = TxStatus::Included;
+                tx.tx_hash = Some(receipt.transaction_hash.to_string());
+                tx.block_number = receipt.block_number;
+            }
+
+            set_progress(job, kind, JobStatus::Included);
+        });
+    }
+
+    fn on_reorged(&self, tx_hash: TxHash, block_number: u64) {
+        let kind = self.kind;
+
+        self.tracker.jobs.update(self.tracker.job_id, |job| {
+            if let Some(tx) = self.transaction(job) {
+                tx.status = TxStatus::Submitted;
+                tx.block_number = None;
+                tx.reorgs.push(TxReorg {
+                    tx_hash: tx_hash.to_string(),
+                    block_number,
+                });
+            }
+
+            set_progress(job, kind, JobStatus::Submitted);
+        });
+    }
+
+    fn on_mined(&self, receipt: &TransactionReceipt) {
+        self.tracker.jobs.update(self.tracker.job_id, |job| {
+            if let Some(tx) = self.transaction(job) {
+                tx.tx_hash = Some(receipt.transaction_hash.to_string());
+                tx.block_number = receipt.block_number;
+                tx.gas_used = Some(receipt.gas_used.to_string());
+                tx.effective_gas_price = Some(receipt.effective_gas_price.to_string());
+                tx.receipt = serde_json::to_string(receipt).ok();
+            }
+        });
+    }
+}
+
+/// Status of a job while its transaction of `kind` progresses. Approvals leave it approving.
+fn set_progress(job: &mut Job, kind: TxKind, status: JobStatus) {
+    if is_safe_export(job) {
+        follow_safe_export(job);
+    } else if kind != TxKind::Approve {
+        job.status = status;
+    }
+}
+
+/// Batches of a Safe export are executed independently, the job follows all of them
+fn follow_safe_export(job: &mut Job) {
+    if is_safe_export(job) {
+        let statuses: Vec<TxStatus> = job.transactions.iter().map(|tx| tx.status).collect();
+
+        job.status = safe_export_status(&statuses);
+    }
+}
+
+fn is_safe_export(job: &Job) -> bool {
+    job.transactions.iter().any(|tx| tx.safe_batch.is_some())
+}
+
+/// The least advanced batch decides, a failed batch only fails the job once no other batch
+/// is left to execute or confirm
+fn safe_export_status(statuses: &[TxStatus]) -> JobStatus {
+    let any = |status: TxStatus| statuses.contains(&status);
+
+    if any(TxStatus::Exported) {
+        JobStatus::AwaitingExecution
+    } else if any(TxStatus::Submitted) {
+        JobStatus::Submitted
+    } else if any(TxStatus::Included) {
+        JobStatus::Included
+    } else if any(TxStatus::Failed) {
+        JobStatus::Failed
+    } else {
+        JobStatus::Confirmed
+    }
+}
+
+fn insert_job(connection: &Connection, job: &Job) -> Result<()> {
+    connection.execute(
+        "INSERT INTO jobs (id, kind, status, requested_by, payload, token_address, amount,
+                           result, error, created_at, updated_at, chain_id)
+         VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12)",
+        params![
+            job.id.to_string(),
+            to_db_enum(&job.kind)?,
+            to_db_enum(&job.status)?,
+            job.requested_by,
+            job.payload.to_string(),
+            job.token_address,
+            to_db_json(&job.amount)?,
+            to_db_json(&job.result)?,
+            to_db_json(&job.error)?,
+            job.created_at as i64,
+            job.updated_at as i64,
+            job.chain_id.map(|chain_id| chain_id as i64),
+        ],
+    )?;
+
+    Ok(())
+}
+
+/// Writes the job row and the transactions that differ from `before`
+fn save_job_state(connection: &mut Connection, job: &Job, before: &[JobTransaction]) -> Result<()> {
+    let tx = connection.transaction()?;
+    let job_id = job.id.to_string();
+
+    tx.execute(
+        "UPDATE jobs SET status = ?2, token_address = ?3, amount = ?4, result = ?5, error = ?6,
+                         updated_at = ?7
+         WHERE id = ?1",
+        params![
+            job_id,
+            to_db_enum(&job.status)?,
+            job.token_address,
+            to_db_json(&job.amount)?,
+            to_db_json(&job.result)?,
+            to_db_json(&job.error)?,
+            job.updated_at as i64,
+        ],
+    )?;
+
+    for (position, transaction) in job.transactions.iter().enumerate() {
+        if before.get(position) != Some(transaction) {
+            save_transaction(&tx, &job_id, position, transaction)?;
+        }
+    }
+
+    tx.commit()?;
+
+    Ok(())
+}
+
+fn save_transaction(
+    connection: &Connection,
+    job_id: &str,
+    position: usize,
+    transaction: &JobTransaction,
+) -> Result<()> {
+    connection.execute(
+        "INSERT OR REPLACE INTO job_transactions (job_id, position, kind, chunk, status, tx_hash,
+                                                  block_number, gas_used, effective_gas_price,
+                                                  receipt, error, replacements, max_fee_per_gas,
+                                                  max_priority_fee_per_gas, reorgs, safe_batch)
+         VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12, ?13, ?14, ?15, ?16)",
+        params![
+            job_id,
+            position as i64,
+            to_db_enum(&transaction.kind)?,
+            to_db_json(&transaction.chunk)?,
+            to_db_enum(&transaction.status)?,
+            transaction.tx_hash,
+            transaction.block_number.map(|block| block as i64),
+            transaction.gas_used,
+            transaction.effective_gas_price,
+            transaction.receipt,
+            to_db_json(&transaction.error)?,
+            serde_json::to_string(&transaction.replacements)?,
+            transaction.max_fee_per_gas,
+            transaction.max_priority_fee_per_gas,
+            serde_json::to_string(&transaction.reorgs)?,
+            transaction.safe_batch,
+        ],
+    )?;
+
+    Ok(())
+}
+
+fn save_receivers(
+    connection: &mut Connection,
+    job_id: Uuid,
+    receivers: &[(Address, U256)],
+) -> Result<()> {
+    let tx = connection.transaction()?;
+    let job_id = job_id.to_string();
+
+    tx.execute("DELETE FROM job_receivers WHERE job_id = ?1", [&job_id])?;
+    for (position, (address, amount)) in receivers.iter().enumerate() {
+        tx.execute(
+            "INSERT INTO job_receivers (job_id, position, address, amount)
+             VALUES (?1, ?2, ?3, ?4)",
+            params![
+                job_id,
+                position as i64,
+                address.to_string(),
+                amount.to_string()
+            ],
+        )?;
+    }
+
+    tx.commit()?;
+
+    Ok(())
+}
+
+fn load_job(connection: &Connection, job_id: Uuid) -> Result<Option<Job>> {
+    let Some(mut job) = load_job_state(connection, job_id)? else {
+        return Ok(None);
+    };
+
+    job.receivers = connection
+        .prepare("SELECT address, amount FROM job_receivers WHERE job_id = ?1 ORDER BY position")?
+        .query_map([job_id.to_string()], |row| {
+            Ok(JobReceiver {
+                address: row.get(0)?,
+                amount: row.get(1)?,
+            })
+        })?
+        .collect::<rusqlite::Result<Vec<_>>>()?;
+
+    Ok(Some(job))
+}
+
+/// Job with its transactions but without its receivers, which can be thousands
+fn load_job_state(connection: &Connection, job_id: Uuid) -> Result<Option<Job>> {
+    let id = job_id.to_string();
+
+    let job = connection
+        .query_row(
+            "SELECT kind, status, requested_by, payload, token_address, amount, result, error,
+                    created_at, updated_at, chain_id
+             FROM jobs WHERE id = ?1",
+            [&id],
+            |row| Ok(JobRow::from_row(row)),
+        )
+        .optional()?;
+
+    let Some(job) = job else {
+        return Ok(None);
+    };
+    let job = job?;
+
+    let transactions = connection
+        .prepare(
+            "SELECT kind, chunk, status, tx_hash, block_number, gas_used, effective_gas_price,
+                    receipt, error, replacements, max_fee_per_gas, max_priority_fee_per_gas,
+                    reorgs, safe_batch
+             FROM job_transactions WHERE job_id = ?1 ORDER BY position",
+        )?
+        .query_map([&id], |row| Ok(transaction_from_row(row)))?
+        .map(|row| row?)
+        .collect::<Result<Vec<_>>>()?;
+
+    Ok(Some(Job {
+        id: job_id,
+        kind: from_db_enum(&job.kind)?,
+        chain_id: job.chain_id.map(|chain_id| chain_id as u64),
+        status: from_db_enum(&job.status)?,
+        requested_by: job.requested_by,
+        payload: serde_json::from_str(&job.payload)?,
+        token_address: job.token_address,
+        amount: from_db_json(job.amount)?,
+        receivers: vec![],
+        transactions,
+        result: from_db_json(job.result)?,
+        error: from_db_json(job.error)?,
+        created_at: job.created_at as u64,
+        updated_at: job.updated_at as u64,
+    }))
+}
+
+fn transaction_from_row(row: &Row) -> Result<JobTransaction> {
+    Ok(JobTransaction {
+        kind: from_db_enum(&row.get::<_, String>(0)?)?,
+        chunk: from_db_json(row.get(1)?)?,
+        status: from_db_enum(&row.get::<_, String>(2)?)?,
+        tx_hash: row.get(3)?,
+        replacements: from_db_json(row.get(9)?)?.unwrap_or_default(),
+        max_fee_per_gas: row.get(10)?,
+        max_priority_fee_per_gas: row.get(11)?,
+        reorgs: from_db_json(row.get(12)?)?.unwrap_or_default(),
+        block_number: row.get::<_, Option<i64>>(4)?.map(|block| block as u64),
+        gas_used: row.get(5)?,
+        effective_gas_price: row.get(6)?,
+        receipt: row.get(7)?,
+        safe_batch: row.get(13)?,
+        error: from_db_json(row.get(8)?)?,
+    })
+}
+
+struct JobRow {
+    kind: String,
+    status: String,
+    requested_by: Option<String>,
+    payload: String,
+    token_address: Option<String>,
+    amount: Option<String>,
+    result: Option<String>,
+    error: Option<String>,
+    created_at: i64,
+    updated_at: i64,
+    chain_id: Option<i64>,
+}
+
+impl JobRow {
+    fn from_row(row: &Row) -> Result<Self> {
+        Ok(Self {
+            kind: row.get(0)?,
+            status: row.get(1)?,
+            requested_by: row.get(2)?,
+            payload: row.get(3)?,
+            token_address: row.get(4)?,
+            amount: row.get(5)?,
+            result: row.get(6)?,
+            error: row.get(7)?,
+            created_at: row.get(8)?,
+            updated_at: row.get(9)?,
+            chain_id: row.get(10)?,
+        })
+    }
+}
+
+/// Enums are stored by their serde name, e.g. `distribute_native`
+fn to_db_enum<T: Serialize>(value: &T) -> Result<String> {
+    match serde_json::to_value(value)? {
+        serde_json::Value::String(name) => Ok(name),
+        other => Err(anyhow!("Expected enum name, got {}", other)),
+    }
+}
+
+fn from_db_enum<T: for<'de> Deserialize<'de>>(name: &str) -> Result<T> {
+    Ok(serde_json::from_value(serde_json::Value::String(
+        name.to_string(),
+    ))?)
+}
+
+fn to_db_json<T: Serialize>(value: &Option<T>) -> Result<Option<String>> {
+    Ok(match value {
+        Some(value) => Some(serde_json::to_string(value)?),
+        None => None,
+    })
+}
+
+fn from_db_json<T: for<'de> Deserialize<'de>>(value: Option<String>) -> Result<Option<T>> {
+    Ok(match value {
+        Some(value) => Some(serde_json::from_str(&value)?),
+        None => None,
+    })
+}
+
+pub fn normalize_address(value: &str) -> Result<String> {
+    Ok(Address::from_str(value.trim())
+        .map_err(|e| AppError::validation(format!("Invalid address '{}': {}", value, e)))?
+        .to_string())
+}
+
+/// SQLite reads a date it doesn't understand as NULL, which would silently match no job
+fn check_date(connection: &Connection, field: &str, value: &str) -> Result<()> {
+    let valid: bool =
+        connection.query_row("SELECT strftime('%s', ?1) IS NOT NULL", [value], |row| {
+            row.get(0)
+        })?;
+
+    if !valid {
+        bail!(AppError::Validation {
+            code: "INVALID_DATE",
+            message: format!(
+                "{} '{}' is not a date, expected YYYY-MM-DD or a datetime like 2024-05-01T12:00:00",
+                field, value
+            ),
+        });
+    }
+
+    Ok(())
+}
+
+fn unix_timestamp() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|duration| duration.as_secs())
+        .unwrap_or_default()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn job_service() -> JobService {
+        JobService::new(Database::open(":memory:").unwrap())
+    }
+
+    fn history(from_date: Option<&str>, to_date: Option<&str>) -> Result<Vec<Job>> {
+        job_service().history(&HistoryFilter {
+            from_date: from_date.map(str::to_string),
+            to_date: to_date.map(str::to_string),
+            ..Default::default()
+        })
+    }
+
+    #[test]
+    fn history_accepts_dates_and_datetimes() {
+        assert!(history(Some("2024-05-01"), Some("2024-05-31")).is_ok());
+        assert!(history(Some("2024-05-01T12:00:00"), None).is_ok());
+    }
+
+    #[test]
+    fn history_refuses_dates_it_can_not_read() {
+        for (from_date, to_date) in [(Some("yesterday"), None), (None, Some("31/05/2024"))] {
+            let error = history(from_date, to_date).unwrap_err();
+
+            assert_eq!(error.downcast::<AppError>().unwrap().code(), "INVALID_DATE");
+        }
+    }
+
+    fn safe_export(batches: usize) -> JobTracker {
+        let JobCreation::Created(tracker) = job_service()
+            .create(
+                JobKind::DistributeErc20,
+                1,
+                JobOrigin::default(),
+                serde_json::json!({}),
+            )
+            .unwrap()
+        else {
+            panic!("job was replayed");
+        };
+
+        for index in 0..batches {
+            let chunk = TxChunk {
+                index,
+                count: batches,
+                offset: index,
+                receivers: 1,
+            };
+
+            tracker.add_exported_tx(TxKind::Distribute, chunk, "{}".to_string());
+        }
+        tracker.set_status(JobStatus::AwaitingExecution);
+
+        tracker
+    }
+
+    fn statuses(tracker: &JobTracker) -> (JobStatus, Vec<TxStatus>) {
+        let job = tracker.jobs.get(tracker.job_id).unwrap().unwrap();
+
+        (
+            job.status,
+            job.transactions.iter().map(|tx| tx.status).collect(),
+        )
+    }
+
+    #[test]
+    fn batches_of_one_export_are_recorded_concurrently() {
+        let tracker = safe_export(2);
+
+        let claims: Vec<bool> = std::thread::scope(|scope| {
+            let claims: Vec<_> = (0..2)
+                .map(|position| {
+                    let tracker = tracker.clone();
+                    scope.spawn(move || {
+                        let tx_hash = TxHash::with_last_byte(position as u8 + 1);
+
+                        tracker.claim_exported_tx(position, tx_hash).unwrap()
+                    })
+                })
+                .collect();
+
+            claims
+                .into_iter()
+                .map(|claim| claim.join().unwrap())
+                .collect()
+        });
+
+        assert_eq!(claims, [true, true]);
+        assert_eq!(
+            statuses(&tracker),
+            (
+                JobStatus::Submitted,
+                vec![TxStatus::Submitted, TxStatus::Submitted]
+            )
+        );
+
+        // A second record of a batch loses, whatever the other batches do
+        assert!(!tracker
+            .claim_exported_tx(1, TxHash::with_last_byte(3))
+            .unwrap());
+    }
+
+    #[test]
+    fn batches_settle_their_own_rows_and_the_job_follows_all_of_them() {
+        let tracker = safe_export(3);
+        tracker
+            .claim_exported_tx(0, TxHash::with_last_byte(1))
+            .unwrap();
+        tracker
+            .claim_exported_tx(1, TxHash::with_last_byte(2))
+            .unwrap();
+
+        let failed = tracker.exported_tx_listener(1).unwrap();
+        let _ = tracker.settle_tx::<()>(&failed, Err(anyhow!("reverted")));
+
+        assert_eq!(
+            statuses(&tracker),
+            (
+                JobStatus::AwaitingExecution,
+                vec![TxStatus::Submitted, TxStatus::Failed, TxStatus::Exported]
+            )
+        );
+
+        let confirmed = tracker.exported_tx_listener(0).unwrap();
+        tracker.settle_tx(&confirmed, Ok(())).unwrap();
+        tracker
+            .claim_exported_tx(2, TxHash::with_last_byte(3))
+            .unwrap();
+
+        assert_eq!(statuses(&tracker).0, JobStatus::Submitted);
+
+        let last = tracker.exported_tx_listener(2).unwrap();
+        tracker.settle_tx(&last, Ok(())).unwrap();
+
+        assert_eq!(
+            statuses(&tracker),
+            (
+                JobStatus::Failed,
+                vec![TxStatus::Confirmed, TxStatus::Failed, TxStatus::Confirmed]
+            )
+        );
+    }
+
+    #[test]
+    fn transaction_updates_keep_the_receivers_and_other_transactions() {
+        let tracker = safe_export(2);
+        let receivers: Vec<(Address, U256)> = (1..=3)
+            .map(|index| (Address::with_last_byte(index), U256::from(index)))
+            .collect();
+        tracker.set_resolved_amounts(None, None, &receivers);
+
+        tracker
+            .claim_exported_tx(1, TxHash::with_last_byte(1))
+            .unwrap();
+        let listener = tracker.exported_tx_listener(1).unwrap();
+        tracker.settle_tx(&listener, Ok(())).unwrap();
+
+        let job = tracker.jobs.get(tracker.job_id).unwrap().unwrap();
+
+        assert_eq!(
+            job.receivers
+                .iter()
+                .map(|receiver| receiver.amount.as_str())
+                .collect::<Vec<_>>(),
+            ["1", "2", "3"]
+        );
+        assert_eq!(job.transactions[0].status, TxStatus::Exported);
+        assert_eq!(job.transactions[0].safe_batch.as_deref(), Some("{}"));
+        assert_eq!(job.transactions[1].status, TxStatus::Confirmed);
+    }
+}
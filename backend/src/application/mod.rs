@@ -1,3 +1,7 @@
 pub mod action_service;
+pub mod chain_registry;
 pub mod erc20_service;
+pub mod event_indexer;
+pub mod job_service;
 pub mod token_manager_service;
+pub mod validation;
@@ -1,18 +1,186 @@
-use crate::shared::contracts::TokenManager::TokenManagerInstance;
-use crate::shared::execute_call::execute_call;
+use crate::shared::app_error::AppError;
+use crate::shared::contracts::Safe;
+use crate::shared::contracts::TokenManager::{self, TokenManagerCalls, TokenManagerInstance};
+use crate::shared::contracts::ERC20;
+use crate::shared::execute_call::{
+    build_call, execute_call, settle_receipt, submit_signed, FeeEstimate, TxListener,
+};
 use crate::shared::signed_provider::SignedProvider;
-use alloy::primitives::{Address, TxHash, U256};
-use alloy::pubsub::PubSubFrontend;
-use anyhow::Result;
+use crate::shared::submitter::{Replacement, Submitter};
+use crate::shared::token_manager_math;
+use alloy::consensus::TxEnvelope;
+use alloy::contract::Error as ContractError;
+use alloy::eips::BlockNumberOrTag;
+use alloy::primitives::{Address, Bytes, TxHash, B256, U256};
+use alloy::providers::{Provider, WalletProvider};
+use alloy::rpc::types::{Log, TransactionReceipt, TransactionRequest};
+use alloy::sol_types::{SolCall, SolEvent, SolInterface};
+use alloy::transports::{BoxTransport, RpcError, Transport};
+use anyhow::{bail, Result};
+use serde::Deserialize;
+use serde_json::json;
+use std::future::Future;
+use std::ops::Range;
+
+/// Gas bound of an ERC20 distribution that can't be simulated yet: the call itself, and each
+/// receiver paid by a `transferFrom` into an empty balance with its `Distributed` log
+const UNSIMULATED_BASE_GAS: u128 = 60_000;
+const UNSIMULATED_GAS_PER_RECEIVER: u128 = 40_000;
+
+/// Call of a `callTracer` trace, only what is needed to follow native value transfers
+#[derive(Debug, Deserialize)]
+struct CallFrame {
+    from: Address,
+    #[serde(default)]
+    to: Option<Address>,
+    #[serde(default)]
+    value: Option<U256>,
+    #[serde(default)]
+    error: Option<String>,
+    #[serde(default)]
+    calls: Vec<CallFrame>,
+}
+
+/// Part of a distribution small enough to fit into one transaction
+#[derive(Debug, Clone)]
+pub struct DistributionChunk {
+    pub receivers: Vec<Address>,
+    pub proportions: Vec<U256>,
+    pub total_amount: U256,
+}
+
+/// Call of an exported Safe batch, the transaction executing the batch must show its logs
+#[derive(Debug, Clone)]
+pub struct SafeCall {
+    pub to: Address,
+    pub data: Bytes,
+}
+
+/// Part of a collection small enough to fit into one transaction
+#[derive(Debug, Clone)]
+pub struct CollectionChunk {
+    pub froms: Vec<Address>,
+    pub scaled_percents: Vec<U256>,
+}
 
 #[derive(Clone)]
-pub struct TokenManagerService {
-    contract: TokenManagerInstance<PubSubFrontend, SignedProvider>,
+pub struct TokenManagerService<T: Transport + Clone = BoxTransport> {
+    contract: TokenManagerInstance<T, SignedProvider<T>>,
+    /// Account every call is sent and simulated from
+    sender: Address,
+    max_gas_per_tx: u128,
+    submitter: Submitter,
 }
 
-impl TokenManagerService {
-    pub fn new(contract: TokenManagerInstance<PubSubFrontend, SignedProvider>) -> Self {
-        Self { contract }
+impl<T> TokenManagerService<T>
+where
+    T: Transport + Clone,
+{
+    pub fn new(
+        contract: TokenManagerInstance<T, SignedProvider<T>>,
+        max_gas_per_tx: u128,
+        submitter: Submitter,
+    ) -> Self {
+        Self {
+            sender: contract.provider().default_signer_address(),
+            contract,
+            max_gas_per_tx,
+            submitter,
+        }
+    }
+
+    /// Same service sending from another account registered in the wallet
+    pub fn with_sender(&self, sender: Address) -> Self {
+        Self {
+            sender,
+            ..self.clone()
+        }
+    }
+
+    /// Splits a distribution into chunks that fit the gas limit.
+    /// A distribution that fits is returned untouched as a single chunk. Otherwise every
+    /// receiver's exact amount from the whole distribution becomes its proportion in a chunk,
+    /// so `amount * chunkTotal / chunkTotal` pays exactly what one transaction would have paid.
+    pub async fn plan_distribution(
+        &self,
+        token_address: Option<Address>,
+        receivers: Vec<Address>,
+        proportions: Vec<U256>,
+        total_amount: U256,
+    ) -> Result<Vec<DistributionChunk>> {
+        let max_gas = self.max_gas().await?;
+
+        let whole = DistributionChunk {
+            receivers,
+            proportions,
+            total_amount,
+        };
+
+        if self
+            .distribution_fits(token_address, whole.clone(), max_gas)
+            .await?
+        {
+            return Ok(vec![whole]);
+        }
+
+        let entries = exact_amount_entries(whole)?;
+
+        let ranges = fitting_ranges(entries.len(), max_gas, |range| {
+            self.distribution_fits(token_address, entries_chunk(&entries[range]), max_gas)
+        })
+        .await?;
+
+        println!(
+            "->> plan_distribution. {} receivers split into {} chunks",
+            entries.len(),
+            ranges.len()
+        );
+
+        Ok(ranges
+            .into_iter()
+            .map(|range| entries_chunk(&entries[range]))
+            .collect())
+    }
+
+    /// Splits a collection into chunks that fit the gas limit.
+    /// Percentages are applied per wallet, so chunks need no adjustment.
+    pub async fn plan_collection(
+        &self,
+        token_address: Address,
+        froms: Vec<Address>,
+        scaled_percents: Vec<U256>,
+    ) -> Result<Vec<CollectionChunk>> {
+        let max_gas = self.max_gas().await?;
+
+        let whole = CollectionChunk {
+            froms,
+            scaled_percents,
+        };
+
+        if self
+            .collection_fits(token_address, whole.clone(), max_gas)
+            .await?
+        {
+            return Ok(vec![whole]);
+        }
+
+        let to_chunk = |range: Range<usize>| CollectionChunk {
+            froms: whole.froms[range.clone()].to_vec(),
+            scaled_percents: whole.scaled_percents[range].to_vec(),
+        };
+
+        let ranges = fitting_ranges(whole.froms.len(), max_gas, |range| {
+            self.collection_fits(token_address, to_chunk(range), max_gas)
+        })
+        .await?;
+
+        println!(
+            "->> plan_collection. {} wallets split into {} chunks",
+            whole.froms.len(),
+            ranges.len()
+        );
+
+        Ok(ranges.into_iter().map(to_chunk).collect())
     }
 
     pub async fn distribute_native_tokens(
@@ -20,13 +188,20 @@ impl TokenManagerService {
         receivers: Vec<Address>,
         proportions: Vec<U256>,
         total_amount: U256,
-    ) -> Result<TxHash> {
+        listener: &dyn TxListener,
+    ) -> Result<TransactionReceipt> {
         let template = self
             .contract
             .distributeNativeTokens(receivers, proportions, total_amount)
             .value(total_amount);
 
-        Ok(execute_call(template, "distribute_native_tokens").await?)
+        execute_call(
+            template.from(self.sender),
+            &self.submitter,
+            "distribute_native_tokens",
+            listener,
+        )
+        .await
     }
 
     pub async fn distribute_erc20_tokens(
@@ -35,7 +210,8 @@ impl TokenManagerService {
         receivers: Vec<Address>,
         proportions: Vec<U256>,
         total_amount: U256,
-    ) -> Result<TxHash> {
+        listener: &dyn TxListener,
+    ) -> Result<TransactionReceipt> {
         let template = self.contract.distributeERC20Tokens(
             token_address,
             receivers,
@@ -43,7 +219,13 @@ impl TokenManagerService {
             total_amount,
         );
 
-        Ok(execute_call(template, "distribute_erc20_tokens").await?)
+        execute_call(
+            template.from(self.sender),
+            &self.submitter,
+            "distribute_erc20_tokens",
+            listener,
+        )
+        .await
     }
 
     pub async fn collect_erc20_tokens(
@@ -51,15 +233,1119 @@ impl TokenManagerService {
         token_address: Address,
         froms: Vec<Address>,
         scaled_percents: Vec<U256>,
-    ) -> Result<TxHash> {
+        listener: &dyn TxListener,
+    ) -> Result<TransactionReceipt> {
         let template = self
             .contract
             .collectERC20Tokens(token_address, froms, scaled_percents);
 
-        Ok(execute_call(template, "collect_erc20_tokens").await?)
+        execute_call(
+            template.from(self.sender),
+            &self.submitter,
+            "collect_erc20_tokens",
+            listener,
+        )
+        .await
+    }
+
+    /// Unsigned transaction of one distribution chunk with its simulated gas
+    pub async fn build_distribution(
+        &self,
+        token_address: Option<Address>,
+        chunk: &DistributionChunk,
+    ) -> Result<TransactionRequest> {
+        match token_address {
+            Some(token_address) => {
+                let template = self.contract.distributeERC20Tokens(
+                    token_address,
+                    chunk.receivers.clone(),
+                    chunk.proportions.clone(),
+                    chunk.total_amount,
+                );
+
+                build_call(
+                    template.from(self.sender),
+                    &self.submitter.gas,
+                    "distribute_erc20_tokens",
+                )
+                .await
+            }
+            None => {
+                let template = self
+                    .contract
+                    .distributeNativeTokens(
+                        chunk.receivers.clone(),
+                        chunk.proportions.clone(),
+                        chunk.total_amount,
+                    )
+                    .value(chunk.total_amount);
+
+                build_call(
+                    template.from(self.sender),
+                    &self.submitter.gas,
+                    "distribute_native_tokens",
+                )
+                .await
+            }
+        }
+    }
+
+    /// Unsigned transactions of an ERC20 distribution that reverts until its approval is mined,
+    /// so it can't be simulated. It is split by the gas bound of its receivers instead, which
+    /// is also the gas limit of each chunk, only the gas actually used is paid.
+    pub async fn build_unsimulated_distribution(
+        &self,
+        token_address: Address,
+        receivers: Vec<Address>,
+        proportions: Vec<U256>,
+        total_amount: U256,
+    ) -> Result<Vec<(DistributionChunk, TransactionRequest)>> {
+        let max_gas = self.max_gas().await?;
+
+        let chunks = split_distribution(
+            receivers,
+            proportions,
+            total_amount,
+            unsimulated_chunk_size(max_gas),
+        )?;
+
+        Ok(chunks
+            .into_iter()
+            .map(|chunk| {
+                let tx = self
+                    .contract
+                    .distributeERC20Tokens(
+                        token_address,
+                        chunk.receivers.clone(),
+                        chunk.proportions.clone(),
+                        chunk.total_amount,
+                    )
+                    .from(self.sender)
+                    .gas(unsimulated_distribution_gas(chunk.receivers.len()))
+                    .into_transaction_request();
+
+                (chunk, tx)
+            })
+            .collect())
+    }
+
+    /// Unsigned transaction of one collection chunk with its simulated gas
+    pub async fn build_collection(
+        &self,
+        token_address: Address,
+        chunk: &CollectionChunk,
+    ) -> Result<TransactionRequest> {
+        let template = self.contract.collectERC20Tokens(
+            token_address,
+            chunk.froms.clone(),
+            chunk.scaled_percents.clone(),
+        );
+
+        build_call(
+            template.from(self.sender),
+            &self.submitter.gas,
+            "collect_erc20_tokens",
+        )
+        .await
+    }
+
+    /// Broadcasts a transaction signed outside of this process and waits for its receipt
+    pub async fn submit_signed(
+        &self,
+        envelope: &TxEnvelope,
+        listener: &dyn TxListener,
+    ) -> Result<TransactionReceipt> {
+        let result = submit_signed(
+            self.contract.provider(),
+            envelope,
+            "submit_signed",
+            self.submitter.confirmations,
+            listener,
+        )
+        .await;
+
+        // The signer may be one of our accounts, whose cached nonce is stale now
+        self.submitter
+            .nonces
+            .resync(envelope.recover_signer()?)
+            .await;
+
+        result
+    }
+
+    /// Sends a new version of a transaction still waited for, with the same nonce and higher fees
+    pub async fn replace(
+        &self,
+        tx_hash: TxHash,
+        replacement: Replacement,
+    ) -> Result<(TxHash, FeeEstimate)> {
+        let Some(tx) = self.submitter.in_flight.get(tx_hash) else {
+            bail!(AppError::Conflict {
+                code: "NOT_REPLACEABLE",
+                message: format!(
+                    "Transaction {} is not waiting for its receipt in this process",
+                    tx_hash
+                ),
+            });
+        };
+
+        self.submitter
+            .replace(self.contract.provider(), &tx, replacement)
+            .await
+    }
+
+    /// Refuses hashes that are unknown to the node, not mined yet or not executing `calls`
+    /// through `safe`, before anything is recorded for them. Only the logs of the Safe decide,
+    /// so executions sent by relayers, modules or through MultiSend are accepted as well.
+    pub async fn check_safe_transaction(
+        &self,
+        safe: Address,
+        tx_hash: TxHash,
+        calls: &[SafeCall],
+    ) -> Result<()> {
+        let provider = self.contract.provider();
+
+        let Some(receipt) = provider.get_transaction_receipt(tx_hash).await? else {
+            if provider.get_transaction_by_hash(tx_hash).await?.is_some() {
+                bail!(AppError::Conflict {
+                    code: "TRANSACTION_NOT_MINED",
+                    message: format!(
+                        "Transaction {} is not mined yet, record it once it is",
+                        tx_hash
+                    ),
+                });
+            }
+
+            bail!(AppError::NotFound(format!(
+                "Transaction {} is not known to the node",
+                tx_hash
+            )));
+        };
+
+        self.match_safe_execution(&receipt, safe, calls)
+    }
+
+    /// Waits for a Safe transaction executed outside of this backend. Its logs are matched
+    /// against `calls` again, the confirmed receipt may come from another block after a reorg.
+    pub async fn confirm_safe_execution(
+        &self,
+        safe: Address,
+        tx_hash: TxHash,
+        calls: &[SafeCall],
+        listener: &dyn TxListener,
+    ) -> Result<TransactionReceipt> {
+        let receipt = settle_receipt(
+            self.contract.provider(),
+            tx_hash,
+            "confirm_safe_execution",
+            self.submitter.confirmations,
+            listener,
+        )
+        .await?;
+
+        self.match_safe_execution(&receipt, safe, calls)?;
+
+        Ok(receipt)
+    }
+
+    /// Executing the Safe transaction succeeds even when the batch inside it fails, so the
+    /// Safe's own event decides whether it ran, then the logs of the batch whether it is ours
+    fn match_safe_execution(
+        &self,
+        receipt: &TransactionReceipt,
+        safe: Address,
+        calls: &[SafeCall],
+    ) -> Result<()> {
+        let tx_hash = receipt.transaction_hash;
+        let logs = receipt.inner.logs();
+
+        let emitted = |signatures: [B256; 2]| {
+            logs.iter().any(|log| {
+                log.address() == safe
+                    && log
+                        .topics()
+                        .first()
+                        .is_some_and(|topic| signatures.contains(topic))
+            })
+        };
+
+        if !emitted([
+            Safe::ExecutionSuccess::SIGNATURE_HASH,
+            Safe::ExecutionFromModuleSuccess::SIGNATURE_HASH,
+        ]) {
+            if emitted([
+                Safe::ExecutionFailure::SIGNATURE_HASH,
+                Safe::ExecutionFromModuleFailure::SIGNATURE_HASH,
+            ]) {
+                bail!(AppError::Revert {
+                    code: "SAFE_EXECUTION_FAILED",
+                    message: format!("Safe {} did not execute the batch of {}", safe, tx_hash),
+                });
+            }
+
+            bail!(AppError::Validation {
+                code: "NOT_A_SAFE_EXECUTION",
+                message: format!(
+                    "Transaction {} is not an execution of Safe {}",
+                    tx_hash, safe
+                ),
+            });
+        }
+
+        if let Err(mismatch) = match_safe_batch(logs, safe, self.get_token_manager_address(), calls)
+        {
+            bail!(AppError::Validation {
+                code: "SAFE_BATCH_MISMATCH",
+                message: format!(
+                    "Transaction {} does not execute the exported batch: {}",
+                    tx_hash, mismatch
+                ),
+            });
+        }
+
+        Ok(())
+    }
+
+    /// Nonce the next transaction of the sender gets, counting its pending transactions
+    pub async fn pending_nonce(&self) -> Result<u64> {
+        Ok(self
+            .contract
+            .provider()
+            .get_transaction_count(self.sender)
+            .pending()
+            .await?)
+    }
+
+    pub fn sender(&self) -> Address {
+        self.sender
+    }
+
+    /// Fees the gas policy of this chain would send a transaction with now
+    pub async fn estimate_fees(&self) -> Result<FeeEstimate> {
+        self.submitter.gas.fees(self.contract.provider()).await
     }
 
     pub fn get_token_manager_address(&self) -> Address {
-        self.contract.address().clone()
+        *self.contract.address()
+    }
+
+    /// Native value the TokenManager sent per receiver inside `tx_hash`, read from a
+    /// `callTracer` trace. `None` when the node doesn't serve `debug_traceTransaction`.
+    pub async fn native_transfers(&self, tx_hash: TxHash) -> Option<Vec<(Address, U256)>> {
+        let trace: Result<CallFrame, _> = self
+            .contract
+            .provider()
+            .raw_request(
+                "debug_traceTransaction".into(),
+                (tx_hash, json!({ "tracer": "callTracer" })),
+            )
+            .await;
+
+        let trace = match trace {
+            Ok(trace) => trace,
+            Err(e) => {
+                println!("->> Trace of {} is not available: {}", tx_hash, e);
+                return None;
+            }
+        };
+
+        let mut transfers = vec![];
+        collect_value_transfers(&trace, self.get_token_manager_address(), &mut transfers);
+
+        Some(transfers)
+    }
+
+    /// Configured limit, but never above half of the current block gas limit
+    async fn max_gas(&self) -> Result<u128> {
+        let block = self
+            .contract
+            .provider()
+            .get_block_by_number(BlockNumberOrTag::Latest, false)
+            .await?;
+
+        Ok(match block {
+            Some(block) => self.max_gas_per_tx.min(block.header.gas_limit / 2),
+            None => self.max_gas_per_tx,
+        })
+    }
+
+    async fn distribution_fits(
+        &self,
+        token_address: Option<Address>,
+        chunk: DistributionChunk,
+        max_gas: u128,
+    ) -> Result<bool> {
+        let fits = fits_gas_limit(
+            self.estimate_distribution(token_address, &chunk, Some(max_gas))
+                .await,
+            max_gas,
+        )?;
+
+        if fits || chunk.receivers.len() > 1 {
+            return Ok(fits);
+        }
+
+        // A lone receiver refused at the cap is estimated again without it, so a revert surfaces
+        uncapped_estimate(
+            self.estimate_distribution(token_address, &chunk, None)
+                .await,
+        )
+    }
+
+    async fn estimate_distribution(
+        &self,
+        token_address: Option<Address>,
+        chunk: &DistributionChunk,
+        cap: Option<u128>,
+    ) -> alloy::contract::Result<u128> {
+        let estimate = match token_address {
+            Some(token_address) => {
+                let call = self
+                    .contract
+                    .distributeERC20Tokens(
+                        token_address,
+                        chunk.receivers.clone(),
+                        chunk.proportions.clone(),
+                        chunk.total_amount,
+                    )
+                    .from(self.sender);
+
+                match cap {
+                    Some(cap) => call.gas(cap).estimate_gas().await,
+                    None => call.estimate_gas().await,
+                }
+            }
+            None => {
+                let call = self
+                    .contract
+                    .distributeNativeTokens(
+                        chunk.receivers.clone(),
+                        chunk.proportions.clone(),
+                        chunk.total_amount,
+                    )
+                    .value(chunk.total_amount)
+                    .from(self.sender);
+
+                match cap {
+                    Some(cap) => call.gas(cap).estimate_gas().await,
+                    None => call.estimate_gas().await,
+                }
+            }
+        };
+
+        estimate.map(|gas| self.submitter.gas.gas_limit(gas))
+    }
+
+    async fn collection_fits(
+        &self,
+        token_address: Address,
+        chunk: CollectionChunk,
+        max_gas: u128,
+    ) -> Result<bool> {
+        let fits = fits_gas_limit(
+            self.estimate_collection(token_address, &chunk, Some(max_gas))
+                .await,
+            max_gas,
+        )?;
+
+        if fits || chunk.froms.len() > 1 {
+            return Ok(fits);
+        }
+
+        // A lone wallet refused at the cap is estimated again without it, so a revert surfaces
+        uncapped_estimate(self.estimate_collection(token_address, &chunk, None).await)
+    }
+
+    async fn estimate_collection(
+        &self,
+        token_address: Address,
+        chunk: &CollectionChunk,
+        cap: Option<u128>,
+    ) -> alloy::contract::Result<u128> {
+        let call = self
+            .contract
+            .collectERC20Tokens(
+                token_address,
+                chunk.froms.clone(),
+                chunk.scaled_percents.clone(),
+            )
+            .from(self.sender);
+
+        let estimate = match cap {
+            Some(cap) => call.gas(cap).estimate_gas().await,
+            None => call.estimate_gas().await,
+        };
+
+        estimate.map(|gas| self.submitter.gas.gas_limit(gas))
+    }
+}
+
+/// ERC20 `Transfer` logs of `token_address` in `receipt`. A distribution reports what each
+/// receiver got from `payer`, a `collection` what each wallet sent to `payer`.
+pub fn erc20_transfers(
+    receipt: &TransactionReceipt,
+    token_address: Address,
+    payer: Address,
+    collection: bool,
+) -> Vec<(Address, U256)> {
+    receipt
+        .inner
+        .logs()
+        .iter()
+        .filter(|log| log.address() == token_address)
+        // `Approval` logs of `transferFrom` share the layout of `Transfer`
+        .filter_map(decode_log::<ERC20::Transfer>)
+        .filter_map(|transfer| match collection {
+            false if transfer.from == payer => Some((transfer.to, transfer.value)),
+            true if transfer.to == payer => Some((transfer.from, transfer.value)),
+            _ => None,
+        })
+        .collect()
+}
+
+/// Compares the logs `safe` caused with the ones `calls` cause: the exact `Distributed` amounts,
+/// a `Collected` log from the wallets of each collection and an `Approval` for each approval.
+/// Collected amounts depend on balances at execution time, so only the wallets are compared.
+fn match_safe_batch(
+    logs: &[Log],
+    safe: Address,
+    token_manager: Address,
+    calls: &[SafeCall],
+) -> Result<(), String> {
+    let mut distributed: Vec<(Address, Address, U256)> = logs
+        .iter()
+        .filter(|log| log.address() == token_manager)
+        .filter_map(decode_log::<TokenManager::Distributed>)
+        .filter(|event| event.sender == safe)
+        .map(|event| (event.token, event.receiver, event.amount))
+        .collect();
+
+    let mut collected: Vec<(Address, Address)> = logs
+        .iter()
+        .filter(|log| log.address() == token_manager)
+        .filter_map(decode_log::<TokenManager::Collected>)
+        .filter(|event| event.collector == safe)
+        .map(|event| (event.token, event.wallet))
+        .collect();
+
+    let mut approvals: Vec<(Address, Address, U256)> = logs
+        .iter()
+        .filter_map(|log| Some((log.address(), decode_log::<ERC20::Approval>(log)?)))
+        .filter(|(_, event)| event.owner == safe)
+        .map(|(token, event)| (token, event.spender, event.value))
+        .collect();
+
+    for call in calls {
+        if call.to != token_manager {
+            let approve = ERC20::approveCall::abi_decode(&call.data, true)
+                .map_err(|_| format!("call to {} is not an approval", call.to))?;
+
+            if !take(&mut approvals, &(call.to, approve.spender, approve.value)) {
+                return Err(format!(
+                    "{} of token {} was not approved for {}",
+                    approve.value, call.to, approve.spender
+                ));
+            }
+
+            continue;
+        }
+
+        match TokenManagerCalls::abi_decode(&call.data, true).map_err(|e| e.to_string())? {
+            TokenManagerCalls::distributeNativeTokens(call) => expect_distributed(
+                &mut distributed,
+                Address::ZERO,
+                &call.receivers,
+                &call.proportions,
+                call.totalAmount,
+            )?,
+            TokenManagerCalls::distributeERC20Tokens(call) => expect_distributed(
+                &mut distributed,
+                call.tokenAddress,
+                &call.receivers,
+                &call.proportions,
+                call.totalAmount,
+            )?,
+            TokenManagerCalls::collectERC20Tokens(call) => {
+                let before = collected.len();
+                collected.retain(|(token, wallet)| {
+                    *token != call.tokenAddress || !call.wallets.contains(wallet)
+                });
+
+                // A collection that moved nothing can't be told apart from another transaction
+                if collected.len() == before {
+                    return Err(format!(
+                        "nothing of token {} was collected from the {} wallet(s)",
+                        call.tokenAddress,
+                        call.wallets.len()
+                    ));
+                }
+            }
+            _ => return Err("call is not a distribution or a collection".to_string()),
+        }
+    }
+
+    if let Some((token, receiver, amount)) = distributed.first() {
+        return Err(format!(
+            "{} of token {} was distributed to {} outside of the batch",
+            amount, token, receiver
+        ));
+    }
+
+    if let Some((token, wallet)) = collected.first() {
+        return Err(format!(
+            "token {} was collected from {} outside of the batch",
+            token, wallet
+        ));
+    }
+
+    Ok(())
+}
+
+/// `log_decode` doesn't look at the signature, so events sharing a layout like `Distributed`
+/// and `Collected` would decode as each other
+fn decode_log<E: SolEvent>(log: &Log) -> Option<E> {
+    if log.topic0() != Some(&E::SIGNATURE_HASH) {
+        return None;
+    }
+
+    log.log_decode::<E>().ok().map(|log| log.inner.data)
+}
+
+/// Takes the `Distributed` log of every receiver of one distribution call out of `distributed`
+fn expect_distributed(
+    distributed: &mut Vec<(Address, Address, U256)>,
+    token: Address,
+    receivers: &[Address],
+    proportions: &[U256],
+    total_amount: U256,
+) -> Result<(), String> {
+    let amounts = token_manager_math::distribution_amounts(total_amount, proportions)
+        .map_err(|e| e.to_string())?;
+
+    for (receiver, amount) in receivers.iter().zip(amounts) {
+        if !take(distributed, &(token, *receiver, amount)) {
+            return Err(format!(
+                "{} of token {} was not distributed to {}",
+                amount, token, receiver
+            ));
+        }
+    }
+
+    Ok(())
+}
+
+/// Removes the first occurrence of `item`, so a log only matches one expected call
+fn take<I: PartialEq>(items: &mut Vec<I>, item: &I) -> bool {
+    match items.iter().position(|candidate| candidate == item) {
+        Some(index) => {
+            items.remove(index);
+            true
+        }
+        None => false,
+    }
+}
+
+/// Splits a distribution into chunks of at most `chunk_size` receivers without asking the node,
+/// amounts are kept exact the same way `plan_distribution` keeps them
+pub fn split_distribution(
+    receivers: Vec<Address>,
+    proportions: Vec<U256>,
+    total_amount: U256,
+    chunk_size: usize,
+) -> Result<Vec<DistributionChunk>> {
+    let whole = DistributionChunk {
+        receivers,
+        proportions,
+        total_amount,
+    };
+
+    if whole.receivers.len() <= chunk_size {
+        return Ok(vec![whole]);
+    }
+
+    Ok(exact_amount_entries(whole)?
+        .chunks(chunk_size.max(1))
+        .map(entries_chunk)
+        .collect())
+}
+
+/// Gas limit of an unsimulated ERC20 distribution to `receivers` receivers
+pub fn unsimulated_distribution_gas(receivers: usize) -> u128 {
+    UNSIMULATED_BASE_GAS + UNSIMULATED_GAS_PER_RECEIVER * receivers as u128
+}
+
+/// Most receivers whose unsimulated distribution stays within `max_gas`, at least one
+fn unsimulated_chunk_size(max_gas: u128) -> usize {
+    (max_gas.saturating_sub(UNSIMULATED_BASE_GAS) / UNSIMULATED_GAS_PER_RECEIVER).max(1) as usize
+}
+
+/// Splits a collection into chunks of at most `chunk_size` wallets without asking the node
+pub fn split_collection(
+    froms: Vec<Address>,
+    scaled_percents: Vec<U256>,
+    chunk_size: usize,
+) -> Vec<CollectionChunk> {
+    froms
+        .chunks(chunk_size.max(1))
+        .zip(scaled_percents.chunks(chunk_size.max(1)))
+        .map(|(froms, scaled_percents)| CollectionChunk {
+            froms: froms.to_vec(),
+            scaled_percents: scaled_percents.to_vec(),
+        })
+        .collect()
+}
+
+/// Every receiver with its exact amount from the whole distribution
+fn exact_amount_entries(whole: DistributionChunk) -> Result<Vec<(Address, U256)>> {
+    let amounts = token_manager_math::distribution_amounts(whole.total_amount, &whole.proportions)?;
+
+    // Receivers getting nothing would only make a chunk revert with zero parts
+    Ok(whole
+        .receivers
+        .into_iter()
+        .zip(amounts)
+        .filter(|(_, amount)| !amount.is_zero())
+        .collect())
+}
+
+fn entries_chunk(entries: &[(Address, U256)]) -> DistributionChunk {
+    DistributionChunk {
+        receivers: entries.iter().map(|(receiver, _)| *receiver).collect(),
+        proportions: entries.iter().map(|(_, amount)| *amount).collect(),
+        total_amount: entries.iter().map(|(_, amount)| *amount).sum(),
+    }
+}
+
+/// Value sent by `sender` in calls that did not fail, nested calls included
+fn collect_value_transfers(
+    frame: &CallFrame,
+    sender: Address,
+    transfers: &mut Vec<(Address, U256)>,
+) {
+    if frame.error.is_some() {
+        return;
+    }
+
+    if let (Some(to), Some(value)) = (frame.to, frame.value) {
+        if frame.from == sender && !value.is_zero() {
+            transfers.push((to, value));
+        }
+    }
+
+    for call in &frame.calls {
+        collect_value_transfers(call, sender, transfers);
+    }
+}
+
+/// Cuts `len` items into consecutive ranges that each fit, halving a range that doesn't.
+/// Every range is estimated, one with costlier items is split again instead of sent as is.
+async fn fitting_ranges<F, Fut>(len: usize, max_gas: u128, mut fits: F) -> Result<Vec<Range<usize>>>
+where
+    F: FnMut(Range<usize>) -> Fut,
+    Fut: Future<Output = Result<bool>>,
+{
+    let mut ranges: Vec<Range<usize>> = vec![];
+    let mut size = len.div_ceil(2).max(1);
+    let mut start = 0;
+
+    while start < len {
+        let range = start..(start + size).min(len);
+
+        if fits(range.clone()).await? {
+            start = range.end;
+            ranges.push(range);
+        } else if range.len() == 1 {
+            bail!(gas_limit_error(max_gas));
+        } else {
+            size = range.len().div_ceil(2);
+        }
+    }
+
+    Ok(ranges)
+}
+
+/// Estimate made with `max_gas` as its gas cap. The node refusing it is the out-of-gas signal,
+/// whatever its message, only transport failures are errors here.
+fn fits_gas_limit(estimate: alloy::contract::Result<u128>, max_gas: u128) -> Result<bool> {
+    match estimate {
+        Ok(gas) => Ok(gas <= max_gas),
+        Err(ContractError::TransportError(RpcError::ErrorResp(_))) => Ok(false),
+        Err(e) => Err(AppError::from(anyhow::Error::from(e)).into()),
+    }
+}
+
+/// Estimate without a cap of a single item refused at it. Succeeding means it costs more
+/// than the limit, failing means the call itself is broken.
+fn uncapped_estimate(estimate: alloy::contract::Result<u128>) -> Result<bool> {
+    match estimate {
+        Ok(_) => Ok(false),
+        Err(e) => Err(AppError::from(anyhow::Error::from(e)).into()),
+    }
+}
+
+fn gas_limit_error(max_gas: u128) -> AppError {
+    AppError::Validation {
+        code: "GAS_LIMIT_EXCEEDED",
+        message: format!(
+            "A single receiver does not fit into the gas limit of {}",
+            max_gas
+        ),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use alloy::primitives::Log as PrimitiveLog;
+
+    fn addresses(count: usize) -> Vec<Address> {
+        (1..=count)
+            .map(|index| Address::with_last_byte(index as u8))
+            .collect()
+    }
+
+    fn units(values: &[u64]) -> Vec<U256> {
+        values.iter().map(|value| U256::from(*value)).collect()
+    }
+
+    /// What every receiver gets from every chunk, in the order they are paid
+    fn paid(chunks: &[DistributionChunk]) -> Vec<(Address, U256)> {
+        chunks
+            .iter()
+            .flat_map(|chunk| {
+                let amounts = token_manager_math::distribution_amounts(
+                    chunk.total_amount,
+                    &chunk.proportions,
+                )
+                .unwrap();
+
+                chunk.receivers.clone().into_iter().zip(amounts)
+            })
+            .collect()
+    }
+
+    #[test]
+    fn distribution_that_fits_stays_whole() {
+        let chunks =
+            split_distribution(addresses(3), units(&[1, 2, 3]), U256::from(600), 3).unwrap();
+
+        assert_eq!(chunks.len(), 1);
+        assert_eq!(chunks[0].proportions, units(&[1, 2, 3]));
+        assert_eq!(chunks[0].total_amount, U256::from(600));
+    }
+
+    #[test]
+    fn distribution_chunks_pay_exactly_what_one_transaction_would() {
+        let receivers = addresses(7);
+        let proportions = units(&[1, 2, 3, 4, 5, 6, 7]);
+        let total_amount = U256::from(1_000_003);
+
+        let whole: Vec<(Address, U256)> = receivers
+            .iter()
+            .copied()
+            .zip(token_manager_math::distribution_amounts(total_amount, &proportions).unwrap())
+            .collect();
+
+        let chunks = split_distribution(receivers, proportions, total_amount, 3).unwrap();
+
+        assert_eq!(
+            chunks
+                .iter()
+                .map(|chunk| chunk.receivers.len())
+                .collect::<Vec<_>>(),
+            [3, 3, 1]
+        );
+        assert_eq!(paid(&chunks), whole);
+    }
+
+    #[test]
+    fn distribution_chunks_skip_receivers_getting_nothing() {
+        // 1 of 1000 parts of 10 rounds down to zero, a chunk of only that receiver would revert
+        let chunks = split_distribution(addresses(2), units(&[999, 1]), U256::from(10), 1).unwrap();
+
+        assert_eq!(paid(&chunks), [(Address::with_last_byte(1), U256::from(9))]);
+    }
+
+    #[test]
+    fn zero_chunk_size_splits_per_receiver() {
+        let chunks = split_distribution(addresses(2), units(&[1, 1]), U256::from(10), 0).unwrap();
+
+        assert_eq!(chunks.len(), 2);
+    }
+
+    #[test]
+    fn unsimulated_chunks_stay_within_the_gas_limit() {
+        for max_gas in [1_000_000, 15_000_000, 30_000_000] {
+            let chunk_size = unsimulated_chunk_size(max_gas);
+
+            assert!(unsimulated_distribution_gas(chunk_size) <= max_gas);
+            assert!(unsimulated_distribution_gas(chunk_size + 1) > max_gas);
+        }
+
+        // A limit too low for one receiver still sends one per transaction
+        assert_eq!(unsimulated_chunk_size(50_000), 1);
+    }
+
+    #[test]
+    fn collection_chunks_cover_the_wallets_in_order() {
+        let froms = addresses(5);
+        let scaled_percents = units(&[10, 20, 30, 40, 50]);
+
+        let chunks = split_collection(froms.clone(), scaled_percents.clone(), 2);
+
+        assert_eq!(
+            chunks
+                .iter()
+                .map(|chunk| chunk.froms.len())
+                .collect::<Vec<_>>(),
+            [2, 2, 1]
+        );
+
+        let covered: Vec<(Address, U256)> = chunks
+            .iter()
+            .flat_map(|chunk| {
+                chunk
+                    .froms
+                    .clone()
+                    .into_iter()
+                    .zip(chunk.scaled_percents.clone())
+            })
+            .collect();
+
+        assert_eq!(
+            covered,
+            froms.into_iter().zip(scaled_percents).collect::<Vec<_>>()
+        );
+    }
+
+    /// Ranges `fitting_ranges` cuts items of these gas costs into
+    async fn ranges(costs: &[u128], max_gas: u128) -> Result<Vec<Range<usize>>> {
+        fitting_ranges(costs.len(), max_gas, |range| {
+            let gas: u128 = costs[range].iter().sum();
+            async move { Ok(gas <= max_gas) }
+        })
+        .await
+    }
+
+    #[tokio::test]
+    async fn ranges_cover_every_item_once_in_order() {
+        let costs = [10; 9];
+
+        let ranges = ranges(&costs, 30).await.unwrap();
+
+        assert_eq!(ranges, [0..3, 3..6, 6..9]);
+    }
+
+    #[tokio::test]
+    async fn every_range_is_estimated_and_costlier_ones_split_again() {
+        // The second half holds the costly items, a size that fit the first half doesn't fit there
+        let costs = [10, 10, 10, 10, 40, 40, 40, 40];
+
+        let ranges = ranges(&costs, 40).await.unwrap();
+
+        assert_eq!(ranges, [0..4, 4..5, 5..6, 6..7, 7..8]);
+    }
+
+    #[tokio::test]
+    async fn item_above_the_limit_is_refused() {
+        let error = ranges(&[10, 50, 10], 40).await.unwrap_err();
+
+        assert_eq!(
+            error.downcast::<AppError>().unwrap().code(),
+            "GAS_LIMIT_EXCEEDED"
+        );
+    }
+
+    fn node_error(message: &str) -> alloy::contract::Result<u128> {
+        let payload = json!({ "code": -32000, "message": message });
+
+        Err(ContractError::TransportError(RpcError::ErrorResp(
+            serde_json::from_value(payload).unwrap(),
+        )))
+    }
+
+    #[test]
+    fn estimates_refused_at_the_cap_do_not_fit() {
+        assert!(fits_gas_limit(Ok(100), 100).unwrap());
+        assert!(!fits_gas_limit(Ok(101), 100).unwrap());
+
+        // Whatever the node says, refusing at the cap means the call needs more
+        assert!(!fits_gas_limit(node_error("gas required exceeds allowance (100)"), 100).unwrap());
+        assert!(!fits_gas_limit(node_error("execution reverted"), 100).unwrap());
+
+        assert!(
+            fits_gas_limit(Err(ContractError::TransportError(RpcError::NullResp)), 100).is_err()
+        );
+    }
+
+    #[test]
+    fn lone_items_refused_without_a_cap_are_errors() {
+        assert!(!uncapped_estimate(Ok(150)).unwrap());
+
+        let error =
+            uncapped_estimate(node_error("execution reverted: Token transfer failed")).unwrap_err();
+
+        assert!(error.downcast::<AppError>().is_ok());
+    }
+
+    const SAFE: Address = Address::with_last_byte(0xaa);
+    const TOKEN_MANAGER: Address = Address::with_last_byte(0xbb);
+    const TOKEN: Address = Address::with_last_byte(0xcc);
+
+    fn log<E: SolEvent>(address: Address, event: &E) -> Log {
+        Log {
+            inner: PrimitiveLog {
+                address,
+                data: event.encode_log_data(),
+            },
+            ..Default::default()
+        }
+    }
+
+    fn distributed(receiver: Address, amount: u64) -> Log {
+        log(
+            TOKEN_MANAGER,
+            &TokenManager::Distributed {
+                sender: SAFE,
+                token: TOKEN,
+                receiver,
+                amount: U256::from(amount),
+            },
+        )
+    }
+
+    fn distribute_call(
+        receivers: Vec<Address>,
+        proportions: &[u64],
+        total_amount: u64,
+    ) -> SafeCall {
+        SafeCall {
+            to: TOKEN_MANAGER,
+            data: TokenManager::distributeERC20TokensCall {
+                tokenAddress: TOKEN,
+                receivers,
+                proportions: units(proportions),
+                totalAmount: U256::from(total_amount),
+            }
+            .abi_encode()
+            .into(),
+        }
+    }
+
+    fn collect_call(wallets: Vec<Address>) -> SafeCall {
+        SafeCall {
+            to: TOKEN_MANAGER,
+            data: TokenManager::collectERC20TokensCall {
+                tokenAddress: TOKEN,
+                percentages: vec![U256::from(100_000_000); wallets.len()],
+                wallets,
+            }
+            .abi_encode()
+            .into(),
+        }
+    }
+
+    fn matches(logs: &[Log], calls: &[SafeCall]) -> Result<(), String> {
+        match_safe_batch(logs, SAFE, TOKEN_MANAGER, calls)
+    }
+
+    #[test]
+    fn batch_matches_its_distributed_logs() {
+        let [alice, bob] = addresses(2).try_into().unwrap();
+        let calls = [distribute_call(vec![alice, bob], &[1, 3], 100)];
+
+        assert_eq!(
+            matches(&[distributed(bob, 75), distributed(alice, 25)], &calls),
+            Ok(())
+        );
+    }
+
+    #[test]
+    fn batch_does_not_match_other_amounts_or_receivers() {
+        let [alice, bob, carol] = addresses(3).try_into().unwrap();
+        let calls = [distribute_call(vec![alice, bob], &[1, 3], 100)];
+
+        assert!(matches(&[distributed(alice, 25), distributed(bob, 74)], &calls).is_err());
+        assert!(matches(&[distributed(alice, 25)], &calls).is_err());
+        assert!(matches(
+            &[
+                distributed(alice, 25),
+                distributed(bob, 75),
+                distributed(carol, 1)
+            ],
+            &calls
+        )
+        .is_err());
+    }
+
+    #[test]
+    fn batch_does_not_match_distributions_paid_by_someone_else() {
+        let [alice] = addresses(1).try_into().unwrap();
+        let calls = [distribute_call(vec![alice], &[1], 100)];
+
+        let paid_by_other = log(
+            TOKEN_MANAGER,
+            &TokenManager::Distributed {
+                sender: Address::with_last_byte(0xdd),
+                token: TOKEN,
+                receiver: alice,
+                amount: U256::from(100),
+            },
+        );
+
+        assert!(matches(&[paid_by_other], &calls).is_err());
+    }
+
+    #[test]
+    fn batch_matches_approvals() {
+        let [alice] = addresses(1).try_into().unwrap();
+        let approve = SafeCall {
+            to: TOKEN,
+            data: ERC20::approveCall {
+                spender: TOKEN_MANAGER,
+                value: U256::from(100),
+            }
+            .abi_encode()
+            .into(),
+        };
+        let calls = [approve, distribute_call(vec![alice], &[1], 100)];
+
+        let approval = log(
+            TOKEN,
+            &ERC20::Approval {
+                owner: SAFE,
+                spender: TOKEN_MANAGER,
+                value: U256::from(100),
+            },
+        );
+
+        assert_eq!(
+            matches(&[approval, distributed(alice, 100)], &calls),
+            Ok(())
+        );
+        assert!(matches(&[distributed(alice, 100)], &calls).is_err());
+    }
+
+    #[test]
+    fn collection_matches_the_wallets_it_collected_from() {
+        let [alice, bob, carol] = addresses(3).try_into().unwrap();
+        let calls = [collect_call(vec![alice, bob])];
+
+        let collected = |wallet| {
+            log(
+                TOKEN_MANAGER,
+                &TokenManager::Collected {
+                    collector: SAFE,
+                    token: TOKEN,
+                    wallet,
+                    amount: U256::from(1),
+                },
+            )
+        };
+
+        // Wallets without balance emit nothing
+        assert_eq!(matches(&[collected(bob)], &calls), Ok(()));
+        assert!(matches(&[], &calls).is_err());
+        assert!(matches(&[collected(alice), collected(carol)], &calls).is_err());
     }
 }
@@ -0,0 +1,354 @@
+use crate::api::routes_distribute::{AmountUnit, DistributeBasePayload};
+use crate::shared::app_error::{AppError, ValidationIssue};
+use crate::shared::token_manager_math;
+use crate::shared::units;
+use alloy::primitives::{Address, U256};
+use std::collections::HashMap;
+use std::str::FromStr;
+
+pub struct ValidatedDistribution {
+    pub receivers: Vec<Address>,
+    pub proportions: Vec<U256>,
+    pub amount: U256,
+}
+
+/// Checks everything that doesn't depend on token decimals, so a malformed payload is refused
+/// before anything is read from the chain. Decimal amounts are only checked to be positive numbers.
+pub fn check_distribution(
+    payload: &DistributeBasePayload,
+    token_manager_address: Address,
+) -> Result<(), AppError> {
+    validate(payload, token_manager_address, None).map(|_| ())
+}
+
+/// Checks a distribution payload without sending anything and reports every problem at once.
+/// `decimals` are only used to scale amounts passed in `AmountUnit::Decimal`.
+pub fn validate_distribution(
+    payload: &DistributeBasePayload,
+    token_manager_address: Address,
+    decimals: u8,
+) -> Result<ValidatedDistribution, AppError> {
+    validate(payload, token_manager_address, Some(decimals))?
+        .ok_or_else(|| AppError::Internal("Distribution amount was not scaled".to_string()))
+}
+
+/// `None` when the payload is valid, but its decimal amount can't be scaled without `decimals`
+fn validate(
+    payload: &DistributeBasePayload,
+    token_manager_address: Address,
+    decimals: Option<u8>,
+) -> Result<Option<ValidatedDistribution>, AppError> {
+    let mut issues: Vec<ValidationIssue> = vec![];
+
+    if payload.receivers_with_proportions.is_empty() {
+        issues.push(payload_issue(
+            "receivers_with_proportions",
+            "At least one receiver is required",
+        ));
+    }
+
+    let mut receivers: Vec<Address> = vec![];
+    let mut proportions: Vec<U256> = vec![];
+    let mut seen: HashMap<Address, usize> = HashMap::new();
+
+    for (index, set) in payload.receivers_with_proportions.iter().enumerate() {
+        match parse_address(&set.receiver) {
+            Ok(receiver) => {
+                if receiver.is_zero() {
+                    issues.push(issue(index, "receiver", "Receiver is the zero address"));
+                } else if receiver == token_manager_address {
+                    issues.push(issue(
+                        index,
+                        "receiver",
+                        "Receiver is the TokenManager contract itself",
+                    ));
+                } else if let Some(first_index) = seen.get(&receiver) {
+                    issues.push(issue(
+                        index,
+                        "receiver",
+                        &format!(
+                            "Duplicate receiver, already listed at index {}",
+                            first_index
+                        ),
+                    ));
+                } else {
+                    seen.insert(receiver, index);
+                }
+
+                receivers.push(receiver);
+            }
+            Err(message) => issues.push(issue(index, "receiver", &message)),
+        }
+
+        match U256::from_str(set.proportion.trim()) {
+            Ok(proportion) => proportions.push(proportion),
+            Err(e) => issues.push(issue(
+                index,
+                "proportion",
+                &format!("Invalid proportion '{}': {}", set.proportion, e),
+            )),
+        }
+    }
+
+    let parsed_amount = match (payload.unit, decimals) {
+        (AmountUnit::Base, _) => U256::from_str(payload.amount.trim()).map_err(|e| e.to_string()),
+        (AmountUnit::Decimal, Some(decimals)) => units::parse_units(&payload.amount, decimals),
+        // Scaled by its own fractional digits, enough to refuse malformed and zero amounts
+        (AmountUnit::Decimal, None) => {
+            units::parse_units(&payload.amount, fraction_digits(&payload.amount))
+        }
+    };
+    let scaled = payload.unit == AmountUnit::Base || decimals.is_some();
+
+    let amount = match parsed_amount {
+        Ok(amount) if amount.is_zero() => {
+            issues.push(payload_issue("amount", "Amount must be greater than zero"));
+            None
+        }
+        Ok(amount) => Some(amount),
+        Err(e) => {
+            issues.push(payload_issue(
+                "amount",
+                &format!("Invalid amount '{}': {}", payload.amount, e),
+            ));
+            None
+        }
+    };
+
+    if proportions.len() == payload.receivers_with_proportions.len() && !proportions.is_empty() {
+        match token_manager_math::total_parts(&proportions) {
+            Ok(total_parts) if total_parts.is_zero() => issues.push(payload_issue(
+                "proportion",
+                "Sum of proportions must be greater than zero",
+            )),
+            Ok(_) => {
+                // Overflow of the on-chain formula is only detectable with the final amount
+                if let Some(amount) = amount.filter(|_| scaled) {
+                    if let Err(e) = token_manager_math::distribution_amounts(amount, &proportions) {
+                        issues.push(payload_issue("amount", &e.to_string()));
+                    }
+                }
+            }
+            Err(e) => issues.push(payload_issue("proportion", &e.to_string())),
+        }
+    }
+
+    match amount {
+        Some(amount) if issues.is_empty() => Ok(scaled.then_some(ValidatedDistribution {
+            receivers,
+            proportions,
+            amount,
+        })),
+        _ => Err(AppError::InvalidPayload(issues)),
+    }
+}
+
+fn fraction_digits(value: &str) -> u8 {
+    match value.trim().split_once('.') {
+        Some((_, fraction_part)) => fraction_part.len().min(u8::MAX as usize) as u8,
+        None => 0,
+    }
+}
+
+/// Accepts lowercase/uppercase hex as is and enforces EIP-55 checksum on mixed case input
+fn parse_address(value: &str) -> Result<Address, String> {
+    let value = value.trim();
+    let hex_part = value.strip_prefix("0x").unwrap_or(value);
+
+    let is_mixed_case = hex_part.chars().any(|c| c.is_ascii_lowercase())
+        && hex_part.chars().any(|c| c.is_ascii_uppercase());
+
+    if !is_mixed_case {
+        return Address::from_str(value)
+            .map_err(|e| format!("Invalid hex address '{}': {}", value, e));
+    }
+
+    Address::parse_checksummed(value, None).map_err(|e| match Address::from_str(value) {
+        Ok(_) => format!("Invalid checksum of address '{}'", value),
+        Err(_) => format!("Invalid hex address '{}': {}", value, e),
+    })
+}
+
+fn issue(index: usize, field: &'static str, message: &str) -> ValidationIssue {
+    ValidationIssue {
+        index: Some(index),
+        field: field.to_string(),
+        message: message.to_string(),
+    }
+}
+
+fn payload_issue(field: &'static str, message: &str) -> ValidationIssue {
+    ValidationIssue {
+        index: None,
+        field: field.to_string(),
+        message: message.to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::api::routes_distribute::ReceiversWithProportions;
+
+    const TOKEN_MANAGER: &str = "0x5FbDB2315678afecb367f032d93F642f64180aa3";
+    const ALICE: &str = "0x70997970C51812dc3A010C7d01b50e0d17dc79C8";
+    const BOB: &str = "0x3C44CdDdB6a900fa2b585dd299e03d12FA4293BC";
+
+    fn payload(
+        receivers: &[(&str, &str)],
+        amount: &str,
+        unit: AmountUnit,
+    ) -> DistributeBasePayload {
+        DistributeBasePayload {
+            chain_id: 31337,
+            receivers_with_proportions: receivers
+                .iter()
+                .map(|(receiver, proportion)| ReceiversWithProportions {
+                    receiver: receiver.to_string(),
+                    proportion: proportion.to_string(),
+                })
+                .collect(),
+            amount: amount.to_string(),
+            unit,
+            from: None,
+            dry_run: false,
+        }
+    }
+
+    fn token_manager() -> Address {
+        TOKEN_MANAGER.parse().unwrap()
+    }
+
+    fn issues(payload: &DistributeBasePayload) -> Vec<(Option<usize>, String)> {
+        match validate_distribution(payload, token_manager(), 18) {
+            Err(AppError::InvalidPayload(issues)) => issues
+                .into_iter()
+                .map(|issue| (issue.index, issue.field))
+                .collect(),
+            Err(error) => panic!("unexpected error {}", error),
+            Ok(_) => panic!("payload was accepted"),
+        }
+    }
+
+    #[test]
+    fn accepts_a_valid_distribution() {
+        let payload = payload(&[(ALICE, "1"), (BOB, "3")], "1000", AmountUnit::Base);
+
+        let validated = validate_distribution(&payload, token_manager(), 18).unwrap();
+
+        assert_eq!(
+            validated.receivers,
+            [ALICE.parse::<Address>().unwrap(), BOB.parse().unwrap()]
+        );
+        assert_eq!(validated.proportions, [U256::from(1), U256::from(3)]);
+        assert_eq!(validated.amount, U256::from(1000));
+    }
+
+    #[test]
+    fn scales_decimal_amounts() {
+        let payload = payload(&[(ALICE, "1")], "1.5", AmountUnit::Decimal);
+
+        let validated = validate_distribution(&payload, token_manager(), 6).unwrap();
+
+        assert_eq!(validated.amount, U256::from(1_500_000));
+    }
+
+    #[test]
+    fn reports_duplicate_receivers_at_the_repeated_index() {
+        let payload = payload(
+            &[(ALICE, "1"), (BOB, "1"), (&ALICE.to_lowercase(), "1")],
+            "1000",
+            AmountUnit::Base,
+        );
+
+        assert_eq!(issues(&payload), [(Some(2), "receiver".to_string())]);
+    }
+
+    #[test]
+    fn reports_zero_amounts_receivers_and_proportions() {
+        let zero_address = Address::ZERO.to_string();
+        let payload = payload(
+            &[(&zero_address, "0"), (TOKEN_MANAGER, "0")],
+            "0",
+            AmountUnit::Base,
+        );
+
+        assert_eq!(
+            issues(&payload),
+            [
+                (Some(0), "receiver".to_string()),
+                (Some(1), "receiver".to_string()),
+                (None, "amount".to_string()),
+                (None, "proportion".to_string()),
+            ]
+        );
+    }
+
+    #[test]
+    fn reports_every_problem_at_once() {
+        let payload = payload(
+            &[("0x1234", "1"), (ALICE, "half"), (BOB, "-1")],
+            "1.5",
+            AmountUnit::Base,
+        );
+
+        assert_eq!(
+            issues(&payload),
+            [
+                (Some(0), "receiver".to_string()),
+                (Some(1), "proportion".to_string()),
+                (Some(2), "proportion".to_string()),
+                (None, "amount".to_string()),
+            ]
+        );
+    }
+
+    #[test]
+    fn sums_proportions_only_when_every_one_parsed() {
+        // Parsed proportions no longer line up with the receivers, their sum would be meaningless
+        let payload = payload(&[(ALICE, "0"), (BOB, "x")], "1000", AmountUnit::Base);
+
+        assert_eq!(issues(&payload), [(Some(1), "proportion".to_string())]);
+    }
+
+    #[test]
+    fn refuses_an_empty_distribution() {
+        let payload = payload(&[], "1000", AmountUnit::Base);
+
+        assert_eq!(
+            issues(&payload),
+            [(None, "receivers_with_proportions".to_string())]
+        );
+    }
+
+    #[test]
+    fn refuses_wrong_checksums() {
+        let payload = payload(
+            &[("0x70997970c51812dc3A010C7d01b50e0d17dc79C8", "1")],
+            "1000",
+            AmountUnit::Base,
+        );
+
+        assert_eq!(issues(&payload), [(Some(0), "receiver".to_string())]);
+    }
+
+    #[test]
+    fn checks_decimal_amounts_without_decimals() {
+        let valid = payload(&[(ALICE, "1")], "0.000001", AmountUnit::Decimal);
+        let zero = payload(&[(ALICE, "1")], "0.000", AmountUnit::Decimal);
+
+        assert!(check_distribution(&valid, token_manager()).is_ok());
+        assert!(check_distribution(&zero, token_manager()).is_err());
+    }
+
+    #[test]
+    fn refuses_more_fractional_digits_than_the_token_has() {
+        let payload = payload(&[(ALICE, "1")], "1.5", AmountUnit::Decimal);
+
+        assert!(check_distribution(&payload, token_manager()).is_ok());
+        assert!(matches!(
+            validate_distribution(&payload, token_manager(), 0),
+            Err(AppError::InvalidPayload(_))
+        ));
+    }
+}
@@ -0,0 +1,185 @@
+//! Stand-in for the external signer the backend uses with `source = "remote"`, meant for tests
+//! and local development only: it keeps the keys in memory and signs whatever it is asked to.
+//!
+//! Speaks JSON-RPC over HTTP (`--listen 127.0.0.1:9545`) or a Unix socket
+//! (`--socket /tmp/signer.sock`, one newline-delimited request per connection):
+//! - `eth_accounts` returns the addresses of the keys, the first one is the default
+//! - `eth_signTransaction` takes a transaction object and returns the signed raw transaction
+use alloy::eips::eip2718::Encodable2718;
+use alloy::network::{Ethereum, EthereumWallet, NetworkWallet};
+use alloy::primitives::Bytes;
+use alloy::rpc::types::TransactionRequest;
+use alloy::signers::local::PrivateKeySigner;
+use anyhow::{bail, Result};
+use axum::extract::State;
+use axum::routing::post;
+use axum::{Json, Router};
+use clap::Parser;
+use serde_json::{json, Value};
+use std::net::SocketAddr;
+use std::path::PathBuf;
+use std::sync::Arc;
+use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader};
+use tokio::net::UnixListener;
+
+#[derive(Debug, Parser)]
+struct Args {
+    /// Serve HTTP on this address
+    #[arg(long, required_unless_present = "socket", conflicts_with = "socket")]
+    listen: Option<SocketAddr>,
+
+    /// Serve on this Unix socket instead of HTTP
+    #[arg(long)]
+    socket: Option<PathBuf>,
+
+    /// Hex private keys to sign with, comma separated
+    #[arg(
+        long,
+        env = "STAND_IN_SIGNER_KEYS",
+        hide_env_values = true,
+        value_delimiter = ','
+    )]
+    keys: Vec<String>,
+}
+
+#[tokio::main]
+async fn main() -> Result<()> {
+    let args = Args::parse();
+
+    if args.keys.is_empty() {
+        bail!("No key given, set STAND_IN_SIGNER_KEYS or --keys");
+    }
+
+    let mut wallet = EthereumWallet::default();
+
+    for (index, key) in args.keys.iter().enumerate() {
+        let signer: PrivateKeySigner = key.trim().parse()?;
+
+        println!("->> stand_in_signer. Account {}", signer.address());
+
+        if index == 0 {
+            wallet.register_default_signer(signer);
+        } else {
+            wallet.register_signer(signer);
+        }
+    }
+
+    let wallet = Arc::new(wallet);
+
+    match (args.listen, args.socket) {
+        (_, Some(path)) => serve_unix(path, wallet).await,
+        (Some(addr), None) => serve_http(addr, wallet).await,
+        (None, None) => unreachable!("clap requires --listen or --socket"),
+    }
+}
+
+async fn serve_http(addr: SocketAddr, wallet: Arc<EthereumWallet>) -> Result<()> {
+    let routes = Router::new()
+        .route(
+            "/",
+            post(
+                |State(wallet): State<Arc<EthereumWallet>>, Json(request): Json<Value>| async move {
+                    Json(handle(&wallet, request).await)
+                },
+            ),
+        )
+        .with_state(wallet);
+
+    let listener = tokio::net::TcpListener::bind(addr).await?;
+
+    println!("->> stand_in_signer. Listening on http://{}", addr);
+
+    axum::serve(listener, routes).await?;
+
+    Ok(())
+}
+
+async fn serve_unix(path: PathBuf, wallet: Arc<EthereumWallet>) -> Result<()> {
+    // A socket left behind by a previous run would make bind fail
+    let _ = std::fs::remove_file(&path);
+
+    let listener = UnixListener::bind(&path)?;
+
+    println!(
+        "->> stand_in_signer. Listening on unix://{}",
+        path.display()
+    );
+
+    loop {
+        let (stream, _) = listener.accept().await?;
+        let wallet = wallet.clone();
+
+        tokio::spawn(async move {
+            let (reader, mut writer) = stream.into_split();
+
+            let mut line = String::new();
+            if BufReader::new(reader).read_line(&mut line).await.is_err() {
+                return;
+            }
+
+            let response = match serde_json::from_str(&line) {
+                Ok(request) => handle(&wallet, request).await,
+                Err(e) => error_response(Value::Null, -32700, e.to_string()),
+            };
+
+            let mut response = response.to_string();
+            response.push('\n');
+
+            let _ = writer.write_all(response.as_bytes()).await;
+        });
+    }
+}
+
+async fn handle(wallet: &EthereumWallet, request: Value) -> Value {
+    let id = request["id"].clone();
+
+    let result = match request["method"].as_str() {
+        Some("eth_accounts") => {
+            let default = NetworkWallet::<Ethereum>::default_signer_address(wallet);
+
+            let mut accounts = vec![default];
+            accounts.extend(
+                NetworkWallet::<Ethereum>::signer_addresses(wallet)
+                    .filter(|account| *account != default),
+            );
+
+            Ok(json!(accounts))
+        }
+        Some("eth_signTransaction") => sign_transaction(wallet, request["params"][0].clone()).await,
+        method => Err((-32601, format!("Method {:?} is not supported", method))),
+    };
+
+    match result {
+        Ok(result) => json!({ "jsonrpc": "2.0", "id": id, "result": result }),
+        Err((code, message)) => error_response(id, code, message),
+    }
+}
+
+async fn sign_transaction(wallet: &EthereumWallet, tx: Value) -> Result<Value, (i64, String)> {
+    let request: TransactionRequest =
+        serde_json::from_value(tx).map_err(|e| (-32602, e.to_string()))?;
+
+    let Some(from) = request.from else {
+        return Err((-32602, "Transaction has no from".to_string()));
+    };
+
+    if !NetworkWallet::<Ethereum>::has_signer_for(wallet, &from) {
+        return Err((-32000, format!("No key for {}", from)));
+    }
+
+    let envelope = NetworkWallet::<Ethereum>::sign_request(wallet, request)
+        .await
+        .map_err(|e| (-32000, e.to_string()))?;
+
+    println!(
+        "->> stand_in_signer. Signed {} from {}",
+        envelope.tx_hash(),
+        from
+    );
+
+    Ok(json!(Bytes::from(envelope.encoded_2718())))
+}
+
+fn error_response(id: Value, code: i64, message: String) -> Value {
+    json!({ "jsonrpc": "2.0", "id": id, "error": { "code": code, "message": message } })
+}
@@ -0,0 +1,75 @@
+use crate::shared::keystore::{encrypt_keystore, prompt_private_key};
+use alloy::signers::local::PrivateKeySigner;
+use anyhow::Result;
+use clap::{Args, Parser, Subcommand};
+use std::path::PathBuf;
+
+/// Distributes and collects native and ERC20 tokens through the TokenManager contract
+#[derive(Debug, Parser)]
+#[command(version)]
+pub struct Cli {
+    /// TOML config file, `./config.toml` is read when present and none is given
+    #[arg(long, env = "CONFIG_FILE", global = true)]
+    pub config: Option<PathBuf>,
+
+    /// Print the effective configuration with secrets redacted, then exit
+    #[arg(long)]
+    pub print_config: bool,
+
+    /// Runs the server when omitted
+    #[command(subcommand)]
+    pub command: Option<Command>,
+}
+
+#[derive(Debug, Subcommand)]
+pub enum Command {
+    /// Manage encrypted JSON keystores (Web3 Secret Storage) for the signer
+    #[command(subcommand)]
+    Keystore(KeystoreCommand),
+}
+
+#[derive(Debug, Subcommand)]
+pub enum KeystoreCommand {
+    /// Generate a random key and store it encrypted
+    New(KeystoreArgs),
+    /// Encrypt an existing private key, asked for on the terminal
+    Import(KeystoreArgs),
+}
+
+#[derive(Debug, Args)]
+pub struct KeystoreArgs {
+    /// Directory the keystore is written to
+    #[arg(long, default_value = "keystores")]
+    pub dir: PathBuf,
+
+    /// File name of the keystore, a random UUID when omitted
+    #[arg(long)]
+    pub name: Option<String>,
+
+    /// File holding the password, asked for on the terminal when omitted
+    #[arg(long)]
+    pub password_file: Option<PathBuf>,
+}
+
+pub fn run_keystore_command(command: KeystoreCommand) -> Result<()> {
+    let (signer, args) = match command {
+        KeystoreCommand::New(args) => (PrivateKeySigner::random(), args),
+        KeystoreCommand::Import(args) => (prompt_private_key()?, args),
+    };
+
+    let path = encrypt_keystore(
+        &signer,
+        &args.dir,
+        args.name.as_deref(),
+        args.password_file.as_deref(),
+    )?;
+
+    println!(
+        "->> Keystore of {} written to {}",
+        signer.address(),
+        path.display()
+    );
+    println!("->> Use it with [signer] source = \"keystore\" or KEYSTORE_PATH");
+
+    Ok(())
+}
@@ -1,78 +1,173 @@
 use crate::application::action_service::ActionService;
-use crate::application::erc20_service::Erc20Service;
-use crate::application::token_manager_service::TokenManagerService;
-use alloy::primitives::Address;
-use alloy::pubsub::PubSubFrontend;
+use crate::application::chain_registry::ChainRegistry;
+use crate::application::event_indexer::EventIndexer;
+use crate::application::job_service::{JobService, TxChunk, TxKind};
+use crate::cli::{run_keystore_command, Cli, Command};
+use alloy::rpc::types::TransactionRequest;
 use anyhow::Result;
-use axum::response::IntoResponse;
 use axum::Router;
-use serde::Serialize;
-use shared::contracts::TokenManager;
-use shared::contracts::TokenManager::TokenManagerInstance;
-use shared::signed_provider::{SignedProvider, Web3Provider};
-use std::str::FromStr;
-use std::sync::Arc;
+use clap::Parser;
+use serde::{Deserialize, Serialize};
+use shared::config::AppConfig;
+use shared::database::Database;
+use shared::signed_provider::Web3Provider;
+use shared::units::FormattedAmount;
+use std::net::SocketAddr;
 
 mod api;
 mod application;
+mod cli;
 mod shared;
 mod ui;
 
-#[derive(Clone)]
-pub struct AppState {
-    contract: Arc<TokenManagerInstance<PubSubFrontend, SignedProvider>>,
-}
-#[derive(Debug, Serialize)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct AppResponse {
     pub tx_hash_approve: Option<String>,
     pub tx_hash_distribute: Option<String>,
-    pub error: Option<String>,
+    /// Every distribute/collect transaction, more than one when the job was split into chunks
+    #[serde(default)]
+    pub tx_hashes_distribute: Vec<String>,
+    /// Distributed total, or the total a collection pulls
+    pub amount: Option<FormattedAmount>,
+    /// What the transactions actually moved, absent when it could not be read
+    #[serde(default)]
+    pub transfers: Option<TransferReport>,
 }
 
-impl AppState {
-    pub fn new(contract: Arc<TokenManagerInstance<PubSubFrontend, SignedProvider>>) -> Self {
-        Self { contract }
-    }
+/// Amounts moved per address next to the amounts the job asked for, read from the receipts'
+/// ERC20 `Transfer` logs or, for native distributions, from a call trace of each transaction
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TransferReport {
+    /// `logs` or `trace`
+    pub source: String,
+    /// Every requested address got exactly its amount and no other address appears
+    pub reconciled: bool,
+    pub transfers: Vec<AddressTransfer>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AddressTransfer {
+    /// Receiver of a distribution or wallet of a collection
+    pub address: String,
+    /// Absent for addresses the job did not ask for, e.g. the fee receiver of a token
+    pub requested: Option<FormattedAmount>,
+    pub transferred: FormattedAmount,
+    pub matches: bool,
+}
+
+/// Outcome of an action sent with `dry_run`, nothing is broadcast
+#[derive(Debug, Serialize)]
+pub struct DryRunResponse {
+    pub transactions: Vec<SimulatedTransaction>,
+    /// Sum of the gas estimates, an upper bound when a distribution waits for its approval
+    pub gas_estimate: String,
+    pub max_fee_per_gas: String,
+    pub max_priority_fee_per_gas: Option<String>,
+    /// Upper bound of the fee in native tokens, `gas_estimate * max_fee_per_gas`
+    pub max_fee: FormattedAmount,
+    /// Distributed total, or the total a collection pulls
+    pub amount: Option<FormattedAmount>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct SimulatedTransaction {
+    pub kind: TxKind,
+    pub chunk: Option<TxChunk>,
+    /// Simulated gas, or the gas bound of a distribution that can only be simulated once its
+    /// approval is mined
+    pub gas_estimate: Option<String>,
+}
+
+/// Unsigned transactions of an action for a signer outside of this process,
+/// in the order they have to be signed and sent back to `/broadcast`
+#[derive(Debug, Serialize)]
+pub struct BuildResponse {
+    pub chain_id: u64,
+    pub from: String,
+    pub transactions: Vec<UnsignedTransaction>,
+    /// Distributed total, or the total a collection pulls
+    pub amount: Option<FormattedAmount>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct UnsignedTransaction {
+    pub kind: TxKind,
+    pub chunk: Option<TxChunk>,
+    /// Transaction object as `eth_signTransaction` takes it, with nonce, gas, fees and chain id
+    pub tx: TransactionRequest,
 }
 
 #[tokio::main]
 async fn main() -> Result<()> {
+    // .env is optional once settings live in the config file
+    dotenvy::dotenv().ok();
+
+    let cli = Cli::parse();
+
+    if let Some(Command::Keystore(command)) = cli.command {
+        return run_keystore_command(command);
+    }
+
+    let config = AppConfig::load(cli.config.as_deref())?;
+
+    if cli.print_config {
+        print!("{}", config.to_redacted_toml()?);
+        return config.validate();
+    }
+
     println!("->> Starting application!");
-    dotenvy::dotenv()?;
+    config.validate()?;
 
-    let contract_address_str = dotenvy::var("TOKEN_MANAGER_ADDRESS")?;
+    let wallet = Web3Provider::wallet(&config.signer).await?;
 
-    let contract_address = Address::from_str(&contract_address_str)?;
-    let provider = Web3Provider::prepare_ws_signed().await?;
+    let chains = ChainRegistry::connect(
+        config.chains.clone(),
+        wallet,
+        &config.gas,
+        config.transactions.replacement_policy(),
+    )
+    .await?;
 
-    let token_manager_instance = TokenManager::new(contract_address, provider.clone());
+    let database = Database::open(&config.database_path)?;
 
-    let token_manager_service = TokenManagerService::new(token_manager_instance);
-    let erc20_service = Erc20Service::new(provider.clone());
+    let job_service = JobService::new(database.clone());
 
-    let routes_distribute = api::routes_distribute::routes(ActionService::new(
-        erc20_service.clone(),
-        token_manager_service.clone(),
-    ));
-    let routes_collect =
-        api::routes_collect::routes(ActionService::new(erc20_service, token_manager_service));
+    let event_indexer = EventIndexer::new(database);
+    event_indexer.spawn(&chains);
+
+    let action_service = ActionService::new(chains.clone(), job_service.clone());
+
+    let routes_distribute = api::routes_distribute::routes(action_service.clone());
+    let routes_collect = api::routes_collect::routes(action_service.clone());
+    let routes_broadcast = api::routes_broadcast::routes(action_service.clone());
+    let routes_safe = api::routes_safe::routes(action_service.clone());
+    let routes_jobs = api::routes_jobs::routes(job_service, action_service);
+    let routes_chains = api::routes_chains::routes(chains);
+    let routes_events = api::routes_events::routes(event_indexer);
 
     // build our application with a route
     let routes = Router::new()
         .merge(routes_distribute)
         .merge(routes_collect)
+        .merge(routes_broadcast)
+        .merge(routes_safe)
+        .merge(routes_jobs)
+        .merge(routes_chains)
+        .merge(routes_events)
         .merge(ui::routes_root());
 
-    let port = dotenvy::var("PORT").unwrap_or("5000".to_string());
-
-    let listener = tokio::net::TcpListener::bind(format!("127.0.0.1:{}", port)).await?;
+    let listener = tokio::net::TcpListener::bind(config.socket_addr()).await?;
 
     println!(
         "->> Application is running on {:?}!",
         listener.local_addr()?
     );
 
-    axum::serve(listener, routes).await?;
+    axum::serve(
+        listener,
+        routes.into_make_service_with_connect_info::<SocketAddr>(),
+    )
+    .await?;
 
     Ok(())
 }
@@ -0,0 +1,327 @@
+use crate::shared::contracts::TokenManager::TokenManagerErrors;
+use crate::shared::contracts::ERC20::ERC20Errors;
+use alloy::contract::Error as ContractError;
+use alloy::primitives::hex::FromHexError;
+use alloy::primitives::ruint::ParseError;
+use alloy::sol_types::{Revert, SolError};
+use alloy::transports::{RpcError, TransportError};
+use axum::http::StatusCode;
+use axum::response::{IntoResponse, Response};
+use axum::Json;
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+
+/// Errors returned by handlers. Every variant carries a stable `code`
+/// clients can branch on, the status code is derived from the variant.
+#[derive(Debug, Error)]
+pub enum AppError {
+    #[error("{message}")]
+    Validation { code: &'static str, message: String },
+    #[error("Payload has {} invalid field(s)", .0.len())]
+    InvalidPayload(Vec<ValidationIssue>),
+    #[error("{message}")]
+    InsufficientFunds { code: &'static str, message: String },
+    #[error("{message}")]
+    Revert { code: &'static str, message: String },
+    #[error("{0}")]
+    NotFound(String),
+    #[error("{message}")]
+    Conflict { code: &'static str, message: String },
+    #[error("{message}")]
+    Timeout { code: &'static str, message: String },
+    #[error("{0}")]
+    Rpc(String),
+    #[error("{0}")]
+    Internal(String),
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ValidationIssue {
+    /// Position in the payload list, absent for payload-level problems
+    pub index: Option<usize>,
+    pub field: String,
+    pub message: String,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ErrorResponse {
+    pub code: String,
+    pub message: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub issues: Option<Vec<ValidationIssue>>,
+}
+
+impl AppError {
+    pub fn validation(message: impl Into<String>) -> Self {
+        Self::Validation {
+            code: "VALIDATION_ERROR",
+            message: message.into(),
+        }
+    }
+
+    pub fn status_code(&self) -> StatusCode {
+        match self {
+            AppError::Validation { .. } => StatusCode::BAD_REQUEST,
+            AppError::InvalidPayload(_) => StatusCode::BAD_REQUEST,
+            AppError::InsufficientFunds { .. } => StatusCode::UNPROCESSABLE_ENTITY,
+            AppError::Revert { .. } => StatusCode::UNPROCESSABLE_ENTITY,
+            AppError::NotFound(_) => StatusCode::NOT_FOUND,
+            AppError::Conflict { .. } => StatusCode::CONFLICT,
+            AppError::Timeout { .. } => StatusCode::GATEWAY_TIMEOUT,
+            AppError::Rpc(_) => StatusCode::BAD_GATEWAY,
+            AppError::Internal(_) => StatusCode::INTERNAL_SERVER_ERROR,
+        }
+    }
+
+    pub fn code(&self) -> &'static str {
+        match self {
+            AppError::Validation { code, .. } => code,
+            AppError::InvalidPayload(_) => "INVALID_PAYLOAD",
+            AppError::InsufficientFunds { code, .. } => code,
+            AppError::Revert { code, .. } => code,
+            AppError::NotFound(_) => "NOT_FOUND",
+            AppError::Conflict { code, .. } => code,
+            AppError::Timeout { code, .. } => code,
+            AppError::Rpc(_) => "RPC_ERROR",
+            AppError::Internal(_) => "INTERNAL_ERROR",
+        }
+    }
+
+    /// Tries to decode revert data of a failed call into TokenManager or ERC20 errors
+    fn from_transport_error(error: &TransportError) -> Self {
+        let RpcError::ErrorResp(payload) = error else {
+            return AppError::Rpc(error.to_string());
+        };
+
+        if let Some(decoded) = payload.as_decoded_error::<TokenManagerErrors>(true) {
+            return Self::from_token_manager_error(decoded);
+        }
+
+        if let Some(decoded) = payload.as_decoded_error::<ERC20Errors>(true) {
+            return Self::from_erc20_error(decoded);
+        }
+
+        if let Some(data) = payload.as_revert_data() {
+            if let Ok(revert) = Revert::abi_decode(&data, true) {
+                return Self::from_revert_reason(&revert.reason);
+            }
+
+            return AppError::Revert {
+                code: "EXECUTION_REVERTED",
+                message: payload.message.clone(),
+            };
+        }
+
+        if payload.message.contains("insufficient funds") {
+            return AppError::InsufficientFunds {
+                code: "INSUFFICIENT_FUNDS_FOR_GAS",
+                message: payload.message.clone(),
+            };
+        }
+
+        AppError::Rpc(payload.message.clone())
+    }
+
+    fn from_token_manager_error(error: TokenManagerErrors) -> Self {
+        let (code, message) = match error {
+            TokenManagerErrors::InvalidLengthOfWalletsOrParts(_) => (
+                "INVALID_LENGTH_OF_WALLETS_OR_PARTS",
+                "Wallets and parts have different length",
+            ),
+            TokenManagerErrors::InvalidWalletsLength(_) => {
+                ("INVALID_WALLETS_LENGTH", "Wallets list is empty")
+            }
+            TokenManagerErrors::ZeroSpentAmount(_) => ("ZERO_SPENT_AMOUNT", "Spent amount is zero"),
+            TokenManagerErrors::InsufficientSpentAmount(_) => {
+                return AppError::InsufficientFunds {
+                    code: "INSUFFICIENT_SPENT_AMOUNT",
+                    message: "Sender balance is lower than the total amount".to_string(),
+                }
+            }
+            TokenManagerErrors::InvalidsPartsQuantity(_) => {
+                ("INVALID_PARTS_QUANTITY", "Sum of parts is zero")
+            }
+            TokenManagerErrors::InvalidsSpentQuantity(_) => {
+                ("INVALID_SPENT_QUANTITY", "Invalid spent quantity")
+            }
+            TokenManagerErrors::TooEarly(_) => ("TOO_EARLY", "Too early"),
+            TokenManagerErrors::ReentrancyGuardReentrantCall(_) => {
+                ("REENTRANT_CALL", "Reentrant call")
+            }
+        };
+
+        AppError::Revert {
+            code,
+            message: message.to_string(),
+        }
+    }
+
+    fn from_erc20_error(error: ERC20Errors) -> Self {
+        match error {
+            ERC20Errors::ERC20InsufficientBalance(e) => AppError::InsufficientFunds {
+                code: "ERC20_INSUFFICIENT_BALANCE",
+                message: format!(
+                    "{} has balance {}, but {} is needed",
+                    e.sender, e.balance, e.needed
+                ),
+            },
+            ERC20Errors::ERC20InsufficientAllowance(e) => AppError::Revert {
+                code: "ERC20_INSUFFICIENT_ALLOWANCE",
+                message: format!(
+                    "{} has allowance {}, but {} is needed",
+                    e.spender, e.allowance, e.needed
+                ),
+            },
+            other => AppError::Revert {
+                code: "ERC20_ERROR",
+                message: format!("{:?}", other),
+            },
+        }
+    }
+
+    fn from_revert_reason(reason: &str) -> Self {
+        let code = match reason {
+            "Token transfer failed" => "TOKEN_TRANSFER_FAILED",
+            "Native token transfer failed" => "NATIVE_TOKEN_TRANSFER_FAILED",
+            _ => "EXECUTION_REVERTED",
+        };
+
+        AppError::Revert {
+            code,
+            message: reason.to_string(),
+        }
+    }
+}
+
+impl From<anyhow::Error> for AppError {
+    fn from(error: anyhow::Error) -> Self {
+        let error = match error.downcast::<AppError>() {
+            Ok(app_error) => return app_error,
+            Err(error) => error,
+        };
+
+        if let Some(ContractError::TransportError(transport_error)) =
+            error.downcast_ref::<ContractError>()
+        {
+            return Self::from_transport_error(transport_error);
+        }
+
+        if let Some(transport_error) = error.downcast_ref::<TransportError>() {
+            return Self::from_transport_error(transport_error);
+        }
+
+        if error.downcast_ref::<FromHexError>().is_some()
+            || error.downcast_ref::<ParseError>().is_some()
+        {
+            return AppError::validation(error.to_string());
+        }
+
+        AppError::Internal(error.to_string())
+    }
+}
+
+impl From<FromHexError> for AppError {
+    fn from(error: FromHexError) -> Self {
+        AppError::validation(format!("Invalid hex: {}", error))
+    }
+}
+
+impl From<ParseError> for AppError {
+    fn from(error: ParseError) -> Self {
+        AppError::validation(format!("Invalid number: {}", error))
+    }
+}
+
+impl From<&AppError> for ErrorResponse {
+    fn from(error: &AppError) -> Self {
+        let issues = match error {
+            AppError::InvalidPayload(issues) => Some(issues.clone()),
+            _ => None,
+        };
+
+        ErrorResponse {
+            code: error.code().to_string(),
+            message: error.to_string(),
+            issues,
+        }
+    }
+}
+
+impl IntoResponse for AppError {
+    fn into_response(self) -> Response {
+        println!("->> {:<12} - {} {}", "ERROR", self.code(), self);
+
+        (self.status_code(), Json(ErrorResponse::from(&self))).into_response()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::shared::contracts::{TokenManager, ERC20};
+    use alloy::primitives::{hex, Address, U256};
+    use serde_json::json;
+
+    /// Node response to `eth_call` or `eth_estimateGas` reverting with `data`
+    fn reverted(data: Vec<u8>) -> AppError {
+        let payload = json!({
+            "code": 3,
+            "message": "execution reverted",
+            "data": hex::encode_prefixed(data),
+        });
+
+        AppError::from_transport_error(&RpcError::ErrorResp(
+            serde_json::from_value(payload).unwrap(),
+        ))
+    }
+
+    #[test]
+    fn decodes_token_manager_errors() {
+        let error = reverted(TokenManager::ZeroSpentAmount {}.abi_encode());
+        assert_eq!(error.code(), "ZERO_SPENT_AMOUNT");
+        assert_eq!(error.status_code(), StatusCode::UNPROCESSABLE_ENTITY);
+
+        let error = reverted(TokenManager::InsufficientSpentAmount {}.abi_encode());
+        assert!(matches!(error, AppError::InsufficientFunds { .. }));
+        assert_eq!(error.code(), "INSUFFICIENT_SPENT_AMOUNT");
+    }
+
+    #[test]
+    fn decodes_erc20_errors_with_their_arguments() {
+        let error = reverted(
+            ERC20::ERC20InsufficientAllowance {
+                spender: Address::with_last_byte(1),
+                allowance: U256::from(5),
+                needed: U256::from(7),
+            }
+            .abi_encode(),
+        );
+
+        assert_eq!(error.code(), "ERC20_INSUFFICIENT_ALLOWANCE");
+        assert_eq!(
+            error.to_string(),
+            format!(
+                "{} has allowance 5, but 7 is needed",
+                Address::with_last_byte(1)
+            )
+        );
+    }
+
+    #[test]
+    fn decodes_revert_reasons() {
+        let error = reverted(Revert::from("Token transfer failed").abi_encode());
+        assert_eq!(error.code(), "TOKEN_TRANSFER_FAILED");
+
+        let error = reverted(Revert::from("Ownable: caller is not the owner").abi_encode());
+        assert_eq!(error.code(), "EXECUTION_REVERTED");
+        assert_eq!(error.to_string(), "Ownable: caller is not the owner");
+    }
+
+    #[test]
+    fn unknown_revert_data_keeps_the_node_message() {
+        let error = reverted(vec![0xde, 0xad, 0xbe, 0xef]);
+
+        assert_eq!(error.code(), "EXECUTION_REVERTED");
+        assert_eq!(error.to_string(), "execution reverted");
+    }
+}
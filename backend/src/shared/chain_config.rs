@@ -0,0 +1,77 @@
+use crate::shared::gas_policy::FeeMode;
+use alloy::primitives::Address;
+use serde::{Deserialize, Serialize};
+
+/// Connection details of one chain the backend sends transactions to
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ChainConfig {
+    /// Checked against the node on start, taken from the node when omitted
+    #[serde(default)]
+    pub chain_id: Option<u64>,
+    #[serde(default)]
+    pub name: Option<String>,
+    pub rpc_url: String,
+    pub token_manager_address: Address,
+    #[serde(default = "default_native_symbol")]
+    pub native_symbol: String,
+    /// Blocks on top of the one including a transaction before it counts as confirmed,
+    /// 0 confirms it as soon as it is included
+    #[serde(default = "default_confirmations")]
+    pub confirmations: u64,
+    #[serde(default)]
+    pub gas: GasOverrides,
+    /// Block the event indexer starts from, usually the TokenManager deployment.
+    /// Events of this chain are not indexed when unset.
+    #[serde(default)]
+    pub events_start_block: Option<u64>,
+}
+
+/// Fee settings of `[gas]` replaced for one chain, unset fields keep the global value
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct GasOverrides {
+    #[serde(default)]
+    pub fee_mode: Option<FeeMode>,
+    #[serde(default)]
+    pub fee_multiplier: Option<f64>,
+    #[serde(default)]
+    pub max_fee_per_gas_gwei: Option<f64>,
+    #[serde(default)]
+    pub max_priority_fee_per_gas_gwei: Option<f64>,
+    #[serde(default)]
+    pub fee_ceiling_gwei: Option<f64>,
+}
+
+impl ChainConfig {
+    /// Chain described only by `RPC_URL` and `TOKEN_MANAGER_ADDRESS`
+    pub fn legacy(rpc_url: String, token_manager_address: Address) -> Self {
+        Self {
+            chain_id: None,
+            name: None,
+            rpc_url,
+            token_manager_address,
+            native_symbol: default_native_symbol(),
+            confirmations: default_confirmations(),
+            gas: GasOverrides::default(),
+            events_start_block: None,
+        }
+    }
+
+    /// Name used in configuration errors
+    pub fn label(&self, index: usize) -> String {
+        match (&self.name, self.chain_id) {
+            (Some(name), _) => format!("chains[{}] ({})", index, name),
+            (None, Some(chain_id)) => format!("chains[{}] (chain {})", index, chain_id),
+            (None, None) => format!("chains[{}]", index),
+        }
+    }
+}
+
+fn default_native_symbol() -> String {
+    "ETH".to_string()
+}
+
+fn default_confirmations() -> u64 {
+    1
+}
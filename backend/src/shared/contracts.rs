@@ -15,3 +15,16 @@ sol!(
     ERC20,
     "../foundry/out/ERC20.sol/ERC20.json"
 );
+
+// Only the events telling whether the Safe ran the batch, it doesn't revert when the batch fails.
+// Owners execute through `execTransaction`, modules through `execTransactionFromModule`.
+sol!(
+    #[allow(missing_docs)]
+    #[derive(Debug)]
+    interface Safe {
+        event ExecutionSuccess(bytes32 txHash, uint256 payment);
+        event ExecutionFailure(bytes32 txHash, uint256 payment);
+        event ExecutionFromModuleSuccess(address indexed module);
+        event ExecutionFromModuleFailure(address indexed module);
+    }
+);
@@ -0,0 +1,144 @@
+use anyhow::Result;
+use rusqlite::Connection;
+use std::sync::{Arc, Mutex, MutexGuard};
+
+/// Schema migrations, applied in order. Index + 1 is stored in `PRAGMA user_version`,
+/// so already applied entries must never be edited, only new ones appended.
+const MIGRATIONS: &[&str] = &[
+    r#"
+    CREATE TABLE jobs (
+        id TEXT PRIMARY KEY,
+        kind TEXT NOT NULL,
+        status TEXT NOT NULL,
+        requested_by TEXT,
+        payload TEXT NOT NULL,
+        token_address TEXT,
+        amount TEXT,
+        result TEXT,
+        error TEXT,
+        created_at INTEGER NOT NULL,
+        updated_at INTEGER NOT NULL
+    );
+    CREATE INDEX jobs_token_address ON jobs (token_address);
+    CREATE INDEX jobs_created_at ON jobs (created_at);
+
+    CREATE TABLE job_receivers (
+        job_id TEXT NOT NULL REFERENCES jobs (id),
+        position INTEGER NOT NULL,
+        address TEXT NOT NULL,
+        amount TEXT NOT NULL,
+        PRIMARY KEY (job_id, position)
+    );
+    CREATE INDEX job_receivers_address ON job_receivers (address);
+
+    CREATE TABLE job_transactions (
+        job_id TEXT NOT NULL REFERENCES jobs (id),
+        position INTEGER NOT NULL,
+        kind TEXT NOT NULL,
+        status TEXT NOT NULL,
+        tx_hash TEXT,
+        block_number INTEGER,
+        gas_used TEXT,
+        effective_gas_price TEXT,
+        receipt TEXT,
+        error TEXT,
+        PRIMARY KEY (job_id, position)
+    );
+    CREATE INDEX job_transactions_tx_hash ON job_transactions (tx_hash);
+"#,
+    r#"
+    CREATE TABLE idempotency_keys (
+        key TEXT PRIMARY KEY,
+        payload_hash TEXT NOT NULL,
+        job_id TEXT NOT NULL REFERENCES jobs (id),
+        created_at INTEGER NOT NULL
+    );
+"#,
+    r#"
+    ALTER TABLE job_transactions ADD COLUMN chunk TEXT;
+"#,
+    r#"
+    ALTER TABLE jobs ADD COLUMN chain_id INTEGER;
+    CREATE INDEX jobs_chain_id ON jobs (chain_id);
+"#,
+    r#"
+    ALTER TABLE job_transactions ADD COLUMN replacements TEXT;
+"#,
+    r#"
+    ALTER TABLE job_transactions ADD COLUMN max_fee_per_gas TEXT;
+    ALTER TABLE job_transactions ADD COLUMN max_priority_fee_per_gas TEXT;
+"#,
+    r#"
+    ALTER TABLE job_transactions ADD COLUMN reorgs TEXT;
+"#,
+    r#"
+    CREATE TABLE events (
+        chain_id INTEGER NOT NULL,
+        block_number INTEGER NOT NULL,
+        block_hash TEXT NOT NULL,
+        tx_hash TEXT NOT NULL,
+        log_index INTEGER NOT NULL,
+        kind TEXT NOT NULL,
+        sender TEXT NOT NULL,
+        token TEXT NOT NULL,
+        wallet TEXT NOT NULL,
+        amount TEXT NOT NULL,
+        PRIMARY KEY (chain_id, tx_hash, log_index)
+    );
+    CREATE INDEX events_block_number ON events (chain_id, block_number);
+    CREATE INDEX events_token ON events (token);
+    CREATE INDEX events_sender ON events (sender);
+    CREATE INDEX events_wallet ON events (wallet);
+
+    CREATE TABLE event_cursors (
+        chain_id INTEGER PRIMARY KEY,
+        next_block INTEGER NOT NULL
+    );
+"#,
+    r#"
+    ALTER TABLE job_transactions ADD COLUMN safe_batch TEXT;
+"#,
+];
+
+/// Embedded SQLite database shared by all services
+#[derive(Clone)]
+pub struct Database {
+    connection: Arc<Mutex<Connection>>,
+}
+
+impl Database {
+    pub fn open(path: &str) -> Result<Self> {
+        let mut connection = Connection::open(path)?;
+
+        connection.pragma_update(None, "journal_mode", "WAL")?;
+        connection.pragma_update(None, "foreign_keys", true)?;
+
+        Self::migrate(&mut connection)?;
+
+        println!("->> Database is ready at {}!", path);
+
+        Ok(Self {
+            connection: Arc::new(Mutex::new(connection)),
+        })
+    }
+
+    pub fn connection(&self) -> MutexGuard<'_, Connection> {
+        self.connection.lock().unwrap()
+    }
+
+    fn migrate(connection: &mut Connection) -> Result<()> {
+        let version: usize =
+            connection.pragma_query_value(None, "user_version", |row| row.get(0))?;
+
+        for (index, migration) in MIGRATIONS.iter().enumerate().skip(version) {
+            let tx = connection.transaction()?;
+            tx.execute_batch(migration)?;
+            tx.pragma_update(None, "user_version", index + 1)?;
+            tx.commit()?;
+
+            println!("->> Applied database migration {}", index + 1);
+        }
+
+        Ok(())
+    }
+}
@@ -1,31 +1,470 @@
+use crate::shared::app_error::AppError;
+use crate::shared::gas_policy::GasPolicy;
 use crate::shared::signed_provider::SignedProvider;
+use crate::shared::submitter::{InFlightTx, Replacement, Submitter};
+use alloy::consensus::TxEnvelope;
 use alloy::contract::SolCallBuilder;
+use alloy::eips::eip2718::Encodable2718;
 use alloy::primitives::TxHash;
-use alloy::pubsub::PubSubFrontend;
+use alloy::providers::Provider;
+use alloy::rpc::types::{TransactionReceipt, TransactionRequest};
 use alloy::sol_types::SolCall;
-use anyhow::Result;
+use alloy::transports::Transport;
+use anyhow::{anyhow, bail, Result};
+use std::future::Future;
+use std::sync::Arc;
+use tokio::time::Instant;
 
-pub async fn execute_call<T>(
-    call: SolCallBuilder<PubSubFrontend, &SignedProvider, T>,
+/// Gets notified about transaction progress while `execute_call` waits for the receipt
+pub trait TxListener: Send + Sync {
+    /// `fees` are absent when the transaction was sent by someone else, e.g. a Safe
+    fn on_submitted(&self, tx_hash: TxHash, fees: Option<FeeEstimate>);
+
+    /// A new version with the same nonce was sent in place of `replaced_tx_hash`
+    fn on_replaced(
+        &self,
+        replacement: Replacement,
+        replaced_tx_hash: TxHash,
+        tx_hash: TxHash,
+        fees: FeeEstimate,
+    );
+
+    /// Called as soon as a block includes the transaction, also for reverted transactions
+    fn on_included(&self, receipt: &TransactionReceipt);
+
+    /// The block that included `tx_hash` is no longer part of the chain, the wait starts over
+    fn on_reorged(&self, tx_hash: TxHash, block_number: u64);
+
+    /// Called once `confirmations` blocks are on top of the including block
+    fn on_mined(&self, receipt: &TransactionReceipt);
+}
+
+/// Fees a transaction sent now would pay per gas unit
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct FeeEstimate {
+    pub max_fee_per_gas: u128,
+    /// Absent on chains without EIP-1559
+    pub max_priority_fee_per_gas: Option<u128>,
+}
+
+impl FeeEstimate {
+    /// Fees set on `request`, `None` when they are left to the provider
+    pub fn of(request: &TransactionRequest) -> Option<Self> {
+        match (request.max_fee_per_gas, request.gas_price) {
+            (Some(max_fee_per_gas), _) => Some(Self {
+                max_fee_per_gas,
+                max_priority_fee_per_gas: request.max_priority_fee_per_gas,
+            }),
+            (None, Some(gas_price)) => Some(Self {
+                max_fee_per_gas: gas_price,
+                max_priority_fee_per_gas: None,
+            }),
+            (None, None) => None,
+        }
+    }
+
+    /// Sets these fees on `request`, as `gasPrice` when there is no priority fee
+    pub fn apply(&self, mut request: TransactionRequest) -> TransactionRequest {
+        match self.max_priority_fee_per_gas {
+            Some(max_priority_fee_per_gas) => {
+                request.gas_price = None;
+                request.max_fee_per_gas = Some(self.max_fee_per_gas);
+                request.max_priority_fee_per_gas = Some(max_priority_fee_per_gas);
+            }
+            None => {
+                request.gas_price = Some(self.max_fee_per_gas);
+                request.max_fee_per_gas = None;
+                request.max_priority_fee_per_gas = None;
+            }
+        }
+
+        request
+    }
+}
+
+/// Runs the call through `eth_call` and `eth_estimateGas` from the signer address,
+/// so a transaction that would revert fails here with its decoded reason instead of costing gas
+pub async fn simulate_call<T, C>(
+    call: &SolCallBuilder<T, &SignedProvider<T>, C>,
+    service_name: &str,
+) -> Result<u128>
+where
+    T: Transport + Clone,
+    C: SolCall,
+{
+    call.call_raw().await?;
+
+    let gas = call.estimate_gas().await?;
+
+    println!("{}. Simulation passed, gas estimate: {}", service_name, gas);
+
+    Ok(gas)
+}
+
+/// Simulates the call like `execute_call` and returns it as an unsigned transaction
+/// carrying the buffered gas estimate, nonce and fees are left to the caller
+pub async fn build_call<T, C>(
+    call: SolCallBuilder<T, &SignedProvider<T>, C>,
+    gas: &GasPolicy,
     service_name: &str,
-) -> Result<TxHash>
+) -> Result<TransactionRequest>
 where
-    T: SolCall,
+    T: Transport + Clone,
+    C: SolCall,
 {
-    let pending_tx = call.send().await?;
+    let estimate = simulate_call(&call, service_name).await?;
+
+    Ok(call.gas(gas.gas_limit(estimate)).into_transaction_request())
+}
+
+pub async fn estimate_fees<T>(provider: &SignedProvider<T>) -> Result<FeeEstimate>
+where
+    T: Transport + Clone,
+{
+    match provider.estimate_eip1559_fees(None).await {
+        Ok(fees) => Ok(FeeEstimate {
+            max_fee_per_gas: fees.max_fee_per_gas,
+            max_priority_fee_per_gas: Some(fees.max_priority_fee_per_gas),
+        }),
+        Err(_) => Ok(FeeEstimate {
+            max_fee_per_gas: provider.get_gas_price().await?,
+            max_priority_fee_per_gas: None,
+        }),
+    }
+}
+
+/// Simulates, sends and waits until `confirmations` blocks are on top of the transaction's.
+/// `call` needs its `from` set, the wallet signs with that account and the simulation
+/// sees the same `msg.sender`. The nonce comes from the submitter's `nonces`, and a
+/// transaction without receipt after the policy's timeout is replaced with higher fees.
+pub async fn execute_call<T, C>(
+    call: SolCallBuilder<T, &SignedProvider<T>, C>,
+    submitter: &Submitter,
+    service_name: &str,
+    listener: &dyn TxListener,
+) -> Result<TransactionReceipt>
+where
+    T: Transport + Clone,
+    C: SolCall,
+{
+    let provider = call.provider;
+    let estimate = simulate_call(&call, service_name).await?;
+
+    let request = call
+        .gas(submitter.gas.gas_limit(estimate))
+        .into_transaction_request();
+    let from = request
+        .from
+        .ok_or_else(|| anyhow!("{} has no sender", service_name))?;
+
+    // Fees are set here rather than by the provider, a replacement has to outbid them
+    let fees = submitter.gas.fees(provider).await?;
+    let request = fees.apply(request);
+
+    let reservation = submitter.nonces.reserve(provider, from).await?;
+    let request = request.nonce(reservation.nonce());
+
+    let tx_hash = *provider.send_transaction(request.clone()).await?.tx_hash();
+
+    reservation.commit();
 
     println!(
-        "{}. Pending transaction... {}",
-        service_name,
-        pending_tx.tx_hash()
+        "{}. Pending transaction... {} (max fee {}, priority fee {:?})",
+        service_name, tx_hash, fees.max_fee_per_gas, fees.max_priority_fee_per_gas
     );
 
-    let receipt = pending_tx.get_receipt().await?;
+    listener.on_submitted(tx_hash, Some(fees));
+
+    // Tracked until confirmed, a reorg may put the transaction back into the mempool
+    let tx = submitter.in_flight.track(tx_hash, request);
+    let receipt = wait_until_confirmed(
+        provider,
+        submitter.confirmations,
+        service_name,
+        listener,
+        || wait_in_flight(provider, &tx, submitter, service_name, listener),
+    )
+    .await;
+    submitter.in_flight.forget(&tx).await;
+    let receipt = receipt?;
+
+    listener.on_mined(&receipt);
+
+    tx.ensure_not_cancelled(receipt.transaction_hash).await?;
+
+    check_status(receipt, service_name)
+}
+
+/// Sends a transaction signed elsewhere and waits for it like `execute_call`.
+/// It is simulated from its signer first, so a transaction that would revert is never broadcast.
+pub async fn submit_signed<T>(
+    provider: &SignedProvider<T>,
+    envelope: &TxEnvelope,
+    service_name: &str,
+    confirmations: u64,
+    listener: &dyn TxListener,
+) -> Result<TransactionReceipt>
+where
+    T: Transport + Clone,
+{
+    let signer = envelope.recover_signer()?;
+    let request: TransactionRequest = envelope.clone().into();
+    let request = request.from(signer);
+
+    provider.call(&request).await?;
+
+    let tx_hash = *provider
+        .send_raw_transaction(&envelope.encoded_2718())
+        .await?
+        .tx_hash();
+
+    println!("{}. Pending transaction... {}", service_name, tx_hash);
+
+    listener.on_submitted(tx_hash, FeeEstimate::of(&request));
+
+    settle_receipt(provider, tx_hash, service_name, confirmations, listener).await
+}
+
+/// Waits for the receipt of a submitted transaction and fails when it reverted
+pub async fn settle_receipt<T>(
+    provider: &SignedProvider<T>,
+    tx_hash: TxHash,
+    service_name: &str,
+    confirmations: u64,
+    listener: &dyn TxListener,
+) -> Result<TransactionReceipt>
+where
+    T: Transport + Clone,
+{
+    let receipt = wait_until_confirmed(provider, confirmations, service_name, listener, || {
+        wait_for_receipt(provider, tx_hash, service_name)
+    })
+    .await?;
+
+    listener.on_mined(&receipt);
+
+    check_status(receipt, service_name)
+}
+
+fn check_status(receipt: TransactionReceipt, service_name: &str) -> Result<TransactionReceipt> {
+    if !receipt.status() {
+        bail!(AppError::Revert {
+            code: "TRANSACTION_REVERTED",
+            message: format!("Transaction {} reverted", receipt.transaction_hash),
+        });
+    }
 
     println!(
         "{}. Transaction successful with hash: {:?}",
         service_name, receipt.transaction_hash
     );
 
-    Ok(receipt.transaction_hash)
+    Ok(receipt)
+}
+
+/// Polls for the receipt instead of watching new blocks, so a dropped connection only
+/// delays the wait: failed polls are retried until the transport is reconnected.
+/// There is no timeout, a transaction signed elsewhere can't be replaced from here.
+async fn wait_for_receipt<T>(
+    provider: &SignedProvider<T>,
+    tx_hash: TxHash,
+    service_name: &str,
+) -> Result<TransactionReceipt>
+where
+    T: Transport + Clone,
+{
+    let mut interval = tokio::time::interval(provider.client().poll_interval());
+
+    loop {
+        interval.tick().await;
+
+        if let Some(receipt) = poll_receipt(provider, &[tx_hash], service_name).await {
+            return Ok(receipt);
+        }
+    }
+}
+
+/// Waits until a receipt of `next_receipt` has `confirmations` blocks on top. After a reorg the
+/// receipt is waited for again, a version of an in-flight transaction dropped from the mempool
+/// is then sent again by its replacements.
+async fn wait_until_confirmed<T, F, Fut>(
+    provider: &SignedProvider<T>,
+    confirmations: u64,
+    service_name: &str,
+    listener: &dyn TxListener,
+    mut next_receipt: F,
+) -> Result<TransactionReceipt>
+where
+    T: Transport + Clone,
+    F: FnMut() -> Fut,
+    Fut: Future<Output = Result<TransactionReceipt>>,
+{
+    loop {
+        let receipt = next_receipt().await?;
+
+        listener.on_included(&receipt);
+
+        if let Some(receipt) =
+            wait_for_confirmations(provider, receipt, confirmations, service_name, listener).await?
+        {
+            return Ok(receipt);
+        }
+    }
+}
+
+/// Waits for the receipt of any version of `tx`. Every `receipt_timeout` without one the
+/// latest version is sped up, until `max_replacements` is reached and the wait gives up.
+async fn wait_in_flight<T>(
+    provider: &SignedProvider<T>,
+    tx: &Arc<InFlightTx>,
+    submitter: &Submitter,
+    service_name: &str,
+    listener: &dyn TxListener,
+) -> Result<TransactionReceipt>
+where
+    T: Transport + Clone,
+{
+    let policy = submitter.policy;
+    let mut interval = tokio::time::interval(provider.client().poll_interval());
+    let mut deadline = Instant::now() + policy.receipt_timeout;
+    let mut replacements = 0;
+
+    loop {
+        interval.tick().await;
+
+        // Versions replaced through the API are picked up here as well
+        if let Some(receipt) = poll_receipt(provider, &tx.hashes().await, service_name).await {
+            return Ok(receipt);
+        }
+
+        if Instant::now() < deadline {
+            continue;
+        }
+
+        let replaced = tx.latest_hash().await;
+
+        if replacements >= policy.max_replacements {
+            bail!(AppError::Timeout {
+                code: "RECEIPT_TIMEOUT",
+                message: format!(
+                    "No receipt for {} after {} replacement(s), it may still be mined",
+                    replaced, replacements
+                ),
+            });
+        }
+
+        // A failed replacement usually means a version was mined meanwhile, the next poll finds it
+        match submitter.replace(provider, tx, Replacement::SpeedUp).await {
+            Ok((tx_hash, fees)) => {
+                listener.on_replaced(Replacement::SpeedUp, replaced, tx_hash, fees)
+            }
+            Err(e) => println!(
+                "{}. Replacement of {} failed: {}",
+                service_name, replaced, e
+            ),
+        }
+
+        replacements += 1;
+        deadline = Instant::now() + policy.receipt_timeout;
+    }
+}
+
+/// Newest block with `confirmations` blocks on top of it when `head` is the latest block
+pub fn last_confirmed_block(head: u64, confirmations: u64) -> u64 {
+    head.saturating_sub(confirmations)
+}
+
+/// Receipt of the first of `hashes` that was mined, poll errors are logged and skipped
+async fn poll_receipt<T>(
+    provider: &SignedProvider<T>,
+    hashes: &[TxHash],
+    service_name: &str,
+) -> Option<TransactionReceipt>
+where
+    T: Transport + Clone,
+{
+    for tx_hash in hashes {
+        match provider.get_transaction_receipt(*tx_hash).await {
+            Ok(Some(receipt)) => return Some(receipt),
+            Ok(None) => {}
+            Err(e) => println!(
+                "{}. Receipt of {} is not available yet, retrying: {}",
+                service_name, tx_hash, e
+            ),
+        }
+    }
+
+    None
+}
+
+/// Waits until `confirmations` blocks are on top of the block of `receipt`. The receipt is read
+/// again on every poll, `None` means its block was reorged out and the transaction is
+/// either pending again or included by another block.
+async fn wait_for_confirmations<T>(
+    provider: &SignedProvider<T>,
+    receipt: TransactionReceipt,
+    confirmations: u64,
+    service_name: &str,
+    listener: &dyn TxListener,
+) -> Result<Option<TransactionReceipt>>
+where
+    T: Transport + Clone,
+{
+    let (Some(block_number), Some(block_hash)) = (receipt.block_number, receipt.block_hash) else {
+        return Ok(Some(receipt));
+    };
+    let tx_hash = receipt.transaction_hash;
+
+    let mut interval = tokio::time::interval(provider.client().poll_interval());
+
+    loop {
+        interval.tick().await;
+
+        match provider.get_transaction_receipt(tx_hash).await {
+            Ok(Some(current)) if current.block_hash == Some(block_hash) => {}
+            Ok(_) => {
+                println!(
+                    "{}. Block {} ({}) including {} was reorged out",
+                    service_name, block_number, block_hash, tx_hash
+                );
+
+                listener.on_reorged(tx_hash, block_number);
+
+                return Ok(None);
+            }
+            Err(e) => {
+                println!(
+                    "{}. Receipt of {} is not available yet, retrying: {}",
+                    service_name, tx_hash, e
+                );
+                continue;
+            }
+        }
+
+        match provider.get_block_number().await {
+            Ok(latest) if last_confirmed_block(latest, confirmations) >= block_number => {
+                return Ok(Some(receipt))
+            }
+            Ok(_) => {}
+            Err(e) => println!(
+                "{}. Block number is not available yet, retrying: {}",
+                service_name, e
+            ),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn confirmations_count_the_blocks_on_top() {
+        // Block 100 with one block on top once 101 is the latest
+        assert_eq!(last_confirmed_block(101, 1), 100);
+        assert_eq!(last_confirmed_block(112, 12), 100);
+        // Without confirmations the including block itself is enough
+        assert_eq!(last_confirmed_block(100, 0), 100);
+        assert_eq!(last_confirmed_block(3, 12), 0);
+    }
 }
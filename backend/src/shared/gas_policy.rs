@@ -0,0 +1,271 @@
+use crate::shared::app_error::AppError;
+use crate::shared::execute_call::{estimate_fees, FeeEstimate};
+use crate::shared::signed_provider::SignedProvider;
+use alloy::providers::Provider;
+use alloy::transports::Transport;
+use anyhow::{bail, Result};
+use serde::{Deserialize, Serialize};
+
+const WEI_PER_GWEI: f64 = 1e9;
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum FeeMode {
+    /// EIP-1559 fees, `gasPrice` when the node can't estimate them
+    #[default]
+    Auto,
+    /// EIP-1559 fees only, estimating them fails on chains without EIP-1559
+    Eip1559,
+    /// `gasPrice` only, for chains without EIP-1559
+    Legacy,
+}
+
+/// How the fees of the transactions sent on one chain are chosen
+#[derive(Debug, Clone, Copy)]
+pub struct GasPolicy {
+    pub fee_mode: FeeMode,
+    /// Applied to the node's estimates, e.g. 1.2 to be included faster
+    pub fee_multiplier: f64,
+    /// Bids are lowered to these, the transaction may then take longer to be included
+    pub max_fee_per_gas: Option<u128>,
+    pub max_priority_fee_per_gas: Option<u128>,
+    /// Nothing is sent while the bid would be above it
+    pub fee_ceiling: Option<u128>,
+    /// Added to gas estimates, state may change between the simulation and the inclusion
+    pub gas_limit_buffer_percent: u64,
+}
+
+impl GasPolicy {
+    /// Fees to send a transaction with now: the node's estimate times the multiplier,
+    /// refused above the ceiling and capped afterwards
+    pub async fn fees<T>(&self, provider: &SignedProvider<T>) -> Result<FeeEstimate>
+    where
+        T: Transport + Clone,
+    {
+        let estimate = match self.fee_mode {
+            FeeMode::Auto => estimate_fees(provider).await?,
+            FeeMode::Eip1559 => {
+                let fees = provider.estimate_eip1559_fees(None).await?;
+
+                FeeEstimate {
+                    max_fee_per_gas: fees.max_fee_per_gas,
+                    max_priority_fee_per_gas: Some(fees.max_priority_fee_per_gas),
+                }
+            }
+            FeeMode::Legacy => FeeEstimate {
+                max_fee_per_gas: provider.get_gas_price().await?,
+                max_priority_fee_per_gas: None,
+            },
+        };
+
+        self.price(estimate)
+    }
+
+    /// Multiplier, ceiling and caps applied to an estimate of the node
+    pub fn price(&self, estimate: FeeEstimate) -> Result<FeeEstimate> {
+        self.limit(FeeEstimate {
+            max_fee_per_gas: self.scale(estimate.max_fee_per_gas),
+            max_priority_fee_per_gas: estimate.max_priority_fee_per_gas.map(|fee| self.scale(fee)),
+        })
+    }
+
+    /// Gas limit of a transaction estimated to use `estimate`, raised by the buffer
+    pub fn gas_limit(&self, estimate: u128) -> u128 {
+        (estimate * (100 + self.gas_limit_buffer_percent as u128)).div_ceil(100)
+    }
+
+    /// Refuses fees above the ceiling and lowers them to the caps
+    pub fn limit(&self, fees: FeeEstimate) -> Result<FeeEstimate> {
+        if let Some(fee_ceiling) = self.fee_ceiling {
+            if fees.max_fee_per_gas > fee_ceiling {
+                bail!(AppError::Conflict {
+                    code: "GAS_PRICE_ABOVE_CEILING",
+                    message: format!(
+                        "Fee of {} gwei is above the ceiling of {} gwei, nothing is sent",
+                        to_gwei(fees.max_fee_per_gas),
+                        to_gwei(fee_ceiling)
+                    ),
+                });
+            }
+        }
+
+        let max_fee_per_gas = match self.max_fee_per_gas {
+            Some(cap) => fees.max_fee_per_gas.min(cap),
+            None => fees.max_fee_per_gas,
+        };
+
+        let max_priority_fee_per_gas = fees.max_priority_fee_per_gas.map(|fee| {
+            let fee = match self.max_priority_fee_per_gas {
+                Some(cap) => fee.min(cap),
+                None => fee,
+            };
+
+            fee.min(max_fee_per_gas)
+        });
+
+        Ok(FeeEstimate {
+            max_fee_per_gas,
+            max_priority_fee_per_gas,
+        })
+    }
+
+    fn scale(&self, fee: u128) -> u128 {
+        (fee as f64 * self.fee_multiplier).ceil() as u128
+    }
+}
+
+pub fn from_gwei(gwei: f64) -> u128 {
+    (gwei * WEI_PER_GWEI).round() as u128
+}
+
+fn to_gwei(wei: u128) -> f64 {
+    wei as f64 / WEI_PER_GWEI
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use alloy::rpc::types::TransactionRequest;
+
+    const GWEI: u128 = 1_000_000_000;
+
+    fn policy() -> GasPolicy {
+        GasPolicy {
+            fee_mode: FeeMode::Auto,
+            fee_multiplier: 1.0,
+            max_fee_per_gas: None,
+            max_priority_fee_per_gas: None,
+            fee_ceiling: None,
+            gas_limit_buffer_percent: 0,
+        }
+    }
+
+    fn eip1559(max_fee_per_gas: u128, max_priority_fee_per_gas: u128) -> FeeEstimate {
+        FeeEstimate {
+            max_fee_per_gas,
+            max_priority_fee_per_gas: Some(max_priority_fee_per_gas),
+        }
+    }
+
+    fn legacy(gas_price: u128) -> FeeEstimate {
+        FeeEstimate {
+            max_fee_per_gas: gas_price,
+            max_priority_fee_per_gas: None,
+        }
+    }
+
+    fn error_code(result: Result<FeeEstimate>) -> &'static str {
+        result.unwrap_err().downcast::<AppError>().unwrap().code()
+    }
+
+    #[test]
+    fn multiplier_scales_both_fees_and_rounds_up() {
+        let policy = GasPolicy {
+            fee_multiplier: 1.5,
+            ..policy()
+        };
+
+        assert_eq!(policy.price(eip1559(101, 3)).unwrap(), eip1559(152, 5));
+    }
+
+    #[test]
+    fn caps_lower_the_fees() {
+        let policy = GasPolicy {
+            max_fee_per_gas: Some(50 * GWEI),
+            max_priority_fee_per_gas: Some(2 * GWEI),
+            ..policy()
+        };
+
+        assert_eq!(
+            policy.price(eip1559(80 * GWEI, 3 * GWEI)).unwrap(),
+            eip1559(50 * GWEI, 2 * GWEI)
+        );
+        assert_eq!(
+            policy.price(eip1559(30 * GWEI, GWEI)).unwrap(),
+            eip1559(30 * GWEI, GWEI)
+        );
+    }
+
+    #[test]
+    fn priority_fee_never_exceeds_the_capped_max_fee() {
+        let policy = GasPolicy {
+            max_fee_per_gas: Some(10 * GWEI),
+            ..policy()
+        };
+
+        assert_eq!(
+            policy.price(eip1559(20 * GWEI, 15 * GWEI)).unwrap(),
+            eip1559(10 * GWEI, 10 * GWEI)
+        );
+    }
+
+    #[test]
+    fn ceiling_refuses_fees_before_they_are_capped() {
+        let policy = GasPolicy {
+            fee_ceiling: Some(100 * GWEI),
+            max_fee_per_gas: Some(50 * GWEI),
+            ..policy()
+        };
+
+        assert_eq!(
+            error_code(policy.price(eip1559(101 * GWEI, GWEI))),
+            "GAS_PRICE_ABOVE_CEILING"
+        );
+        assert_eq!(
+            policy.price(eip1559(100 * GWEI, GWEI)).unwrap(),
+            eip1559(50 * GWEI, GWEI)
+        );
+    }
+
+    #[test]
+    fn ceiling_applies_after_the_multiplier() {
+        let policy = GasPolicy {
+            fee_multiplier: 2.0,
+            fee_ceiling: Some(100 * GWEI),
+            ..policy()
+        };
+
+        assert_eq!(
+            error_code(policy.price(legacy(60 * GWEI))),
+            "GAS_PRICE_ABOVE_CEILING"
+        );
+    }
+
+    #[test]
+    fn legacy_fees_stay_a_gas_price() {
+        let policy = GasPolicy {
+            fee_mode: FeeMode::Legacy,
+            fee_multiplier: 1.1,
+            max_fee_per_gas: Some(50 * GWEI),
+            max_priority_fee_per_gas: Some(GWEI),
+            ..policy()
+        };
+
+        let fees = policy.price(legacy(10 * GWEI)).unwrap();
+        assert_eq!(fees, legacy(11 * GWEI));
+
+        let request = fees.apply(TransactionRequest::default());
+        assert_eq!(request.gas_price, Some(11 * GWEI));
+        assert_eq!(request.max_fee_per_gas, None);
+        assert_eq!(request.max_priority_fee_per_gas, None);
+    }
+
+    #[test]
+    fn converts_gwei_to_wei() {
+        assert_eq!(from_gwei(1.5), 1_500_000_000);
+        assert_eq!(from_gwei(0.000000001), 1);
+    }
+
+    #[test]
+    fn gas_limit_adds_the_buffer_and_rounds_up() {
+        assert_eq!(policy().gas_limit(21_001), 21_001);
+
+        let policy = GasPolicy {
+            gas_limit_buffer_percent: 20,
+            ..policy()
+        };
+
+        assert_eq!(policy.gas_limit(100_000), 120_000);
+        assert_eq!(policy.gas_limit(21_001), 25_202);
+    }
+}
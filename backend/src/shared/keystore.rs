@@ -0,0 +1,81 @@
+use alloy::primitives::hex;
+use alloy::signers::local::PrivateKeySigner;
+use anyhow::{bail, Context, Result};
+use std::path::{Path, PathBuf};
+
+/// Decrypts a JSON keystore (Web3 Secret Storage), the password is read from
+/// `password_file` or asked for on the terminal
+pub fn decrypt_keystore(path: &Path, password_file: Option<&Path>) -> Result<PrivateKeySigner> {
+    let password = match password_file {
+        Some(password_file) => read_password_file(password_file)?,
+        None => rpassword::prompt_password(format!("Password of {}: ", path.display()))?,
+    };
+
+    println!("->> Decrypting keystore {}", path.display());
+
+    PrivateKeySigner::decrypt_keystore(path, password)
+        .with_context(|| format!("Failed to decrypt keystore {}", path.display()))
+}
+
+/// Encrypts `signer` into `dir/name`, the file is named by a random UUID without `name`
+pub fn encrypt_keystore(
+    signer: &PrivateKeySigner,
+    dir: &Path,
+    name: Option<&str>,
+    password_file: Option<&Path>,
+) -> Result<PathBuf> {
+    if let Some(name) = name {
+        if dir.join(name).exists() {
+            bail!("Keystore {} already exists", dir.join(name).display());
+        }
+    }
+
+    let password = match password_file {
+        Some(password_file) => read_password_file(password_file)?,
+        None => prompt_new_password()?,
+    };
+
+    std::fs::create_dir_all(dir)
+        .with_context(|| format!("Failed to create directory {}", dir.display()))?;
+
+    let (_, uuid) = PrivateKeySigner::encrypt_keystore(
+        dir,
+        &mut rand::thread_rng(),
+        signer.to_bytes(),
+        password,
+        name,
+    )?;
+
+    Ok(dir.join(name.unwrap_or(&uuid)))
+}
+
+/// Private key to import, asked for without echoing it
+pub fn prompt_private_key() -> Result<PrivateKeySigner> {
+    let private_key = rpassword::prompt_password("Private key (hex): ")?;
+
+    let bytes = hex::decode(private_key.trim()).context("Private key is not valid hex")?;
+
+    PrivateKeySigner::from_slice(&bytes).context("Incorrect private key")
+}
+
+/// Only the trailing line break is dropped, other whitespace is part of the password
+fn read_password_file(path: &Path) -> Result<String> {
+    let password = std::fs::read_to_string(path)
+        .with_context(|| format!("Failed to read password file {}", path.display()))?;
+
+    Ok(password.trim_end_matches(['\n', '\r']).to_string())
+}
+
+fn prompt_new_password() -> Result<String> {
+    let password = rpassword::prompt_password("New keystore password: ")?;
+
+    if password.is_empty() {
+        bail!("Password must not be empty");
+    }
+
+    if rpassword::prompt_password("Repeat password: ")? != password {
+        bail!("Passwords do not match");
+    }
+
+    Ok(password)
+}
@@ -0,0 +1,26 @@
+use alloy::signers::local::PrivateKeySigner;
+use anyhow::{Context, Result};
+use coins_bip32::ecdsa::SigningKey;
+use coins_bip32::path::DerivationPath;
+use coins_bip39::{English, Mnemonic};
+
+pub const DEFAULT_DERIVATION_PATH: &str = "m/44'/60'/0'/0";
+
+/// Derives the account at `derivation_path/index` of a BIP-39 mnemonic,
+/// index 0 of the default path is the first account of MetaMask and most other wallets
+pub fn derive_signer(phrase: &str, derivation_path: &str, index: u32) -> Result<PrivateKeySigner> {
+    // Words are matched one by one, so stray line breaks or double spaces would break them
+    let phrase = phrase.split_whitespace().collect::<Vec<_>>().join(" ");
+
+    let mnemonic = Mnemonic::<English>::new_from_phrase(&phrase).context("Invalid mnemonic")?;
+
+    let path = format!("{}/{}", derivation_path.trim_end_matches('/'), index);
+    let path: DerivationPath = path
+        .parse()
+        .with_context(|| format!("Invalid derivation path {}", path))?;
+
+    let key = mnemonic.derive_key(path, None)?;
+    let signing_key: &SigningKey = key.as_ref();
+
+    Ok(PrivateKeySigner::from_signing_key(signing_key.clone()))
+}
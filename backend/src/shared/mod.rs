@@ -1,3 +1,16 @@
+pub mod app_error;
+pub mod chain_config;
+pub mod config;
 pub mod contracts;
+pub mod database;
 pub mod execute_call;
+pub mod gas_policy;
+pub mod keystore;
+pub mod mnemonic;
+pub mod nonce_manager;
+pub mod provider_health;
+pub mod remote_signer;
 pub mod signed_provider;
+pub mod submitter;
+pub mod token_manager_math;
+pub mod units;
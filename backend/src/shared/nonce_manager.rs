@@ -0,0 +1,128 @@
+use crate::shared::signed_provider::SignedProvider;
+use alloy::primitives::Address;
+use alloy::providers::Provider;
+use alloy::transports::Transport;
+use anyhow::Result;
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use tokio::sync::{Mutex as AsyncMutex, OwnedMutexGuard};
+
+/// Nonces of the wallet accounts on one chain, allocated here instead of by the provider's
+/// nonce filler, which hands the same nonce to concurrent sends and never takes a failed one back
+#[derive(Clone, Default)]
+pub struct NonceManager {
+    accounts: Arc<Mutex<HashMap<Address, AccountNonces>>>,
+}
+
+#[derive(Clone, Default)]
+struct AccountNonces {
+    /// Held by a job for all of its transactions, so the approval and distribution
+    /// of one job can't interleave with the transactions of another job
+    submissions: Arc<AsyncMutex<()>>,
+    /// Next nonce to use, `None` until it is read from the chain's pending nonce
+    next: Arc<AsyncMutex<Option<u64>>>,
+}
+
+/// Exclusive right to send from an account, released when dropped
+pub struct SubmissionLock {
+    _guard: OwnedMutexGuard<()>,
+}
+
+/// Nonce of a transaction being sent. Without `commit` it is given back when dropped and the
+/// next nonce is read from the chain again, so a failed send leaves no gap behind.
+pub struct NonceReservation {
+    next: OwnedMutexGuard<Option<u64>>,
+    nonce: u64,
+    committed: bool,
+}
+
+impl NonceManager {
+    /// Waits until no other job sends from `account`
+    pub async fn lock_submissions(&self, account: Address) -> SubmissionLock {
+        SubmissionLock {
+            _guard: self.account(account).submissions.lock_owned().await,
+        }
+    }
+
+    /// Reads the pending nonce of every account, so the first transaction after
+    /// a restart continues after the ones still waiting in the mempool
+    pub async fn sync<T>(&self, provider: &SignedProvider<T>, accounts: &[Address]) -> Result<()>
+    where
+        T: Transport + Clone,
+    {
+        for account in accounts {
+            let pending = provider.get_transaction_count(*account).pending().await?;
+            let latest = provider.get_transaction_count(*account).await?;
+
+            println!(
+                "->> Nonce of {} is {}, {} transaction(s) pending",
+                account,
+                pending,
+                pending.saturating_sub(latest)
+            );
+
+            *self.account(*account).next.lock().await = Some(pending);
+        }
+
+        Ok(())
+    }
+
+    /// Holds the nonce of `account` until the reservation is committed or dropped,
+    /// so two sends can never get the same nonce
+    pub async fn reserve<T>(
+        &self,
+        provider: &SignedProvider<T>,
+        account: Address,
+    ) -> Result<NonceReservation>
+    where
+        T: Transport + Clone,
+    {
+        let next = self.account(account).next.lock_owned().await;
+
+        let nonce = match *next {
+            Some(nonce) => nonce,
+            None => provider.get_transaction_count(account).pending().await?,
+        };
+
+        Ok(NonceReservation {
+            next,
+            nonce,
+            committed: false,
+        })
+    }
+
+    /// Reads the nonce from the chain again before the next send,
+    /// e.g. after a transaction of the account was signed elsewhere
+    pub async fn resync(&self, account: Address) {
+        *self.account(account).next.lock().await = None;
+    }
+
+    fn account(&self, account: Address) -> AccountNonces {
+        self.accounts
+            .lock()
+            .unwrap()
+            .entry(account)
+            .or_default()
+            .clone()
+    }
+}
+
+impl NonceReservation {
+    pub fn nonce(&self) -> u64 {
+        self.nonce
+    }
+
+    /// The transaction reached the node, the next send gets the following nonce
+    pub fn commit(mut self) {
+        *self.next = Some(self.nonce + 1);
+        self.committed = true;
+    }
+}
+
+impl Drop for NonceReservation {
+    fn drop(&mut self) {
+        if !self.committed {
+            *self.next = None;
+        }
+    }
+}
@@ -0,0 +1,201 @@
+use crate::shared::signed_provider::SignedProvider;
+use alloy::providers::Provider;
+use alloy::pubsub::{ConnectionHandle, PubSubConnect};
+use alloy::transports::{Transport, TransportResult};
+use serde::Serialize;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+const MIN_RECONNECT_DELAY: Duration = Duration::from_secs(1);
+const MAX_RECONNECT_DELAY: Duration = Duration::from_secs(60);
+const PROBE_INTERVAL: Duration = Duration::from_secs(15);
+const PROBE_TIMEOUT: Duration = Duration::from_secs(10);
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ConnectionState {
+    Connecting,
+    Connected,
+    Reconnecting,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct HealthSnapshot {
+    pub transport: String,
+    pub connection: ConnectionState,
+    /// Unix timestamp of the current connection state
+    pub since: u64,
+    /// Failed attempts of the ongoing reconnection
+    pub reconnect_attempts: u32,
+    /// Successful reconnections since start
+    pub reconnects: u32,
+    pub latest_block: Option<u64>,
+    /// Unix timestamp of the last successful probe
+    pub last_probe_at: Option<u64>,
+    pub last_error: Option<String>,
+}
+
+impl HealthSnapshot {
+    /// Healthy when connected and the last probe got a block number
+    pub fn is_healthy(&self) -> bool {
+        self.connection == ConnectionState::Connected && self.last_error.is_none()
+    }
+}
+
+/// Connection state of the RPC provider, updated by the reconnecting transport
+/// and by a background probe, read by the health endpoint
+#[derive(Clone)]
+pub struct ProviderHealth {
+    state: Arc<Mutex<HealthSnapshot>>,
+}
+
+impl ProviderHealth {
+    pub fn new(transport: &str) -> Self {
+        Self {
+            state: Arc::new(Mutex::new(HealthSnapshot {
+                transport: transport.to_string(),
+                connection: ConnectionState::Connecting,
+                since: unix_timestamp(),
+                reconnect_attempts: 0,
+                reconnects: 0,
+                latest_block: None,
+                last_probe_at: None,
+                last_error: None,
+            })),
+        }
+    }
+
+    pub fn snapshot(&self) -> HealthSnapshot {
+        self.state.lock().unwrap().clone()
+    }
+
+    /// Requests over HTTP don't keep a connection, so the probe is what detects an outage there
+    pub fn spawn_probe<T>(&self, provider: SignedProvider<T>)
+    where
+        T: Transport + Clone,
+    {
+        let health = self.clone();
+
+        tokio::spawn(async move {
+            let mut interval = tokio::time::interval(PROBE_INTERVAL);
+
+            loop {
+                interval.tick().await;
+
+                let result = tokio::time::timeout(PROBE_TIMEOUT, provider.get_block_number()).await;
+
+                let mut state = health.state.lock().unwrap();
+
+                match result {
+                    Ok(Ok(block_number)) => {
+                        if state.connection == ConnectionState::Connecting {
+                            state.connection = ConnectionState::Connected;
+                            state.since = unix_timestamp();
+                        }
+                        state.latest_block = Some(block_number);
+                        state.last_probe_at = Some(unix_timestamp());
+                        state.last_error = None;
+                    }
+                    Ok(Err(e)) => state.last_error = Some(e.to_string()),
+                    Err(_) => {
+                        state.last_error =
+                            Some(format!("No block number within {:?}", PROBE_TIMEOUT))
+                    }
+                }
+            }
+        });
+    }
+
+    fn set_connected(&self) {
+        let mut state = self.state.lock().unwrap();
+
+        if state.connection == ConnectionState::Reconnecting {
+            state.reconnects += 1;
+        }
+
+        state.connection = ConnectionState::Connected;
+        state.since = unix_timestamp();
+        state.reconnect_attempts = 0;
+        state.last_error = None;
+    }
+
+    fn set_reconnecting(&self, attempts: u32, error: Option<String>) {
+        let mut state = self.state.lock().unwrap();
+
+        if state.connection != ConnectionState::Reconnecting {
+            state.connection = ConnectionState::Reconnecting;
+            state.since = unix_timestamp();
+        }
+
+        state.reconnect_attempts = attempts;
+        if error.is_some() {
+            state.last_error = error;
+        }
+    }
+}
+
+/// Wraps a WebSocket or IPC connector so a dropped socket is reconnected with
+/// exponential backoff instead of giving up after one attempt. The pubsub service
+/// re-sends in-flight requests and re-subscribes once the new backend is up.
+pub struct ReconnectingConnect<C> {
+    inner: C,
+    health: ProviderHealth,
+}
+
+impl<C: PubSubConnect> ReconnectingConnect<C> {
+    pub fn new(inner: C, health: ProviderHealth) -> Self {
+        Self { inner, health }
+    }
+}
+
+impl<C: PubSubConnect> PubSubConnect for ReconnectingConnect<C> {
+    fn is_local(&self) -> bool {
+        self.inner.is_local()
+    }
+
+    async fn connect(&self) -> TransportResult<ConnectionHandle> {
+        let handle = self.inner.connect().await?;
+
+        self.health.set_connected();
+
+        Ok(handle)
+    }
+
+    async fn try_reconnect(&self) -> TransportResult<ConnectionHandle> {
+        let mut delay = MIN_RECONNECT_DELAY;
+        let mut attempts: u32 = 0;
+
+        self.health.set_reconnecting(attempts, None);
+
+        loop {
+            println!(
+                "->> RPC connection lost, reconnecting (attempt {})",
+                attempts + 1
+            );
+
+            match self.connect().await {
+                Ok(handle) => {
+                    println!("->> RPC connection restored");
+
+                    return Ok(handle);
+                }
+                Err(e) => {
+                    attempts += 1;
+                    self.health.set_reconnecting(attempts, Some(e.to_string()));
+
+                    println!("->> Reconnection failed, retrying in {:?}: {}", delay, e);
+
+                    tokio::time::sleep(delay).await;
+                    delay = (delay * 2).min(MAX_RECONNECT_DELAY);
+                }
+            }
+        }
+    }
+}
+
+fn unix_timestamp() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs()
+}
@@ -0,0 +1,181 @@
+use alloy::consensus::{TxEnvelope, TypedTransaction};
+use alloy::eips::eip2718::Decodable2718;
+use alloy::primitives::{Address, Bytes};
+use alloy::rpc::types::TransactionRequest;
+use anyhow::{anyhow, bail, Context, Result};
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader};
+use tokio::net::UnixStream;
+
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// Client of an external signer process speaking JSON-RPC: `eth_accounts` lists the
+/// accounts it holds and `eth_signTransaction` returns a signed raw transaction, so the
+/// private key never enters this process. See `src/bin/stand_in_signer.rs`.
+#[derive(Debug, Clone)]
+pub struct RemoteSigner {
+    endpoint: Endpoint,
+    accounts: Vec<Address>,
+    client: reqwest::Client,
+    next_id: Arc<AtomicU64>,
+}
+
+/// HTTP POST per request, or one newline-delimited JSON-RPC request per Unix socket connection
+#[derive(Debug, Clone)]
+enum Endpoint {
+    Http(String),
+    Unix(PathBuf),
+}
+
+#[derive(Debug, Deserialize)]
+struct RpcResponse<R> {
+    result: Option<R>,
+    error: Option<RpcError>,
+}
+
+#[derive(Debug, Deserialize)]
+struct RpcError {
+    code: i64,
+    message: String,
+}
+
+impl RemoteSigner {
+    /// `url` is `http(s)://...` or `unix:///path/to/signer.sock`.
+    /// The accounts are read once, the first one becomes the default signer.
+    pub async fn connect(url: &str) -> Result<Self> {
+        let mut signer = Self {
+            endpoint: endpoint(url)?,
+            accounts: vec![],
+            client: reqwest::Client::builder()
+                .timeout(REQUEST_TIMEOUT)
+                .build()?,
+            next_id: Arc::new(AtomicU64::new(1)),
+        };
+
+        let accounts: Vec<Address> = signer
+            .request("eth_accounts", json!([]))
+            .await
+            .with_context(|| format!("Remote signer {} is not reachable", url))?;
+
+        if accounts.is_empty() {
+            bail!("Remote signer {} holds no account", url);
+        }
+
+        signer.accounts = accounts;
+
+        Ok(signer)
+    }
+
+    pub fn accounts(&self) -> &[Address] {
+        &self.accounts
+    }
+
+    /// The returned transaction is checked to be the requested one, signed by `sender`
+    pub async fn sign_transaction(
+        &self,
+        sender: Address,
+        tx: TypedTransaction,
+    ) -> Result<TxEnvelope> {
+        if !self.accounts.contains(&sender) {
+            bail!("Remote signer holds no key for {}", sender);
+        }
+
+        let request: TransactionRequest = tx.clone().into();
+        let request = request.from(sender);
+
+        let raw: Bytes = self
+            .request("eth_signTransaction", json!([request]))
+            .await?;
+
+        let envelope = TxEnvelope::decode_2718(&mut raw.as_ref())
+            .context("Remote signer returned an undecodable transaction")?;
+
+        if TypedTransaction::from(envelope.clone()) != tx {
+            bail!("Remote signer returned a different transaction than requested");
+        }
+
+        let signer = envelope.recover_signer()?;
+        if signer != sender {
+            bail!("Remote signer signed with {} instead of {}", signer, sender);
+        }
+
+        Ok(envelope)
+    }
+
+    async fn request<P: Serialize, R: DeserializeOwned>(
+        &self,
+        method: &str,
+        params: P,
+    ) -> Result<R> {
+        let request = json!({
+            "jsonrpc": "2.0",
+            "id": self.next_id.fetch_add(1, Ordering::Relaxed),
+            "method": method,
+            "params": params,
+        });
+
+        let response: RpcResponse<R> = match &self.endpoint {
+            Endpoint::Http(url) => {
+                self.client
+                    .post(url)
+                    .json(&request)
+                    .send()
+                    .await?
+                    .error_for_status()?
+                    .json()
+                    .await?
+            }
+            Endpoint::Unix(path) => {
+                tokio::time::timeout(REQUEST_TIMEOUT, unix_request(path, &request))
+                    .await
+                    .map_err(|_| anyhow!("No response within {:?}", REQUEST_TIMEOUT))??
+            }
+        };
+
+        match (response.result, response.error) {
+            (_, Some(error)) => bail!(
+                "Remote signer refused {}: {} ({})",
+                method,
+                error.message,
+                error.code
+            ),
+            (Some(result), None) => Ok(result),
+            (None, None) => bail!("Remote signer sent no result for {}", method),
+        }
+    }
+}
+
+async fn unix_request<R: DeserializeOwned>(path: &Path, request: &serde_json::Value) -> Result<R> {
+    let mut stream = UnixStream::connect(path).await?;
+
+    let mut line = serde_json::to_vec(request)?;
+    line.push(b'\n');
+    stream.write_all(&line).await?;
+
+    let mut response = String::new();
+    BufReader::new(stream).read_line(&mut response).await?;
+
+    Ok(serde_json::from_str(&response)?)
+}
+
+fn endpoint(url: &str) -> Result<Endpoint> {
+    match url.split_once("://") {
+        Some(("http" | "https", _)) => Ok(Endpoint::Http(url.to_string())),
+        Some(("unix", path)) => Ok(Endpoint::Unix(PathBuf::from(path))),
+        _ => bail!(
+            "Remote signer URL '{}' must start with http://, https:// or unix://",
+            url
+        ),
+    }
+}
+
+/// Checked at startup before anything connects
+pub fn validate_url(url: &str) -> Result<()> {
+    endpoint(url).map(|_| ())
+}
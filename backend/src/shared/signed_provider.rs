@@ -1,39 +1,195 @@
-use alloy::network::{Ethereum, EthereumWallet};
+use crate::shared::config::SignerConfig;
+use crate::shared::keystore::decrypt_keystore;
+use crate::shared::mnemonic::derive_signer;
+use crate::shared::provider_health::{ProviderHealth, ReconnectingConnect};
+use crate::shared::remote_signer::RemoteSigner;
+use alloy::consensus::{TxEnvelope, TypedTransaction};
+use alloy::network::{Ethereum, EthereumWallet, NetworkWallet};
+use alloy::primitives::Address;
 use alloy::providers::fillers::{FillProvider, JoinFill, RecommendedFiller, WalletFiller};
-use alloy::providers::{ProviderBuilder, RootProvider, WsConnect};
-use alloy::pubsub::PubSubFrontend;
+use alloy::providers::{ProviderBuilder, RootProvider};
+use alloy::pubsub::PubSubConnect;
+use alloy::rpc::client::{BuiltInConnectionString, ClientBuilder, RpcClient};
 use alloy::signers::local::PrivateKeySigner;
-use anyhow::Result;
+use alloy::transports::ipc::IpcConnect;
+use alloy::transports::ws::WsConnect;
+use alloy::transports::{BoxTransport, Transport};
+use anyhow::{bail, Context, Result};
+use async_trait::async_trait;
 
-pub type SignedProvider = FillProvider<
-    JoinFill<RecommendedFiller, WalletFiller<EthereumWallet>>,
-    RootProvider<PubSubFrontend>,
-    PubSubFrontend,
+pub type SignedProvider<T = BoxTransport> = FillProvider<
+    JoinFill<RecommendedFiller, WalletFiller<SignerWallet>>,
+    RootProvider<T>,
+    T,
     Ethereum,
 >;
 
+/// Signs with keys held in this process, or hands every transaction to a remote signer
+#[derive(Debug, Clone)]
+pub enum SignerWallet {
+    Local(EthereumWallet),
+    Remote(RemoteSigner),
+}
+
+#[async_trait]
+impl NetworkWallet<Ethereum> for SignerWallet {
+    fn default_signer_address(&self) -> Address {
+        match self {
+            Self::Local(wallet) => NetworkWallet::<Ethereum>::default_signer_address(wallet),
+            Self::Remote(signer) => signer.accounts()[0],
+        }
+    }
+
+    fn has_signer_for(&self, address: &Address) -> bool {
+        match self {
+            Self::Local(wallet) => NetworkWallet::<Ethereum>::has_signer_for(wallet, address),
+            Self::Remote(signer) => signer.accounts().contains(address),
+        }
+    }
+
+    fn signer_addresses(&self) -> impl Iterator<Item = Address> {
+        let addresses: Vec<Address> = match self {
+            Self::Local(wallet) => NetworkWallet::<Ethereum>::signer_addresses(wallet).collect(),
+            Self::Remote(signer) => signer.accounts().to_vec(),
+        };
+
+        addresses.into_iter()
+    }
+
+    async fn sign_transaction_from(
+        &self,
+        sender: Address,
+        tx: TypedTransaction,
+    ) -> alloy::signers::Result<TxEnvelope> {
+        match self {
+            Self::Local(wallet) => {
+                NetworkWallet::<Ethereum>::sign_transaction_from(wallet, sender, tx).await
+            }
+            Self::Remote(signer) => signer
+                .sign_transaction(sender, tx)
+                .await
+                .map_err(alloy::signers::Error::other),
+        }
+    }
+}
+
 pub struct Web3Provider {}
 impl Web3Provider {
-    pub async fn prepare_ws_signed() -> Result<SignedProvider> {
-        let rpc_url = dotenvy::var("RPC_URL")?;
+    /// Wallet of the configured signer, shared by the providers of every chain.
+    /// The first account is the default signer, the others are used when a payload asks for them.
+    pub async fn wallet(signer: &SignerConfig) -> Result<SignerWallet> {
+        let signers: Vec<PrivateKeySigner> = match signer {
+            SignerConfig::PrivateKey { private_key } => vec![private_key
+                .parse()
+                .context("Incorrect or empty private key")?],
+            SignerConfig::Keystore {
+                path,
+                password_file,
+            } => vec![decrypt_keystore(path, password_file.as_deref())?],
+            SignerConfig::Mnemonic {
+                mnemonic,
+                derivation_path,
+                indexes,
+            } => indexes
+                .iter()
+                .map(|index| derive_signer(mnemonic, derivation_path, *index))
+                .collect::<Result<_>>()?,
+            SignerConfig::Remote { url } => {
+                let signer = RemoteSigner::connect(url).await?;
+
+                for account in signer.accounts() {
+                    println!("->> Remote signer account {} is ready!", account);
+                }
+
+                return Ok(SignerWallet::Remote(signer));
+            }
+        };
 
-        let ws = WsConnect::new(rpc_url);
+        let mut wallet = EthereumWallet::default();
 
-        // Wallet
-        let private_key = dotenvy::var("PRIVATE_KEY")?;
+        for (index, signer) in signers.into_iter().enumerate() {
+            println!("->> Signer {} is ready!", signer.address());
 
-        let signer: PrivateKeySigner = private_key.parse().expect("Incorrect or empty private key");
+            if index == 0 {
+                wallet.register_default_signer(signer);
+            } else {
+                wallet.register_signer(signer);
+            }
+        }
 
-        let wallet = EthereumWallet::from(signer);
+        Ok(SignerWallet::Local(wallet))
+    }
+
+    /// Transport is picked by the scheme of `rpc_url`: http(s)://, ws(s)://
+    /// or ipc:// (a plain path to the socket works too, e.g. anvil's /tmp/anvil.ipc).
+    /// WebSocket and IPC connections are re-established in the background when they drop.
+    pub async fn prepare_signed(
+        rpc_url: &str,
+        wallet: SignerWallet,
+    ) -> Result<(SignedProvider, ProviderHealth)> {
+        let (transport, connection) = connection_string(rpc_url)?;
+        let health = ProviderHealth::new(transport);
+
+        println!("->> Connecting to RPC over {}", transport);
+
+        let client = match connection {
+            BuiltInConnectionString::Ws(url, auth) => {
+                let connect = WsConnect {
+                    url: url.to_string(),
+                    auth,
+                };
+
+                reconnecting_client(connect, &health).await?
+            }
+            BuiltInConnectionString::Ipc(path) => {
+                reconnecting_client(IpcConnect::new(path), &health).await?
+            }
+            connection => ClientBuilder::default().connect_boxed(connection).await?,
+        };
 
         let provider = ProviderBuilder::new()
             .with_recommended_fillers()
             .wallet(wallet)
-            .on_ws(ws)
-            .await?;
+            .on_client(client);
+
+        health.spawn_probe(provider.clone());
 
         println!("->> Signed provider is ready!");
 
-        Ok(provider)
+        Ok((provider, health))
     }
 }
+
+async fn reconnecting_client<C: PubSubConnect>(
+    connect: C,
+    health: &ProviderHealth,
+) -> Result<RpcClient<BoxTransport>> {
+    let is_local = connect.is_local();
+
+    let frontend = ReconnectingConnect::new(connect, health.clone())
+        .into_service()
+        .await?;
+
+    Ok(RpcClient::new(frontend.boxed(), is_local))
+}
+
+/// Returns the transport name along with the parsed connection
+pub fn connection_string(rpc_url: &str) -> Result<(&'static str, BuiltInConnectionString)> {
+    let scheme = match rpc_url.split_once("://") {
+        Some((scheme, _)) => scheme.to_lowercase(),
+        None if rpc_url.starts_with('/') || rpc_url.ends_with(".ipc") => "ipc".to_string(),
+        None => bail!("RPC URL '{}' has no scheme", rpc_url),
+    };
+
+    let (transport, connection) = match scheme.as_str() {
+        "http" | "https" => ("http", BuiltInConnectionString::try_as_http(rpc_url)?),
+        "ws" | "wss" => ("ws", BuiltInConnectionString::try_as_ws(rpc_url)?),
+        "ipc" | "file" => ("ipc", BuiltInConnectionString::try_as_ipc(rpc_url)?),
+        other => bail!(
+            "Unsupported RPC URL scheme '{}', expected http, https, ws, wss or ipc",
+            other
+        ),
+    };
+
+    Ok((transport, connection))
+}
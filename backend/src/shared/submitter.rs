@@ -0,0 +1,221 @@
+use crate::shared::app_error::AppError;
+use crate::shared::execute_call::FeeEstimate;
+use crate::shared::gas_policy::GasPolicy;
+use crate::shared::nonce_manager::NonceManager;
+use crate::shared::signed_provider::SignedProvider;
+use alloy::primitives::{TxHash, U256};
+use alloy::providers::Provider;
+use alloy::rpc::types::TransactionRequest;
+use alloy::transports::Transport;
+use anyhow::{anyhow, bail, Result};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tokio::sync::Mutex as AsyncMutex;
+
+/// Gas of a plain transfer, what a cancellation costs
+const TRANSFER_GAS: u128 = 21_000;
+
+/// When a transaction waiting for its receipt is replaced with higher fees
+#[derive(Debug, Clone, Copy)]
+pub struct ReplacementPolicy {
+    pub receipt_timeout: Duration,
+    /// Automatic replacements before the wait gives up, 0 disables them
+    pub max_replacements: u32,
+    /// Nodes only accept a replacement paying at least 10% more
+    pub fee_bump_percent: u64,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Replacement {
+    /// Same transaction with higher fees
+    SpeedUp,
+    /// Zero-value transfer to the sender itself, so the original call never runs
+    Cancel,
+}
+
+/// Everything the sends of one chain share
+#[derive(Clone)]
+pub struct Submitter {
+    pub nonces: NonceManager,
+    pub in_flight: InFlightTransactions,
+    pub policy: ReplacementPolicy,
+    pub gas: GasPolicy,
+    pub confirmations: u64,
+}
+
+impl Submitter {
+    pub fn new(policy: ReplacementPolicy, gas: GasPolicy, confirmations: u64) -> Self {
+        Self {
+            nonces: NonceManager::default(),
+            in_flight: InFlightTransactions::default(),
+            policy,
+            gas,
+            confirmations,
+        }
+    }
+
+    /// Sends a new version of `tx` with the same nonce and fees raised by `fee_bump_percent`,
+    /// at least to what the gas policy asks for now. A cancellation stays one when sped up.
+    pub async fn replace<T>(
+        &self,
+        provider: &SignedProvider<T>,
+        tx: &Arc<InFlightTx>,
+        replacement: Replacement,
+    ) -> Result<(TxHash, FeeEstimate)>
+    where
+        T: Transport + Clone,
+    {
+        let mut state = tx.state.lock().await;
+        let replaced = state.hashes[state.hashes.len() - 1];
+
+        let sent = FeeEstimate::of(&state.request)
+            .ok_or_else(|| anyhow!("Transaction has no fees to bump"))?;
+
+        let request = match replacement {
+            Replacement::SpeedUp => state.request.clone(),
+            Replacement::Cancel => {
+                let from = state
+                    .request
+                    .from
+                    .ok_or_else(|| anyhow!("Transaction has no sender"))?;
+
+                let mut cancel = TransactionRequest::default()
+                    .from(from)
+                    .to(from)
+                    .value(U256::ZERO)
+                    .gas_limit(TRANSFER_GAS);
+                cancel.nonce = state.request.nonce;
+                cancel.chain_id = state.request.chain_id;
+                cancel
+            }
+        };
+
+        let current = self.gas.fees(provider).await?;
+        // Rounded up, so even fees of a few wei grow
+        let bump = |fee: u128| fee + (fee * u128::from(self.policy.fee_bump_percent)).div_ceil(100);
+
+        // Nodes refuse a replacement unless both fees grow by the bump
+        let required = FeeEstimate {
+            max_fee_per_gas: bump(sent.max_fee_per_gas),
+            max_priority_fee_per_gas: sent.max_priority_fee_per_gas.map(bump),
+        };
+
+        let fees = self.gas.limit(FeeEstimate {
+            max_fee_per_gas: required.max_fee_per_gas.max(current.max_fee_per_gas),
+            max_priority_fee_per_gas: required
+                .max_priority_fee_per_gas
+                .map(|fee| fee.max(current.max_priority_fee_per_gas.unwrap_or_default())),
+        })?;
+
+        if fees.max_fee_per_gas < required.max_fee_per_gas
+            || fees.max_priority_fee_per_gas < required.max_priority_fee_per_gas
+        {
+            bail!(AppError::Conflict {
+                code: "FEE_CAP_REACHED",
+                message: format!(
+                    "Fees of {} can't be raised by {}% within the configured caps",
+                    replaced, self.policy.fee_bump_percent
+                ),
+            });
+        }
+
+        let request = fees.apply(request);
+        let tx_hash = *provider.send_transaction(request.clone()).await?.tx_hash();
+
+        println!(
+            "->> {:?} of {} sent as {} (max fee {}, priority fee {:?})",
+            replacement, replaced, tx_hash, fees.max_fee_per_gas, fees.max_priority_fee_per_gas
+        );
+
+        state.hashes.push(tx_hash);
+        if replacement == Replacement::Cancel || !state.cancellations.is_empty() {
+            state.cancellations.push(tx_hash);
+        }
+        state.request = request;
+
+        self.in_flight
+            .by_hash
+            .lock()
+            .unwrap()
+            .insert(tx_hash, tx.clone());
+
+        Ok((tx_hash, fees))
+    }
+}
+
+/// Transactions sent by this process and not mined yet, found by any of their hashes
+#[derive(Clone, Default)]
+pub struct InFlightTransactions {
+    by_hash: Arc<Mutex<HashMap<TxHash, Arc<InFlightTx>>>>,
+}
+
+/// One nonce of an account with every version of the transaction sent for it
+pub struct InFlightTx {
+    state: AsyncMutex<InFlightState>,
+}
+
+struct InFlightState {
+    /// Last version sent, replacements start from its fees
+    request: TransactionRequest,
+    hashes: Vec<TxHash>,
+    cancellations: Vec<TxHash>,
+}
+
+impl InFlightTransactions {
+    /// `request` is the transaction exactly as sent, with its nonce, gas and fees
+    pub fn track(&self, tx_hash: TxHash, request: TransactionRequest) -> Arc<InFlightTx> {
+        let tx = Arc::new(InFlightTx {
+            state: AsyncMutex::new(InFlightState {
+                request,
+                hashes: vec![tx_hash],
+                cancellations: vec![],
+            }),
+        });
+
+        self.by_hash.lock().unwrap().insert(tx_hash, tx.clone());
+
+        tx
+    }
+
+    pub fn get(&self, tx_hash: TxHash) -> Option<Arc<InFlightTx>> {
+        self.by_hash.lock().unwrap().get(&tx_hash).cloned()
+    }
+
+    /// Called once one of the versions is mined
+    pub async fn forget(&self, tx: &InFlightTx) {
+        let hashes = tx.hashes().await;
+
+        let mut by_hash = self.by_hash.lock().unwrap();
+        for tx_hash in hashes {
+            by_hash.remove(&tx_hash);
+        }
+    }
+}
+
+impl InFlightTx {
+    pub async fn hashes(&self) -> Vec<TxHash> {
+        self.state.lock().await.hashes.clone()
+    }
+
+    /// Latest version, the one a replacement replaces
+    pub async fn latest_hash(&self) -> TxHash {
+        let state = self.state.lock().await;
+
+        state.hashes[state.hashes.len() - 1]
+    }
+
+    /// Fails when the mined version is a cancellation, the original call did not run
+    pub async fn ensure_not_cancelled(&self, mined: TxHash) -> Result<()> {
+        if self.state.lock().await.cancellations.contains(&mined) {
+            bail!(AppError::Conflict {
+                code: "TRANSACTION_CANCELLED",
+                message: format!("Transaction was cancelled by {}", mined),
+            });
+        }
+
+        Ok(())
+    }
+}
@@ -0,0 +1,149 @@
+use crate::shared::app_error::AppError;
+use alloy::primitives::U256;
+
+// In contract  uint256 public constant CALC_PRECISION = 1_000_000_000_000_000_000;
+pub const CALC_PRECISION: U256 = U256::from_limbs([1_000_000_000_000_000_000, 0, 0, 0]);
+// In contract  uint256 public constant PERCENT_PRECISION = 1_000_000;
+pub const PERCENT_PRECISION: U256 = U256::from_limbs([1_000_000, 0, 0, 0]);
+
+/// Replicates the per-receiver amounts of `distributeNativeTokens` / `distributeERC20Tokens`:
+/// `(totalAmount * proportions[i] * CALC_PRECISION) / (totalParts * CALC_PRECISION)`.
+/// Fails where the contract would revert (zero parts or a checked arithmetic overflow).
+pub fn distribution_amounts(
+    total_amount: U256,
+    proportions: &[U256],
+) -> Result<Vec<U256>, AppError> {
+    let total_parts = total_parts(proportions)?;
+
+    if total_parts.is_zero() {
+        return Err(AppError::Validation {
+            code: "INVALID_PARTS_QUANTITY",
+            message: "Sum of proportions is zero, contract would revert with InvalidsPartsQuantity"
+                .to_string(),
+        });
+    }
+
+    let denominator = checked_mul(total_parts, CALC_PRECISION)?;
+
+    proportions
+        .iter()
+        .map(|proportion| {
+            let numerator = checked_mul(checked_mul(total_amount, *proportion)?, CALC_PRECISION)?;
+            Ok(numerator / denominator)
+        })
+        .collect()
+}
+
+pub fn total_parts(proportions: &[U256]) -> Result<U256, AppError> {
+    proportions.iter().try_fold(U256::ZERO, |acc, proportion| {
+        acc.checked_add(*proportion)
+            .ok_or_else(|| overflow_error("Sum of proportions overflows uint256".to_string()))
+    })
+}
+
+/// Replicates the amount `collectERC20Tokens` pulls from a wallet:
+/// `(walletBalance * percentages[i] * CALC_PRECISION) / (100 * PERCENT_PRECISION * CALC_PRECISION)`.
+pub fn collect_amount(wallet_balance: U256, scaled_percent: U256) -> Result<U256, AppError> {
+    let numerator = checked_mul(checked_mul(wallet_balance, scaled_percent)?, CALC_PRECISION)?;
+    let denominator = U256::from(100) * PERCENT_PRECISION * CALC_PRECISION;
+
+    Ok(numerator / denominator)
+}
+
+fn checked_mul(a: U256, b: U256) -> Result<U256, AppError> {
+    a.checked_mul(b).ok_or_else(|| {
+        overflow_error(format!(
+            "Arithmetic overflow, contract would revert on {} * {}",
+            a, b
+        ))
+    })
+}
+
+fn overflow_error(message: String) -> AppError {
+    AppError::Validation {
+        code: "ARITHMETIC_OVERFLOW",
+        message,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn amounts(total_amount: u64, proportions: &[u64]) -> Result<Vec<U256>, AppError> {
+        let proportions: Vec<U256> = proportions.iter().map(|part| U256::from(*part)).collect();
+
+        distribution_amounts(U256::from(total_amount), &proportions)
+    }
+
+    #[test]
+    fn distribution_splits_by_proportions() {
+        let amounts = amounts(1_000, &[1, 3]).unwrap();
+
+        assert_eq!(amounts, [U256::from(250), U256::from(750)]);
+    }
+
+    #[test]
+    fn distribution_rounds_every_amount_down() {
+        let amounts = amounts(100, &[1, 1, 1]).unwrap();
+
+        // The remainder stays with the sender, like in the contract
+        assert_eq!(amounts, [U256::from(33); 3]);
+    }
+
+    #[test]
+    fn distribution_refuses_zero_parts() {
+        let error = amounts(100, &[0, 0]).unwrap_err();
+
+        assert!(matches!(
+            error,
+            AppError::Validation {
+                code: "INVALID_PARTS_QUANTITY",
+                ..
+            }
+        ));
+    }
+
+    #[test]
+    fn distribution_refuses_overflows_the_contract_reverts_on() {
+        // totalAmount * proportion * CALC_PRECISION doesn't fit into uint256
+        let error = distribution_amounts(U256::MAX / U256::from(2), &[U256::from(4)]).unwrap_err();
+
+        assert!(matches!(
+            error,
+            AppError::Validation {
+                code: "ARITHMETIC_OVERFLOW",
+                ..
+            }
+        ));
+    }
+
+    #[test]
+    fn total_parts_refuses_overflow() {
+        let error = total_parts(&[U256::MAX, U256::from(1)]).unwrap_err();
+
+        assert!(matches!(
+            error,
+            AppError::Validation {
+                code: "ARITHMETIC_OVERFLOW",
+                ..
+            }
+        ));
+    }
+
+    #[test]
+    fn collect_amount_applies_scaled_percentage() {
+        // 12.5% scaled by PERCENT_PRECISION
+        let amount = collect_amount(U256::from(1_000), U256::from(12_500_000)).unwrap();
+
+        assert_eq!(amount, U256::from(125));
+    }
+
+    #[test]
+    fn collect_amount_rounds_down() {
+        // 33.333333% of 10 is 3.3333333
+        let amount = collect_amount(U256::from(10), U256::from(33_333_333)).unwrap();
+
+        assert_eq!(amount, U256::from(3));
+    }
+}
@@ -0,0 +1,138 @@
+use alloy::primitives::U256;
+use serde::{Deserialize, Serialize};
+use std::str::FromStr;
+
+/// Decimals of native tokens on EVM chains
+pub const NATIVE_DECIMALS: u8 = 18;
+
+/// Amount echoed back to clients both in base units and in token units
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct FormattedAmount {
+    pub raw: String,
+    pub formatted: String,
+    pub decimals: u8,
+}
+
+impl FormattedAmount {
+    pub fn new(amount: U256, decimals: u8) -> Self {
+        Self {
+            raw: amount.to_string(),
+            formatted: format_units(amount, decimals),
+            decimals,
+        }
+    }
+}
+
+/// Converts a decimal string like "1250.5" into base units.
+/// Input with more fractional digits than `decimals` is rejected instead of truncated.
+pub fn parse_units(value: &str, decimals: u8) -> Result<U256, String> {
+    let value = value.trim();
+
+    let (integer_part, fraction_part) = match value.split_once('.') {
+        Some((integer_part, fraction_part)) => (integer_part, fraction_part),
+        None => (value, ""),
+    };
+
+    let is_digits = |part: &str| part.chars().all(|c| c.is_ascii_digit());
+
+    if (integer_part.is_empty() && fraction_part.is_empty())
+        || !is_digits(integer_part)
+        || !is_digits(fraction_part)
+    {
+        return Err(format!("'{}' is not a decimal number", value));
+    }
+
+    if fraction_part.len() > decimals as usize {
+        return Err(format!(
+            "'{}' has {} fractional digits, but token supports only {}",
+            value,
+            fraction_part.len(),
+            decimals
+        ));
+    }
+
+    let padded = format!(
+        "{}{}{}",
+        integer_part,
+        fraction_part,
+        "0".repeat(decimals as usize - fraction_part.len())
+    );
+
+    U256::from_str(&padded).map_err(|e| format!("'{}' is out of range: {}", value, e))
+}
+
+/// Formats base units as a decimal string without trailing zeros, e.g. "1250.5"
+pub fn format_units(amount: U256, decimals: u8) -> String {
+    let digits = amount.to_string();
+    let decimals = decimals as usize;
+
+    if decimals == 0 {
+        return digits;
+    }
+
+    let padded = format!("{:0>width$}", digits, width = decimals + 1);
+    let (integer_part, fraction_part) = padded.split_at(padded.len() - decimals);
+    let fraction_part = fraction_part.trim_end_matches('0');
+
+    if fraction_part.is_empty() {
+        integer_part.to_string()
+    } else {
+        format!("{}.{}", integer_part, fraction_part)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_decimal_amounts_into_base_units() {
+        assert_eq!(parse_units("1250.5", 6), Ok(U256::from(1_250_500_000u64)));
+        assert_eq!(parse_units("1", 18), Ok(U256::from(10u64.pow(18))));
+        assert_eq!(parse_units(".5", 1), Ok(U256::from(5)));
+        assert_eq!(parse_units("7.", 2), Ok(U256::from(700)));
+        assert_eq!(parse_units(" 42 ", 0), Ok(U256::from(42)));
+    }
+
+    #[test]
+    fn refuses_more_fractional_digits_than_decimals() {
+        // Rejected instead of rounded, so nobody pays less than they asked for
+        let error = parse_units("1.2345", 3).unwrap_err();
+
+        assert!(error.contains("4 fractional digits"), "{}", error);
+        assert!(parse_units("1.5", 0).is_err());
+    }
+
+    #[test]
+    fn refuses_what_is_not_a_decimal_number() {
+        for value in ["", ".", "-1", "1e18", "1.2.3", "0x10", "1,5"] {
+            assert!(parse_units(value, 18).is_err(), "{:?} was accepted", value);
+        }
+    }
+
+    #[test]
+    fn refuses_amounts_beyond_uint256() {
+        let max = U256::MAX.to_string();
+
+        assert_eq!(parse_units(&max, 0), Ok(U256::MAX));
+
+        let error = parse_units(&max, 1).unwrap_err();
+        assert!(error.contains("out of range"), "{}", error);
+    }
+
+    #[test]
+    fn formats_base_units_without_trailing_zeros() {
+        assert_eq!(format_units(U256::from(1_250_500_000u64), 6), "1250.5");
+        assert_eq!(format_units(U256::from(10u64.pow(18)), 18), "1");
+        assert_eq!(format_units(U256::from(1), 18), "0.000000000000000001");
+        assert_eq!(format_units(U256::ZERO, 6), "0");
+        assert_eq!(format_units(U256::from(1_234), 0), "1234");
+    }
+
+    #[test]
+    fn formatting_reverses_parsing() {
+        for value in ["0.1", "1250.5", "1000000", "0.000001"] {
+            assert_eq!(format_units(parse_units(value, 6).unwrap(), 6), value);
+        }
+    }
+}
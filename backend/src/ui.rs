@@ -15,6 +15,12 @@ async fn ui() -> impl IntoResponse {
         <title>Distribute Tokens</title>
     </head>
     <body>
+        <label for="chainId">Chain</label>
+        <select id="chainId" name="chain_id"></select>
+
+        <label for="fromAccount">Send from</label>
+        <select id="fromAccount" name="from"></select>
+
         <h1>Distribute Native Tokens</h1>
     <form id="nativeForm" action="/distribute/native" method="POST">
         <label for="receivers">Receivers (comma separated)</label>
@@ -24,8 +30,17 @@ async fn ui() -> impl IntoResponse {
         <input type="text" id="proportions" name="proportions" placeholder="Enter proportions, e.g., 50,50">
 
         <label for="amount">Amount</label>
-        <input type="number" id="amount" name="amount" placeholder="Enter the total amount">
+        <input type="text" id="amount" name="amount" placeholder="Enter the total amount, e.g., 1250.5">
+
+        <label for="amountUnit">Amount unit</label>
+        <select id="amountUnit" name="unit">
+            <option value="base">Base units (wei)</option>
+            <option value="decimal">Token units (scaled by decimals)</option>
+        </select>
+
+        <label><input type="checkbox" id="dryRun" name="dry_run"> Dry run (simulate only, nothing is sent)</label>
 
+        <button type="button" onclick="previewNative()">Preview Native Token Distribution</button>
         <button type="button" onclick="submitNative()">Submit Native Token Distribution</button>
     </form>
 
@@ -41,8 +56,17 @@ async fn ui() -> impl IntoResponse {
         <input type="text" id="erc20Proportions" name="proportions" placeholder="Enter proportions, e.g., 50,50">
 
         <label for="erc20Amount">Amount</label>
-        <input type="number" id="erc20Amount" name="amount" placeholder="Enter the total amount">
+        <input type="text" id="erc20Amount" name="amount" placeholder="Enter the total amount, e.g., 1250.5">
 
+        <label for="erc20AmountUnit">Amount unit</label>
+        <select id="erc20AmountUnit" name="unit">
+            <option value="base">Base units (wei)</option>
+            <option value="decimal">Token units (scaled by decimals)</option>
+        </select>
+
+        <label><input type="checkbox" id="erc20DryRun" name="dry_run"> Dry run (simulate only, nothing is sent)</label>
+
+        <button type="button" onclick="previewERC20()">Preview ERC20 Token Distribution</button>
         <button type="button" onclick="submitERC20()">Submit ERC20 Token Distribution</button>
     </form>
 
@@ -58,6 +82,9 @@ async fn ui() -> impl IntoResponse {
         <label for="erc20Proportions">SCALED Percents of each wallet (multiplied by 1_000_000) (comma separated)</label>
         <input type="text" id="erc20Proportions" name="percents" placeholder="Enter scaled percents, e.g., if 50.657444 then write 50657444">
 
+        <label><input type="checkbox" id="collectDryRun" name="dry_run"> Dry run (simulate only, nothing is sent)</label>
+
+        <button type="button" onclick="previewCollectERC20()">Preview ERC20 Token Collection</button>
         <button type="button" onclick="submitCollectERC20()">Submit ERC20 Token Collection</button>
     </form>
 
@@ -70,6 +97,32 @@ async fn ui() -> impl IntoResponse {
     </style>
 
     <script>
+        // Fill the chain selector with chains configured on the backend
+        fetch('/chains')
+            .then(response => response.json())
+            .then(chains => {
+                const select = document.getElementById('chainId');
+                chains.forEach(chain => select.add(new Option(`${chain.name} (${chain.chain_id})`, chain.chain_id)));
+            })
+            .catch(error => console.error('Error:', error));
+
+        function chainId() {
+            return Number(document.getElementById('chainId').value);
+        }
+
+        // Fill the account selector with accounts of the backend signer, the default one first
+        fetch('/accounts')
+            .then(response => response.json())
+            .then(accounts => {
+                const select = document.getElementById('fromAccount');
+                accounts.forEach(account => select.add(new Option(account, account)));
+            })
+            .catch(error => console.error('Error:', error));
+
+        function fromAccount() {
+            return document.getElementById('fromAccount').value || undefined;
+        }
+
         // Helper function to convert comma-separated strings into JSON array format
         function createReceiversWithProportions(receivers, proportions) {
             let receiversArray = receivers.split(',').map(item => item.trim());
@@ -87,10 +140,15 @@ async fn ui() -> impl IntoResponse {
             const receivers = form.receivers.value;
             const proportions = form.proportions.value;
             const amount = String(form.amount.value);
+            const unit = form.unit.value;
 
             const payload = {
+                chain_id: chainId(),
                 receivers_with_proportions: createReceiversWithProportions(receivers, proportions),
-                amount
+                amount,
+                unit,
+                from: fromAccount(),
+                dry_run: form.dry_run.checked
             };
 
             fetch(form.action, {
@@ -105,18 +163,69 @@ async fn ui() -> impl IntoResponse {
             .catch(error => console.error('Error:', error));
         }
 
+        // Preview amounts each receiver gets for native token distribution
+        function previewNative() {
+            const form = document.getElementById('nativeForm');
+
+            const payload = {
+                base: {
+                    chain_id: chainId(),
+                    receivers_with_proportions: createReceiversWithProportions(form.receivers.value, form.proportions.value),
+                    amount: String(form.amount.value),
+                    unit: form.unit.value
+                }
+            };
+
+            postPreview(payload);
+        }
+
+        // Preview amounts each receiver gets for ERC20 token distribution
+        function previewERC20() {
+            const form = document.getElementById('erc20Form');
+
+            const payload = {
+                base: {
+                    chain_id: chainId(),
+                    receivers_with_proportions: createReceiversWithProportions(form.receivers.value, form.proportions.value),
+                    amount: String(form.amount.value),
+                    unit: form.unit.value
+                },
+                token_address: form.token_address.value
+            };
+
+            postPreview(payload);
+        }
+
+        function postPreview(payload) {
+            fetch('/distribute/preview', {
+                method: 'POST',
+                headers: {
+                    'Content-Type': 'application/json'
+                },
+                body: JSON.stringify(payload)
+            })
+            .then(response => response.json())
+            .then(data => alert('Preview: ' + JSON.stringify(data, null, 2)))
+            .catch(error => console.error('Error:', error));
+        }
+
         // Submit form data for ERC20 token distribution
         function submitERC20() {
             const form = document.getElementById('erc20Form');
             const receivers = form.receivers.value;
             const proportions = form.proportions.value;
             const amount = String(form.amount.value);
+            const unit = form.unit.value;
             const tokenAddress = form.token_address.value;
 
             const payload = {
                 base: {
+                    chain_id: chainId(),
                     receivers_with_proportions: createReceiversWithProportions(receivers, proportions),
-                    amount
+                    amount,
+                    unit,
+                    from: fromAccount(),
+                    dry_run: form.dry_run.checked
                 },
                 token_address: tokenAddress
             };
@@ -152,8 +261,11 @@ async fn ui() -> impl IntoResponse {
             const tokenAddress = form.token_address.value;
 
             const payload = {
+                chain_id: chainId(),
                 sets: createSets(froms, percents),
-                token_address: tokenAddress
+                token_address: tokenAddress,
+                from: fromAccount(),
+                dry_run: form.dry_run.checked
             };
 
             fetch(form.action, {
@@ -167,6 +279,28 @@ async fn ui() -> impl IntoResponse {
             .then(data => alert('Response: ' + JSON.stringify(data)))
             .catch(error => console.error('Error:', error));
         }
+
+        // Preview balances, collected amounts and allowance gaps of each wallet
+        function previewCollectERC20() {
+            const form = document.getElementById('collectErc20Form');
+
+            const payload = {
+                chain_id: chainId(),
+                sets: createSets(form.froms.value, form.percents.value),
+                token_address: form.token_address.value
+            };
+
+            fetch('/collect/erc20/preview', {
+                method: 'POST',
+                headers: {
+                    'Content-Type': 'application/json'
+                },
+                body: JSON.stringify(payload)
+            })
+            .then(response => response.json())
+            .then(data => alert('Preview: ' + JSON.stringify(data, null, 2)))
+            .catch(error => console.error('Error:', error));
+        }
     </script>
     </body>
     </html>
@@ -0,0 +1,144 @@
+//! Talks to the stand-in signer binary over its HTTP JSON-RPC interface, the protocol the
+//! backend speaks with `source = "remote"`, and checks who the signed transactions recover to.
+use alloy::consensus::{Transaction, TxEnvelope};
+use alloy::eips::eip2718::Decodable2718;
+use alloy::primitives::{address, Address, Bytes, U256};
+use alloy::signers::local::PrivateKeySigner;
+use serde_json::{json, Value};
+use std::net::TcpListener;
+use std::process::{Child, Command};
+use std::time::Duration;
+
+/// First two accounts of the default anvil mnemonic
+const KEYS: [&str; 2] = [
+    "0xac0974bec39a17e36ba4a6b4d238ff944bacb478cbed5efcae784d7bf4f2ff80",
+    "0x59c6995e998f97a5a0044966f0945389dc9e86dae88c7a8412f4603b6b78690d",
+];
+
+const RECEIVER: Address = address!("70997970C51812dc3A010C7d01b50e0d17dc79C8");
+
+/// Stand-in signer serving HTTP on a free local port, killed when dropped
+struct StandInSigner {
+    child: Child,
+    url: String,
+}
+
+impl StandInSigner {
+    async fn start() -> Self {
+        // Released right away for the signer to bind, nothing else is expected to take it
+        let port = TcpListener::bind("127.0.0.1:0")
+            .and_then(|listener| listener.local_addr())
+            .expect("a free port")
+            .port();
+
+        let child = Command::new(env!("CARGO_BIN_EXE_stand_in_signer"))
+            .arg("--listen")
+            .arg(format!("127.0.0.1:{}", port))
+            .env("STAND_IN_SIGNER_KEYS", KEYS.join(","))
+            .spawn()
+            .expect("stand-in signer starts");
+
+        let signer = Self {
+            child,
+            url: format!("http://127.0.0.1:{}", port),
+        };
+        signer.wait_until_listening().await;
+
+        signer
+    }
+
+    async fn wait_until_listening(&self) {
+        for _ in 0..100 {
+            if self.call("eth_accounts", json!([])).await.is_some() {
+                return;
+            }
+
+            tokio::time::sleep(Duration::from_millis(50)).await;
+        }
+
+        panic!("stand-in signer did not listen on {}", self.url);
+    }
+
+    /// JSON-RPC response, `None` while nothing answers
+    async fn call(&self, method: &str, params: Value) -> Option<Value> {
+        let request = json!({ "jsonrpc": "2.0", "id": 1, "method": method, "params": params });
+
+        let response = reqwest::Client::new()
+            .post(&self.url)
+            .json(&request)
+            .send()
+            .await
+            .ok()?;
+
+        Some(response.json().await.expect("a JSON-RPC response"))
+    }
+
+    async fn sign(&self, from: Address, nonce: u64) -> Value {
+        let tx = json!({
+            "from": from,
+            "to": RECEIVER,
+            "value": U256::from(1_000),
+            "nonce": nonce,
+            "chainId": 31337,
+            "gas": 21_000,
+            "maxFeePerGas": 2_000_000_000u64,
+            "maxPriorityFeePerGas": 1_000_000_000u64,
+        });
+
+        self.call("eth_signTransaction", json!([tx]))
+            .await
+            .expect("stand-in signer answers")
+    }
+}
+
+impl Drop for StandInSigner {
+    fn drop(&mut self) {
+        let _ = self.child.kill();
+        let _ = self.child.wait();
+    }
+}
+
+fn account(key: &str) -> Address {
+    key.parse::<PrivateKeySigner>().unwrap().address()
+}
+
+#[tokio::test]
+async fn lists_the_default_account_first() {
+    let signer = StandInSigner::start().await;
+
+    let response = signer.call("eth_accounts", json!([])).await.unwrap();
+    let accounts: Vec<Address> = serde_json::from_value(response["result"].clone()).unwrap();
+
+    assert_eq!(accounts, [account(KEYS[0]), account(KEYS[1])]);
+}
+
+#[tokio::test]
+async fn signed_transactions_recover_to_the_requested_account() {
+    let signer = StandInSigner::start().await;
+
+    for (nonce, key) in KEYS.iter().enumerate() {
+        let sender = account(key);
+
+        let response = signer.sign(sender, nonce as u64).await;
+        let raw: Bytes = serde_json::from_value(response["result"].clone()).unwrap();
+        let envelope = TxEnvelope::decode_2718(&mut raw.as_ref()).unwrap();
+
+        assert_eq!(envelope.recover_signer().unwrap(), sender);
+        assert_eq!(envelope.nonce(), nonce as u64);
+        assert_eq!(envelope.to(), RECEIVER.into());
+        assert_eq!(envelope.chain_id(), Some(31337));
+    }
+}
+
+#[tokio::test]
+async fn refuses_accounts_without_a_key() {
+    let signer = StandInSigner::start().await;
+
+    let unknown = address!("3C44CdDdB6a900fa2b585dd299e03d12FA4293BC");
+
+    let response = signer.sign(unknown, 0).await;
+    let message = response["error"]["message"].as_str().unwrap();
+
+    assert!(response.get("result").is_none());
+    assert!(message.contains("No key"), "{}", message);
+}
@@ -20,6 +20,23 @@ contract TokenManager is ReentrancyGuard {
     error InvalidsSpentQuantity();
     error TooEarly();
 
+    /// @notice Emitted for every receiver of a distribution
+    /// @dev `token` is address(0) for native tokens
+    event Distributed(
+        address indexed sender,
+        address indexed token,
+        address indexed receiver,
+        uint256 amount
+    );
+
+    /// @notice Emitted for every wallet tokens are collected from, wallets without balance are skipped
+    event Collected(
+        address indexed collector,
+        address indexed token,
+        address indexed wallet,
+        uint256 amount
+    );
+
     modifier validReceiversAndParts(
         address[] calldata receivers,
         uint256[] calldata parts
@@ -90,6 +107,8 @@ contract TokenManager is ReentrancyGuard {
                 success,
                 "Native token transfer failed"
             );
+
+            emit Distributed(msg.sender, address(0), receivers[i], recipientAmount);
         }
     }
 
@@ -131,6 +150,8 @@ contract TokenManager is ReentrancyGuard {
                 token.transferFrom(msg.sender, receivers[i], recipientAmount),
                 "Token transfer failed"
             );
+
+            emit Distributed(msg.sender, tokenAddress, receivers[i], recipientAmount);
         }
     }
 
@@ -168,6 +189,8 @@ contract TokenManager is ReentrancyGuard {
                 token.transferFrom(wallets[i], msg.sender, collectAmount),
                 "Token transfer failed"
             );
+
+            emit Collected(msg.sender, tokenAddress, wallets[i], collectAmount);
         }
     }
 }
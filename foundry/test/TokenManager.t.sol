@@ -2,6 +2,7 @@
 pragma solidity ^0.8.26;
 
 import {Test, console} from "forge-std/Test.sol";
+import {Vm} from "forge-std/Vm.sol";
 import "../src/TokenManager.sol";
 import {ERC20Mock} from "@openzeppelin/contracts/mocks/token/ERC20Mock.sol";
 import {ERC20} from "@openzeppelin/contracts/token/ERC20/ERC20.sol";
@@ -172,4 +173,71 @@ contract TokenManagerT is Test {
 
         vm.stopPrank();
     }
+
+    function testDistributeNativeTokensEmitsDistributed() public {
+        vm.deal(sender, 1 ether);
+        vm.startPrank(sender);
+
+        vm.expectEmit(true, true, true, true, address(tokenManager));
+        emit TokenManager.Distributed(sender, address(0), wallets[0], 0.33333333 ether);
+        vm.expectEmit(true, true, true, true, address(tokenManager));
+        emit TokenManager.Distributed(sender, address(0), wallets[1], 0.66666666 ether);
+        vm.expectEmit(true, true, true, true, address(tokenManager));
+        emit TokenManager.Distributed(sender, address(0), wallets[2], 0.00000001 ether);
+
+        tokenManager.distributeNativeTokens{value: 1 ether}(wallets, parts, 1 ether);
+
+        vm.stopPrank();
+    }
+
+    function testDistributeERC20TokensEmitsDistributed() public {
+        vm.startPrank(sender);
+
+        mockToken.mint(sender, 1_000 ether);
+        mockToken.approve(address(tokenManager), 1_000 ether);
+
+        vm.expectEmit(true, true, true, true, address(tokenManager));
+        emit TokenManager.Distributed(sender, address(mockToken), wallets[0], 333.33333 ether);
+        vm.expectEmit(true, true, true, true, address(tokenManager));
+        emit TokenManager.Distributed(sender, address(mockToken), wallets[1], 666.66666 ether);
+        vm.expectEmit(true, true, true, true, address(tokenManager));
+        emit TokenManager.Distributed(sender, address(mockToken), wallets[2], 0.00001 ether);
+
+        tokenManager.distributeERC20Tokens(address(mockToken), wallets, parts, 1_000 ether);
+
+        vm.stopPrank();
+    }
+
+    function testCollectERC20TokensEmitsCollected() public {
+        parts[0] = 50_000_000; // 50%
+        parts[1] = 100_000_000; // 100%
+        parts[2] = 100_000_000; // 100%, but the wallet has no balance
+
+        for (uint256 i = 0; i < 2; i++) {
+            mockToken.mint(wallets[i], 100 ether);
+
+            vm.prank(wallets[i]);
+            mockToken.approve(address(tokenManager), 100 ether);
+        }
+
+        vm.expectEmit(true, true, true, true, address(tokenManager));
+        emit TokenManager.Collected(sender, address(mockToken), wallets[0], 50 ether);
+        vm.expectEmit(true, true, true, true, address(tokenManager));
+        emit TokenManager.Collected(sender, address(mockToken), wallets[1], 100 ether);
+
+        vm.recordLogs();
+
+        vm.prank(sender);
+        tokenManager.collectERC20Tokens(address(mockToken), wallets, parts);
+
+        // Wallets without balance are skipped without an event
+        Vm.Log[] memory logs = vm.getRecordedLogs();
+        uint256 collected = 0;
+        for (uint256 i = 0; i < logs.length; i++) {
+            if (logs[i].topics[0] == TokenManager.Collected.selector) {
+                collected++;
+            }
+        }
+        assertEq(collected, 2);
+    }
 }